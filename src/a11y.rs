@@ -0,0 +1,163 @@
+// imdialog/src/a11y.rs
+
+use imgui_sys as imgui;
+use libc::c_int;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::FromRawFd;
+use std::process::{Command, Stdio};
+use std::thread;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Role {
+    Button,
+    Checkbox,
+    Entry,
+    Item,
+    PasswordEntry,
+    SpinButton,
+}
+
+impl Role {
+    fn name(self) -> &'static str {
+        match self {
+            Role::Button => "button",
+            Role::Checkbox => "checkbox",
+            Role::Entry => "entry",
+            Role::Item => "item",
+            Role::PasswordEntry => "password entry",
+            Role::SpinButton => "spin button",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct Element {
+    role: Role,
+    label: String,
+    state: Option<&'static str>,
+}
+
+// Works out which widget has focus each frame and reports changes, along with changes to the
+// dialog's text, as `text` and `focus` lines on `--a11y-fd` and as speech with `--speak`. imgui
+// has no keyboard navigation between widgets, so the hovered widget counts as focused, falling
+// back to the active one and then to the selected item, which the dialog moves with the arrow keys
+// and type-ahead.
+pub struct FocusTracker {
+    output: Option<File>,
+    speak: bool,
+    text: Vec<String>,
+    hovered: Option<Element>,
+    active: Option<Element>,
+    selected: Option<Element>,
+    announced_text: Vec<String>,
+    focused: Option<Element>,
+}
+
+impl FocusTracker {
+    pub fn new(fd: Option<c_int>, speak: bool) -> FocusTracker {
+        FocusTracker {
+            output: fd.map(|fd| unsafe { File::from_raw_fd(fd) }),
+            speak: speak,
+            text: vec![],
+            hovered: None,
+            active: None,
+            selected: None,
+            announced_text: vec![],
+            focused: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.output.is_some() || self.speak
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.text.clear();
+        self.hovered = None;
+        self.active = None;
+        self.selected = None
+    }
+
+    pub fn text(&mut self, text: &str) {
+        if self.enabled() {
+            self.text.push(text.to_string())
+        }
+    }
+
+    // Records the widget just submitted to imgui.
+    pub fn item(&mut self, role: Role, label: &str, state: Option<&'static str>) {
+        if !self.enabled() {
+            return
+        }
+        let (hovered, active) = unsafe { (imgui::igIsItemHovered(), imgui::igIsItemActive()) };
+        let element = Element {
+            role: role,
+            label: label.to_string(),
+            state: state,
+        };
+        if hovered {
+            self.hovered = Some(element)
+        } else if active {
+            self.active = Some(element)
+        } else if state == Some("selected") {
+            self.selected = Some(element)
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        if !self.enabled() {
+            return
+        }
+        if self.text != self.announced_text {
+            self.announced_text = self.text.clone();
+            let text = self.text.join("\n");
+            self.emit(&format!("text\t{}", escape(&text)), &text)
+        }
+
+        let focused = self.hovered.take().or(self.active.take()).or(self.selected.take());
+        if focused == self.focused {
+            return
+        }
+        self.focused = focused.clone();
+        if let Some(element) = focused {
+            let mut line = format!("focus\t{}\t{}", element.role.name(), escape(&element.label));
+            let mut speech = format!("{}, {}", element.label, element.role.name());
+            if let Some(state) = element.state {
+                line.push_str(&format!("\t{}", state));
+                speech.push_str(&format!(", {}", state))
+            }
+            self.emit(&line, &speech)
+        }
+    }
+
+    fn emit(&mut self, line: &str, speech: &str) {
+        let mut failed = false;
+        if let Some(ref mut output) = self.output {
+            failed = writeln!(output, "{}", line).is_err()
+        }
+        if failed {
+            writeln!(io::stderr(), "warning: couldn't write to `--a11y-fd`; disabling it").unwrap();
+            self.output = None
+        }
+
+        if !self.speak {
+            return
+        }
+        // spd-say sends at text priority, so each message interrupts the one before it.
+        match Command::new("spd-say").arg(speech)
+                                     .stdin(Stdio::null())
+                                     .stdout(Stdio::null())
+                                     .spawn() {
+            Ok(mut child) => drop(thread::spawn(move || child.wait())),
+            Err(error) => {
+                writeln!(io::stderr(), "warning: couldn't run spd-say: {}", error).unwrap();
+                self.speak = false
+            }
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
@@ -0,0 +1,237 @@
+// imdialog/src/bidi.rs
+//
+// A reduced Unicode bidirectional algorithm, without explicit embeddings, plus Arabic shaping.
+// imgui draws strings left to right exactly as stored, so text passes through here first.
+
+use std::char;
+
+// How many presentation forms (isolated, final, initial, medial) each letter from U+0621 to
+// U+064A has in the Arabic Presentation Forms-B block, which lists them in that order starting at
+// U+FE80.
+static ARABIC_FORM_COUNTS: [u8; 42] = [
+    1, 2, 2, 2, 2, 4, 2, 4, 2, 4, 4, 4, 4, 4, 2, 2, 2, 2, 4, 4, 4, 4, 4, 4, 4, 4,
+    0, 0, 0, 0, 0, 0, 4, 4, 4, 4, 4, 4, 4, 2, 2, 4,
+];
+
+static MIRRORED_PAIRS: [(char, char); 5] = [
+    ('(', ')'), ('[', ']'), ('{', '}'), ('<', '>'), ('«', '»'),
+];
+
+const ARABIC_TATWEEL: char = '\u{640}';
+const ARABIC_LAM: char = '\u{644}';
+const ARABIC_PRESENTATION_FORMS: u32 = 0xfe80;
+const ARABIC_LAM_ALEF_FORMS: u32 = 0xfef5;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Class {
+    Left,
+    Right,
+    Number,
+    Mark,
+    Neutral,
+}
+
+// Shapes and reorders one line. `rtl` sets the direction of lines without any strongly
+// directional characters.
+pub fn visual_line(line: &str, rtl: bool) -> String {
+    if !line.chars().any(is_rtl_character) {
+        return line.to_string()
+    }
+
+    let characters = shape_arabic(&line.chars().collect::<Vec<_>>());
+    let mut classes: Vec<Class> = characters.iter().map(|&c| class(c)).collect();
+    let first_strong = classes.iter().find(|&&class| class == Class::Left || class == Class::Right);
+    let base_rtl = match first_strong {
+        Some(&class) => class == Class::Right,
+        None => rtl,
+    };
+    let base_class = if base_rtl { Class::Right } else { Class::Left };
+
+    // Combining marks take the class of what they combine with, and numbers in left-to-right
+    // context behave as left-to-right text.
+    let mut last_strong = base_class;
+    for index in 0..classes.len() {
+        if classes[index] == Class::Mark {
+            classes[index] = if index == 0 { base_class } else { classes[index - 1] }
+        }
+        match classes[index] {
+            Class::Left | Class::Right => last_strong = classes[index],
+            Class::Number if last_strong == Class::Left => classes[index] = Class::Left,
+            _ => {}
+        }
+    }
+
+    // A run of neutrals takes the direction of the text on both sides if it agrees, and the line
+    // direction otherwise.
+    let mut index = 0;
+    while index < classes.len() {
+        if classes[index] != Class::Neutral {
+            index += 1;
+            continue
+        }
+        let end = classes[index..].iter().position(|&class| class != Class::Neutral)
+                                         .map(|offset| index + offset)
+                                         .unwrap_or(classes.len());
+        let before = if index == 0 { base_class } else { direction(classes[index - 1]) };
+        let after = if end == classes.len() { base_class } else { direction(classes[end]) };
+        let resolved = if before == after { before } else { base_class };
+        for class in &mut classes[index..end] {
+            *class = resolved
+        }
+        index = end
+    }
+
+    let base_level = if base_rtl { 1 } else { 0 };
+    let mut levels: Vec<u8> = classes.iter().map(|&class| {
+        match (class, base_rtl) {
+            (Class::Left, false) => 0,
+            (Class::Left, true) | (Class::Number, _) => 2,
+            _ => 1,
+        }
+    }).collect();
+    for (index, &c) in characters.iter().enumerate().rev() {
+        if c != ' ' && c != '\t' {
+            break
+        }
+        levels[index] = base_level
+    }
+
+    let mut characters: Vec<char> = characters.iter().zip(levels.iter()).map(|(&c, &level)| {
+        if level % 2 == 1 { mirror(c) } else { c }
+    }).collect();
+    let highest_level = *levels.iter().max().unwrap();
+    for level in (1..(highest_level + 1)).rev() {
+        let mut index = 0;
+        while index < levels.len() {
+            if levels[index] < level {
+                index += 1;
+                continue
+            }
+            let end = levels[index..].iter().position(|&run_level| run_level < level)
+                                            .map(|offset| index + offset)
+                                            .unwrap_or(levels.len());
+            characters[index..end].reverse();
+            levels[index..end].reverse();
+            index = end
+        }
+    }
+    characters.into_iter().collect()
+}
+
+pub fn visual(text: &str, rtl: bool) -> String {
+    text.split('\n').map(|line| visual_line(line, rtl)).collect::<Vec<_>>().join("\n")
+}
+
+fn is_rtl_character(c: char) -> bool {
+    match c as u32 {
+        0x590..=0x8ff | 0xfb1d..=0xfdff | 0xfe70..=0xfefe => true,
+        _ => false,
+    }
+}
+
+fn is_mark(c: char) -> bool {
+    match c as u32 {
+        0x300..=0x36f | 0x591..=0x5bd | 0x5bf | 0x5c1..=0x5c2 | 0x5c4..=0x5c5 | 0x5c7 |
+        0x610..=0x61a | 0x64b..=0x65f | 0x670 | 0x6d6..=0x6dc | 0x6df..=0x6e4 | 0x6e7..=0x6e8 |
+        0x6ea..=0x6ed => true,
+        _ => false,
+    }
+}
+
+fn class(c: char) -> Class {
+    match c as u32 {
+        0x30..=0x39 | 0x660..=0x669 | 0x6f0..=0x6f9 => Class::Number,
+        _ if is_mark(c) => Class::Mark,
+        _ if is_rtl_character(c) => Class::Right,
+        _ if c.is_alphabetic() => Class::Left,
+        _ => Class::Neutral,
+    }
+}
+
+fn direction(class: Class) -> Class {
+    if class == Class::Number { Class::Right } else { class }
+}
+
+fn mirror(c: char) -> char {
+    for &(open, close) in &MIRRORED_PAIRS {
+        if c == open {
+            return close
+        }
+        if c == close {
+            return open
+        }
+    }
+    c
+}
+
+// Returns the first presentation form of an Arabic letter and how many forms it has.
+fn arabic_forms(c: char) -> Option<(u32, u8)> {
+    let code = c as u32;
+    if code < 0x621 || code > 0x64a {
+        return None
+    }
+    let index = (code - 0x621) as usize;
+    let preceding: u32 = ARABIC_FORM_COUNTS[..index].iter().map(|&count| count as u32).sum();
+    let first = ARABIC_PRESENTATION_FORMS + preceding;
+    Some((first, ARABIC_FORM_COUNTS[index]))
+}
+
+fn joins_to_previous(c: char) -> bool {
+    c == ARABIC_TATWEEL || arabic_forms(c).map_or(false, |(_, count)| count >= 2)
+}
+
+fn joins_to_next(c: char) -> bool {
+    c == ARABIC_TATWEEL || arabic_forms(c).map_or(false, |(_, count)| count == 4)
+}
+
+fn lam_alef_variant(c: char) -> Option<u32> {
+    match c {
+        '\u{622}' => Some(0),
+        '\u{623}' => Some(1),
+        '\u{625}' => Some(2),
+        '\u{627}' => Some(3),
+        _ => None,
+    }
+}
+
+// Replaces Arabic letters with the contextual forms that connect them to their neighbors.
+fn shape_arabic(characters: &[char]) -> Vec<char> {
+    let mut shaped = vec![];
+    let mut skipped = None;
+    for index in 0..characters.len() {
+        let c = characters[index];
+        if skipped == Some(index) {
+            continue
+        }
+        let (first, count) = match arabic_forms(c) {
+            Some((first, count)) if count > 0 => (first, count),
+            _ => {
+                shaped.push(c);
+                continue
+            }
+        };
+        let previous = characters[..index].iter().rev().cloned().find(|&c| !is_mark(c));
+        let next_index = (index + 1..characters.len()).find(|&index| !is_mark(characters[index]));
+        let next = next_index.map(|index| characters[index]);
+        let joins_previous = count >= 2 && previous.map_or(false, joins_to_next);
+
+        if c == ARABIC_LAM {
+            if let Some(variant) = next.and_then(lam_alef_variant) {
+                let form = ARABIC_LAM_ALEF_FORMS + variant * 2 + if joins_previous { 1 } else { 0 };
+                shaped.push(char::from_u32(form).unwrap());
+                skipped = next_index;
+                continue
+            }
+        }
+
+        let joins_next = count == 4 && next.map_or(false, joins_to_previous);
+        let form = match (joins_previous, joins_next) {
+            (false, false) => 0,
+            (true, false) => 1,
+            (false, true) => 2,
+            (true, true) => 3,
+        };
+        shaped.push(char::from_u32(first + form).unwrap())
+    }
+    shaped
+}
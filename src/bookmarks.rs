@@ -0,0 +1,50 @@
+// imdialog/src/bookmarks.rs
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use xdg::BaseDirectories;
+
+static BOOKMARKS_FILENAME: &'static str = "bookmarks";
+
+fn base_directories() -> io::Result<BaseDirectories> {
+    BaseDirectories::with_prefix("imdialog/").map_err(|error| {
+        io::Error::new(io::ErrorKind::NotFound, error.to_string())
+    })
+}
+
+// Reads the system-wide bookmarks list followed by the user's own, one path per line.
+pub fn load() -> Vec<PathBuf> {
+    let base_directories = match base_directories() {
+        Ok(base_directories) => base_directories,
+        Err(_) => return vec![],
+    };
+    let mut directories = base_directories.get_config_dirs();
+    directories.reverse();
+    directories.push(base_directories.get_config_home());
+
+    let mut bookmarks = vec![];
+    for directory in directories {
+        let file = match File::open(directory.join(BOOKMARKS_FILENAME)) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            let bookmark = PathBuf::from(line.trim());
+            if !line.trim().is_empty() && !bookmarks.contains(&bookmark) {
+                bookmarks.push(bookmark)
+            }
+        }
+    }
+    bookmarks
+}
+
+pub fn add(bookmark: &Path) -> io::Result<()> {
+    let path = base_directories()?.place_config_file(BOOKMARKS_FILENAME)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", bookmark.display())
+}
@@ -0,0 +1,44 @@
+// imdialog/src/collation.rs
+
+use libc::{self, c_char};
+use locale::Locale;
+use std::cmp::Ordering;
+use std::ffi::CString;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Collation {
+    Locale,
+    Codepoint,
+}
+
+impl Collation {
+    pub fn from_name(name: &str) -> Option<Collation> {
+        match name {
+            "locale" => Some(Collation::Locale),
+            "codepoint" => Some(Collation::Codepoint),
+            _ => None,
+        }
+    }
+
+    // Without this, strcoll() sorts like strcmp().
+    pub fn init(self, locale: &Locale) {
+        if self == Collation::Locale {
+            locale.set_collation()
+        }
+    }
+
+    pub unsafe fn compare_c_strings(self, a: *const c_char, b: *const c_char) -> Ordering {
+        let result = match self {
+            Collation::Locale => libc::strcoll(a, b),
+            Collation::Codepoint => libc::strcmp(a, b),
+        };
+        result.cmp(&0)
+    }
+
+    pub fn compare(self, a: &str, b: &str) -> Ordering {
+        match (CString::new(a), CString::new(b)) {
+            (Ok(a), Ok(b)) => unsafe { self.compare_c_strings(a.as_ptr(), b.as_ptr()) },
+            _ => a.cmp(b),
+        }
+    }
+}
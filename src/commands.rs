@@ -0,0 +1,75 @@
+// imdialog/src/commands.rs
+
+use libc::c_int;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+#[derive(Debug)]
+pub enum Command {
+    SetText(String),
+    SetPercent(u32),
+    SetStep(String),
+    Log(String),
+    Close(c_int),
+    EndOfInput,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Option<Command> {
+        // NUL bytes can't be drawn, and would only get in the way of turning the text into a C
+        // string for imgui.
+        let line = line.replace('\0', "");
+        let line = line.trim();
+        // zenity's progress dialog takes `# text` lines as new prompts.
+        if line.starts_with('#') {
+            return Some(Command::SetText(line[1..].trim().to_string()))
+        }
+        let (name, argument) = match line.find(' ') {
+            Some(space) => (&line[..space], line[space + 1..].trim()),
+            None => (line, ""),
+        };
+        match name {
+            "set-text" => Some(Command::SetText(argument.replace("\\n", "\n"))),
+            "set-percent" => argument.parse().ok().map(|percent| Command::SetPercent(percent)),
+            "set-step" => Some(Command::SetStep(argument.to_string())),
+            "log" => Some(Command::Log(argument.to_string())),
+            "close" if argument.is_empty() => Some(Command::Close(0)),
+            "close" => argument.parse().ok().map(|code| Command::Close(code)),
+            // A bare number is a percentage, as with dialog(1)'s gauge.
+            _ => line.parse().ok().map(|percent| Command::SetPercent(percent)),
+        }
+    }
+}
+
+pub struct CommandChannel {
+    receiver: Receiver<Command>,
+}
+
+impl CommandChannel {
+    pub fn start() -> CommandChannel {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if let Some(command) = Command::parse(&line) {
+                    if sender.send(command).is_err() {
+                        return
+                    }
+                }
+            }
+            drop(sender.send(Command::EndOfInput))
+        });
+        CommandChannel {
+            receiver: receiver,
+        }
+    }
+
+    pub fn try_recv(&self) -> Option<Command> {
+        self.receiver.try_recv().ok()
+    }
+}
@@ -0,0 +1,170 @@
+// imdialog/src/compat.rs
+//
+// Runs a corpus of dialog(1) invocations through imdialog, headless, and checks that the exit
+// codes and output match what dialog did. Each case looks like this:
+//
+//     [inputbox returns the typed text]
+//     args: --inputbox "Your name?" 0 0
+//     input: type pat
+//     input: key Return
+//     exit: 0
+//     output: pat
+//
+// `args` is split like a shell would, `input` lines make up the `--script`, and `output` lines are
+// what dialog printed with `--stdout`. Blank lines and lines starting with `#` are ignored.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{self, Command, Stdio};
+
+struct Case {
+    name: String,
+    arguments: Vec<String>,
+    input: Vec<String>,
+    exit_code: i32,
+    output: Vec<String>,
+}
+
+pub fn run(corpus: &Path) -> ! {
+    let cases = match load(corpus) {
+        Ok(cases) => cases,
+        Err(error) => {
+            writeln!(io::stderr(), "error: couldn't read `{}`: {}", corpus.display(), error)
+                .unwrap();
+            process::exit(1)
+        }
+    };
+    let executable = env::current_exe().unwrap();
+    // Keeps recent files and remembered answers from leaking between cases or into the user's.
+    let directory = env::temp_dir().join(format!("imdialog-compat-{}", process::id()));
+    fs::create_dir_all(&directory).unwrap();
+
+    let mut failures = 0;
+    for (index, case) in cases.iter().enumerate() {
+        let problems = match check(case, &executable, &directory, index) {
+            Ok(problems) => problems,
+            Err(error) => vec![format!("couldn't run imdialog: {}", error)],
+        };
+        if problems.is_empty() {
+            println!("ok: {}", case.name);
+            continue
+        }
+        failures += 1;
+        println!("FAILED: {}", case.name);
+        for problem in problems {
+            println!("    {}", problem)
+        }
+    }
+    drop(fs::remove_dir_all(&directory));
+
+    println!("{} passed, {} failed", cases.len() - failures, failures);
+    process::exit(if failures == 0 { 0 } else { 1 })
+}
+
+fn check(case: &Case, executable: &Path, directory: &Path, index: usize)
+         -> io::Result<Vec<String>> {
+    let script_path = directory.join(format!("case-{}.script", index));
+    {
+        let mut script = File::create(&script_path)?;
+        for line in &case.input {
+            writeln!(script, "{}", line)?
+        }
+    }
+    let state_directory = directory.join(format!("case-{}-state", index));
+    fs::create_dir_all(&state_directory)?;
+
+    let result = Command::new(executable).args(&case.arguments)
+                                         .arg("--headless")
+                                         .arg("--script")
+                                         .arg(&script_path)
+                                         .env("XDG_STATE_HOME", &state_directory)
+                                         .env("LC_ALL", "C")
+                                         .stdin(Stdio::null())
+                                         .stderr(Stdio::null())
+                                         .output()?;
+
+    let mut problems = vec![];
+    match result.status.code() {
+        Some(code) if code == case.exit_code => {}
+        Some(code) => problems.push(format!("exited with {}, expected {}", code, case.exit_code)),
+        None => problems.push("killed by a signal".to_string()),
+    }
+    let output: Vec<String> = String::from_utf8_lossy(&result.stdout).lines()
+                                                                     .map(|line| line.to_string())
+                                                                     .collect();
+    if output != case.output {
+        problems.push(format!("printed {:?}, expected {:?}", output, case.output))
+    }
+    Ok(problems)
+}
+
+fn load(corpus: &Path) -> Result<Vec<Case>, String> {
+    let file = File::open(corpus).map_err(|error| error.to_string())?;
+    let mut cases: Vec<Case> = vec![];
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|error| error.to_string())?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+        let error = || format!("line {}: can't understand `{}`", index + 1, line);
+        if line.starts_with('[') && line.ends_with(']') {
+            cases.push(Case {
+                name: line[1..line.len() - 1].to_string(),
+                arguments: vec![],
+                input: vec![],
+                exit_code: 0,
+                output: vec![],
+            });
+            continue
+        }
+        let case = cases.last_mut().ok_or_else(&error)?;
+        let colon = line.find(':').ok_or_else(&error)?;
+        let value = line[colon + 1..].trim();
+        match &line[..colon] {
+            "args" => case.arguments = split_arguments(value).ok_or_else(&error)?,
+            "input" => case.input.push(value.to_string()),
+            "exit" => case.exit_code = value.parse().map_err(|_| error())?,
+            "output" => case.output.push(value.to_string()),
+            _ => return Err(error()),
+        }
+    }
+    Ok(cases)
+}
+
+// Handles single and double quotes and backslashes, which is all the corpus needs.
+fn split_arguments(line: &str) -> Option<Vec<String>> {
+    let mut arguments = vec![];
+    let mut argument = None;
+    let mut characters = line.chars();
+    while let Some(character) = characters.next() {
+        match character {
+            ' ' | '\t' => arguments.extend(argument.take()),
+            '\'' => {
+                let argument = argument.get_or_insert(String::new());
+                loop {
+                    match characters.next()? {
+                        '\'' => break,
+                        character => argument.push(character),
+                    }
+                }
+            }
+            '"' => {
+                let argument = argument.get_or_insert(String::new());
+                loop {
+                    match characters.next()? {
+                        '"' => break,
+                        '\\' => argument.push(characters.next()?),
+                        character => argument.push(character),
+                    }
+                }
+            }
+            '\\' => argument.get_or_insert(String::new()).push(characters.next()?),
+            character => argument.get_or_insert(String::new()).push(character),
+        }
+    }
+    arguments.extend(argument);
+    Some(arguments)
+}
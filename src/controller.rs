@@ -0,0 +1,126 @@
+// imdialog/src/controller.rs
+
+use sdl2::{GameControllerSubsystem, Sdl};
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::{self, Keycode, Scancode};
+
+const AXIS_THRESHOLD: i16 = 16384;
+
+pub struct Controllers {
+    subsystem: Option<GameControllerSubsystem>,
+    controllers: Vec<GameController>,
+    stick_x: Option<Scancode>,
+    stick_y: Option<Scancode>,
+}
+
+impl Controllers {
+    pub fn new(sdl: &Sdl) -> Controllers {
+        Controllers {
+            subsystem: sdl.game_controller().ok(),
+            controllers: vec![],
+            stick_x: None,
+            stick_y: None,
+        }
+    }
+
+    pub fn translate_event(&mut self, event: Event) -> Vec<Event> {
+        match event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                if let Some(ref subsystem) = self.subsystem {
+                    if let Ok(controller) = subsystem.open(which as u32) {
+                        self.controllers.push(controller)
+                    }
+                }
+                vec![]
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.controllers.retain(|controller| controller.instance_id() != which);
+                vec![]
+            }
+            Event::ControllerButtonDown { timestamp, button, .. } => {
+                match button_scancode(button) {
+                    Some(scancode) => vec![key_event(timestamp, scancode, true)],
+                    None => vec![],
+                }
+            }
+            Event::ControllerButtonUp { timestamp, button, .. } => {
+                match button_scancode(button) {
+                    Some(scancode) => vec![key_event(timestamp, scancode, false)],
+                    None => vec![],
+                }
+            }
+            Event::ControllerAxisMotion { timestamp, axis: Axis::LeftX, value, .. } => {
+                let direction = stick_direction(value, Scancode::Left, Scancode::Right);
+                update_stick(&mut self.stick_x, direction, timestamp)
+            }
+            Event::ControllerAxisMotion { timestamp, axis: Axis::LeftY, value, .. } => {
+                let direction = stick_direction(value, Scancode::Up, Scancode::Down);
+                update_stick(&mut self.stick_y, direction, timestamp)
+            }
+            Event::ControllerAxisMotion { .. } => vec![],
+            event => vec![event],
+        }
+    }
+}
+
+fn button_scancode(button: Button) -> Option<Scancode> {
+    match button {
+        Button::DPadUp => Some(Scancode::Up),
+        Button::DPadDown => Some(Scancode::Down),
+        Button::DPadLeft => Some(Scancode::Left),
+        Button::DPadRight => Some(Scancode::Right),
+        Button::A => Some(Scancode::Return),
+        Button::B => Some(Scancode::Escape),
+        _ => None,
+    }
+}
+
+fn stick_direction(value: i16, negative: Scancode, positive: Scancode) -> Option<Scancode> {
+    if value < -AXIS_THRESHOLD {
+        Some(negative)
+    } else if value > AXIS_THRESHOLD {
+        Some(positive)
+    } else {
+        None
+    }
+}
+
+fn update_stick(state: &mut Option<Scancode>, direction: Option<Scancode>, timestamp: u32)
+                -> Vec<Event> {
+    if *state == direction {
+        return vec![]
+    }
+
+    let mut events = vec![];
+    if let Some(old_scancode) = *state {
+        events.push(key_event(timestamp, old_scancode, false))
+    }
+    if let Some(new_scancode) = direction {
+        events.push(key_event(timestamp, new_scancode, true))
+    }
+    *state = direction;
+    events
+}
+
+fn key_event(timestamp: u32, scancode: Scancode, down: bool) -> Event {
+    if down {
+        Event::KeyDown {
+            timestamp: timestamp,
+            window_id: 0,
+            keycode: Keycode::from_scancode(scancode),
+            scancode: Some(scancode),
+            keymod: keyboard::NOMOD,
+            repeat: false,
+        }
+    } else {
+        Event::KeyUp {
+            timestamp: timestamp,
+            window_id: 0,
+            keycode: Keycode::from_scancode(scancode),
+            scancode: Some(scancode),
+            keymod: keyboard::NOMOD,
+            repeat: false,
+        }
+    }
+}
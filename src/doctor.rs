@@ -0,0 +1,153 @@
+// imdialog/src/doctor.rs
+//
+// `--doctor`: checks that a device can show dialogs at all, for deployment scripts to run before
+// relying on it. Each check prints one tab-separated line of name, `ok` or `fail`, and details,
+// and then a last `status` line gives the overall result, which is also the exit code:
+//
+//     font	ok	/usr/local/share/imdialog/Muli.ttf
+//     sdl	ok	x11
+//     gl	fail	couldn't create a GL context: ...
+//     status	fail	1 of 6 checks failed
+
+use gl;
+use gl::types::GLenum;
+use libc::c_int;
+use sdl2::{self, VideoSubsystem};
+use std::env;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Read;
+use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::ptr;
+use BaseDirectories;
+use {FONT_FILENAME, FRAGMENT_SHADER_FILENAME, VERTEX_SHADER_FILENAME};
+use {find_data_file, gpu_description};
+
+const FAILED_EXIT_CODE: c_int = 1;
+
+struct Report {
+    checks: u32,
+    failures: u32,
+}
+
+impl Report {
+    fn check<T, F>(&mut self, name: &str, result: Result<T, String>, detail: F) -> Option<T>
+                   where F: Fn(&T) -> String {
+        self.checks += 1;
+        match result {
+            Ok(value) => {
+                println!("{}\tok\t{}", name, detail(&value));
+                Some(value)
+            }
+            Err(message) => {
+                self.failures += 1;
+                println!("{}\tfail\t{}", name, message);
+                None
+            }
+        }
+    }
+}
+
+pub fn run(headless: bool) -> ! {
+    let mut report = Report {
+        checks: 0,
+        failures: 0,
+    };
+
+    let base_directories = BaseDirectories::with_prefix(PathBuf::from("imdialog/")).ok();
+    let mut paths = vec![];
+    for &(name, filename) in &[("font", FONT_FILENAME),
+                               ("vertex-shader", VERTEX_SHADER_FILENAME),
+                               ("fragment-shader", FRAGMENT_SHADER_FILENAME)] {
+        let path = base_directories.as_ref().and_then(|base_directories| {
+            find_data_file(filename, base_directories)
+        }).ok_or(format!("couldn't find `{}`", filename));
+        paths.push(report.check(name, path, |path| path.display().to_string()))
+    }
+    let shader_paths = match (paths[1].take(), paths[2].take()) {
+        (Some(vertex), Some(fragment)) => Some((vertex, fragment)),
+        _ => None,
+    };
+
+    if headless && env::var_os("SDL_VIDEODRIVER").is_none() {
+        env::set_var("SDL_VIDEODRIVER", "offscreen")
+    }
+    let video = sdl2::init().and_then(|sdl| sdl.video()).map_err(|error| {
+        format!("couldn't start SDL video: {}", error)
+    });
+    if let Some(video) = report.check("sdl", video, |video| {
+        video.current_video_driver().to_string()
+    }) {
+        check_gl(&mut report, &video, shader_paths)
+    } else {
+        report.checks += 2;
+        report.failures += 2;
+        println!("gl\tfail\tneeds SDL video");
+        println!("shaders\tfail\tneeds SDL video")
+    }
+
+    if report.failures == 0 {
+        println!("status\tok\tall {} checks passed", report.checks);
+        process::exit(0)
+    }
+    println!("status\tfail\t{} of {} checks failed", report.failures, report.checks);
+    process::exit(FAILED_EXIT_CODE)
+}
+
+fn check_gl(report: &mut Report,
+            video: &VideoSubsystem,
+            shader_paths: Option<(PathBuf, PathBuf)>) {
+    let window = video.window("imdialog", 64, 64).opengl().hidden().build().map_err(|error| {
+        format!("couldn't create a window: {}", error)
+    });
+    let context = window.and_then(|window| {
+        let context = window.gl_create_context()?;
+        window.gl_make_current(&context)?;
+        Ok((window, context))
+    }).map_err(|error| format!("couldn't create a GL context: {}", error));
+    if context.is_ok() {
+        gl::load_with(|name| video.gl_get_proc_address(name) as *const c_void)
+    }
+    let _context = match report.check("gl", context, |_| gpu_description()) {
+        Some(context) => context,
+        None => {
+            report.checks += 1;
+            report.failures += 1;
+            println!("shaders\tfail\tneeds a GL context");
+            return
+        }
+    };
+
+    let compiled = match shader_paths {
+        Some((vertex, fragment)) => {
+            compile(&vertex, gl::VERTEX_SHADER).and_then(|_| {
+                compile(&fragment, gl::FRAGMENT_SHADER)
+            })
+        }
+        None => Err("needs both shader files".to_string()),
+    };
+    report.check("shaders", compiled, |_| "compiled".to_string());
+}
+
+fn compile(path: &Path, kind: GLenum) -> Result<(), String> {
+    let mut source = String::new();
+    File::open(path).and_then(|mut file| file.read_to_string(&mut source)).map_err(|error| {
+        format!("couldn't read `{}`: {}", path.display(), error)
+    })?;
+    let source = CString::new(source).map_err(|_| format!("`{}` has a NUL", path.display()))?;
+    unsafe {
+        let shader = gl::CreateShader(kind);
+        gl::ShaderSource(shader, 1, &source.as_ptr(), ptr::null());
+        gl::CompileShader(shader);
+        let mut status = 0;
+        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut status);
+        gl::DeleteShader(shader);
+        if status == gl::TRUE as c_int {
+            Ok(())
+        } else {
+            Err(format!("`{}` didn't compile", path.display()))
+        }
+    }
+}
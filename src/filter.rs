@@ -0,0 +1,225 @@
+// imdialog/src/filter.rs
+
+use libc::{self, c_int};
+use locale::Locale;
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+static SIZE_UNITS: [(&'static str, u64); 4] = [
+    ("G", 1 << 30),
+    ("M", 1 << 20),
+    ("K", 1 << 10),
+    ("", 1),
+];
+
+#[derive(Clone)]
+pub struct FileFilter {
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub require_readable: bool,
+    pub require_writable: bool,
+    // Shell-style patterns, like `*.iso`. Files that match none of them are hidden.
+    pub patterns: Vec<String>,
+}
+
+impl FileFilter {
+    // Accepts a number of bytes with an optional K, M, or G suffix.
+    pub fn parse_size(size: &str) -> Option<u64> {
+        let size = size.trim();
+        for &(suffix, multiplier) in &SIZE_UNITS {
+            if !size.ends_with(suffix) && !size.ends_with(&suffix.to_lowercase()[..]) {
+                continue
+            }
+            let number = &size[..size.len() - suffix.len()];
+            return number.parse::<u64>().ok().and_then(|number| number.checked_mul(multiplier))
+        }
+        None
+    }
+
+    // Splits a list like `*.iso;*.img`.
+    pub fn parse_patterns(patterns: &str) -> Vec<String> {
+        patterns.split(';').map(|pattern| pattern.trim()).filter(|pattern| !pattern.is_empty())
+                                                         .map(|pattern| pattern.to_string())
+                                                         .collect()
+    }
+
+    // Directories are always shown so that the user can get to the files inside.
+    pub fn shows(&self, filename: &str, is_dir: bool) -> bool {
+        is_dir || self.patterns.is_empty() || self.patterns.iter().any(|pattern| {
+            glob_matches(pattern, filename)
+        })
+    }
+
+    // Returns why the file at `path` can't be chosen, if it can't.
+    pub fn reason(&self, path: &Path, locale: &Locale) -> Option<String> {
+        if self.min_size.is_some() || self.max_size.is_some() {
+            let size = match fs::metadata(path) {
+                Ok(metadata) => metadata.len(),
+                Err(error) => return Some(error.to_string()),
+            };
+            if let Some(min_size) = self.min_size {
+                if size < min_size {
+                    return Some(locale.translate("Too small: {} is under {}")
+                                      .replacen("{}", &format_size(size, locale), 1)
+                                      .replacen("{}", &format_size(min_size, locale), 1))
+                }
+            }
+            if let Some(max_size) = self.max_size {
+                if size > max_size {
+                    return Some(locale.translate("Too large: {} is over {}")
+                                      .replacen("{}", &format_size(size, locale), 1)
+                                      .replacen("{}", &format_size(max_size, locale), 1))
+                }
+            }
+        }
+        if self.require_readable && !accessible(path, libc::R_OK) {
+            let reason = locale.translate("You don't have permission to read this file");
+            return Some(reason.to_string())
+        }
+        if self.require_writable && !accessible(path, libc::W_OK) {
+            let reason = locale.translate("You don't have permission to write to this file");
+            return Some(reason.to_string())
+        }
+        None
+    }
+}
+
+// Supports `*` and `?`.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut pattern_index, mut text_index) = (0, 0);
+    // Where to resume if the text doesn't match after the last `*`.
+    let mut backtrack = None;
+    while text_index < text.len() {
+        match pattern.get(pattern_index) {
+            Some(&'*') => {
+                backtrack = Some((pattern_index, text_index));
+                pattern_index += 1;
+                continue
+            }
+            Some(&character) if character == '?' || character == text[text_index] => {
+                pattern_index += 1;
+                text_index += 1;
+                continue
+            }
+            _ => {}
+        }
+        match backtrack {
+            Some((star_index, star_text_index)) => {
+                pattern_index = star_index + 1;
+                text_index = star_text_index + 1;
+                backtrack = Some((star_index, star_text_index + 1))
+            }
+            None => return false,
+        }
+    }
+    pattern[pattern_index..].iter().all(|&character| character == '*')
+}
+
+fn accessible(path: &Path, mode: c_int) -> bool {
+    match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), mode) == 0 },
+        Err(_) => false,
+    }
+}
+
+pub fn format_size(size: u64, locale: &Locale) -> String {
+    for &(suffix, multiplier) in &SIZE_UNITS {
+        if suffix.is_empty() {
+            break
+        }
+        if size >= multiplier {
+            let size = locale.format_decimal(size as f64 / multiplier as f64, 1);
+            return format!("{} {}iB", size, suffix)
+        }
+    }
+    if size == 1 {
+        locale.translate("1 byte").to_string()
+    } else {
+        locale.translate("{} bytes").replace("{}", &locale.format_integer(size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use locale::Locale;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::process;
+    use super::{FileFilter, format_size, glob_matches};
+
+    fn filter() -> FileFilter {
+        FileFilter {
+            min_size: None,
+            max_size: None,
+            require_readable: false,
+            require_writable: false,
+            patterns: vec![],
+        }
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(FileFilter::parse_size("512"), Some(512));
+        assert_eq!(FileFilter::parse_size(" 4k"), Some(4096));
+        assert_eq!(FileFilter::parse_size("2M"), Some(2 << 20));
+        assert_eq!(FileFilter::parse_size("1G"), Some(1 << 30));
+        assert_eq!(FileFilter::parse_size("1.5M"), None);
+        assert_eq!(FileFilter::parse_size("M"), None);
+        assert_eq!(FileFilter::parse_size("99999999999G"), None);
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob_matches("*.iso", "debian.iso"));
+        assert!(!glob_matches("*.iso", "debian.iso.sig"));
+        assert!(glob_matches("disk?.img", "disk1.img"));
+        assert!(!glob_matches("disk?.img", "disk.img"));
+        assert!(glob_matches("*a*b*", "xaybz"));
+        assert!(glob_matches("*", ""));
+    }
+
+    #[test]
+    fn shows_directories_and_matching_files() {
+        let mut filter = filter();
+        filter.patterns = FileFilter::parse_patterns(" *.iso ; ;*.img");
+        assert_eq!(filter.patterns, ["*.iso", "*.img"]);
+        assert!(filter.shows("a.img", false));
+        assert!(!filter.shows("a.txt", false));
+        assert!(filter.shows("images", true));
+    }
+
+    #[test]
+    fn formats_sizes() {
+        let english = Locale::new("en_US.UTF-8");
+        let german = Locale::new("de_DE.UTF-8");
+        assert_eq!(format_size(1, &english), "1 byte");
+        assert_eq!(format_size(12345, &english), "12.1 KiB");
+        assert_eq!(format_size(1000, &english), "1000 bytes");
+        assert_eq!(format_size(1000, &german), "1000 Bytes");
+        assert_eq!(format_size(3 << 29, &german), "1,5 GiB");
+    }
+
+    #[test]
+    fn gives_a_reason_for_files_out_of_bounds() {
+        let path = env::temp_dir().join(format!("imdialog-filter-test-{}", process::id()));
+        File::create(&path).unwrap().write_all(b"0123456789").unwrap();
+        let english = Locale::new("en_US.UTF-8");
+        let mut filter = filter();
+        filter.min_size = Some(20);
+        assert_eq!(filter.reason(&path, &english),
+                   Some("Too small: 10 bytes is under 20 bytes".to_string()));
+        filter.min_size = Some(10);
+        filter.max_size = Some(5);
+        assert_eq!(filter.reason(&path, &Locale::new("fr_FR.UTF-8")),
+                   Some("Trop grand : 10 octets dépasse 5 octets".to_string()));
+        filter.max_size = None;
+        filter.require_readable = true;
+        assert_eq!(filter.reason(&path, &english), None);
+        drop(fs::remove_file(path))
+    }
+}
@@ -0,0 +1,154 @@
+// imdialog/src/form.rs
+//
+// Fields for `--form`. Each one is a key and a label, with its type and starting value given as
+// attributes on the label, the same way menu items take them:
+//
+//     --form "Settings" 0 0 \
+//         host 'Host name {default="box"}' \
+//         wifi 'Wi-Fi {type="bool" default="on"}' \
+//         channel 'Channel {type="int" min="1" max="13" default="6"}' \
+//         band 'Band {type="choice" choices="2.4 GHz|5 GHz"}' \
+//         key 'Password {type="password"}'
+
+use libc::c_int;
+use std::ffi::CString;
+use MAX_TEXT_LENGTH;
+
+pub enum Value {
+    // NUL-padded, for imgui to edit in place.
+    Text(Vec<u8>),
+    Integer {
+        value: c_int,
+        min: c_int,
+        max: c_int,
+    },
+    Bool(bool),
+    Choice {
+        choices: Vec<CString>,
+        index: c_int,
+    },
+}
+
+pub struct Field {
+    pub key: String,
+    pub label: String,
+    pub password: bool,
+    pub value: Value,
+}
+
+impl Field {
+    pub fn new(key: &str, label: &str, attributes: &[(String, String)])
+               -> Result<Field, String> {
+        let attribute = |name: &str| {
+            attributes.iter().find(|&&(ref key, _)| key == name).map(|&(_, ref value)| &value[..])
+        };
+        let default = attribute("default").unwrap_or("");
+        let kind = attribute("type").unwrap_or("text");
+        let value = match kind {
+            "text" | "password" => {
+                if default.len() >= MAX_TEXT_LENGTH || default.contains('\0') {
+                    return Err(format!("the default for `{}` is too long", key))
+                }
+                let mut data = default.as_bytes().to_vec();
+                data.resize(MAX_TEXT_LENGTH, 0);
+                Value::Text(data)
+            }
+            "int" => {
+                let number = |name: &str, fallback: c_int| -> Result<c_int, String> {
+                    match attribute(name) {
+                        None | Some("") => Ok(fallback),
+                        Some(value) => {
+                            value.trim().parse().map_err(|_| {
+                                format!("the {} of `{}` isn't a whole number", name, key)
+                            })
+                        }
+                    }
+                };
+                let (min, max) = (number("min", c_int::min_value())?,
+                                  number("max", c_int::max_value())?);
+                if min > max {
+                    return Err(format!("the min of `{}` is more than its max", key))
+                }
+                Value::Integer {
+                    value: number("default", 0)?.max(min).min(max),
+                    min: min,
+                    max: max,
+                }
+            }
+            "bool" => {
+                match default {
+                    "" | "off" | "false" | "0" => Value::Bool(false),
+                    "on" | "true" | "1" => Value::Bool(true),
+                    _ => return Err(format!("the default for `{}` should be on or off", key)),
+                }
+            }
+            "choice" => {
+                let choices: Vec<&str> = match attribute("choices") {
+                    Some(choices) if !choices.is_empty() => choices.split('|').collect(),
+                    _ => return Err(format!("`{}` needs choices, like `a|b|c`", key)),
+                };
+                let index = match choices.iter().position(|&choice| choice == default) {
+                    Some(index) => index,
+                    None if default.is_empty() => 0,
+                    None => return Err(format!("`{}` isn't one of the choices", default)),
+                };
+                Value::Choice {
+                    choices: choices.into_iter().map(|choice| {
+                        CString::new(choice).unwrap_or(CString::new("").unwrap())
+                    }).collect(),
+                    index: index as c_int,
+                }
+            }
+            _ => return Err(format!("unknown field type `{}`", kind)),
+        };
+        Ok(Field {
+            key: key.to_string(),
+            label: label.to_string(),
+            password: kind == "password",
+            value: value,
+        })
+    }
+
+    // What's printed for this field.
+    pub fn output(&self) -> String {
+        match self.value {
+            Value::Text(ref data) => {
+                let length = data.iter().position(|&byte| byte == 0).unwrap_or(data.len());
+                String::from_utf8_lossy(&data[..length]).into_owned()
+            }
+            Value::Integer { value, .. } => value.to_string(),
+            Value::Bool(true) => "on".to_string(),
+            Value::Bool(false) => "off".to_string(),
+            Value::Choice { ref choices, index } => {
+                choices[index as usize].to_string_lossy().into_owned()
+            }
+        }
+    }
+
+    // Puts back a value that `output` gave, as far as the field still allows it.
+    pub fn restore(&mut self, output: &str) {
+        match self.value {
+            Value::Text(ref mut data) => {
+                if output.len() < MAX_TEXT_LENGTH && !output.contains('\0') {
+                    data.clear();
+                    data.extend_from_slice(output.as_bytes());
+                    data.resize(MAX_TEXT_LENGTH, 0)
+                }
+            }
+            Value::Integer { ref mut value, min, max } => {
+                if let Ok(number) = output.parse::<c_int>() {
+                    *value = number.max(min).min(max)
+                }
+            }
+            Value::Bool(ref mut value) => *value = output == "on",
+            Value::Choice { ref choices, ref mut index } => {
+                let position = choices.iter().position(|choice| {
+                    choice.to_string_lossy() == output
+                });
+                if let Some(position) = position {
+                    *index = position as c_int
+                }
+            }
+        }
+    }
+}
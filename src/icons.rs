@@ -0,0 +1,241 @@
+// imdialog/src/icons.rs
+//
+// Icons shown before menu items, from `{icon="..."}`. An icon is either the name of one of the
+// glyphs below, drawn as shapes in the text color, or a path to a PNG. The PNGs are loaded while
+// the arguments are read and packed side by side into one texture the first time they're drawn,
+// since there's no GL context before then.
+
+use gl;
+use imgui_sys as imgui;
+use imgui_sys::{ImDrawList, ImGuiCol, ImTextureID, ImU32, ImVec2, ImVec4};
+use libc::{c_int, c_uint, c_void};
+use png;
+use std::cell::Cell;
+use std::path::Path;
+
+// Anything bigger is almost certainly the wrong file.
+const MAX_ICON_SIZE: u32 = 256;
+
+#[derive(Clone, Copy)]
+pub enum Glyph {
+    Play,
+    Pause,
+    Stop,
+    Record,
+    Folder,
+    File,
+    Check,
+    Cross,
+}
+
+static GLYPHS: [(&'static str, Glyph); 8] = [
+    ("play", Glyph::Play),
+    ("pause", Glyph::Pause),
+    ("stop", Glyph::Stop),
+    ("record", Glyph::Record),
+    ("folder", Glyph::Folder),
+    ("file", Glyph::File),
+    ("check", Glyph::Check),
+    ("cross", Glyph::Cross),
+];
+
+#[derive(Clone, Copy)]
+pub enum Icon {
+    Glyph(Glyph),
+    // An index into the atlas's regions.
+    Image(usize),
+}
+
+// Where an image sits in the atlas, in pixels; the atlas is one row of images, top-aligned.
+struct Region {
+    x: u32,
+    width: u32,
+    height: u32,
+}
+
+pub struct IconAtlas {
+    regions: Vec<Region>,
+    // RGBA, top row first.
+    images: Vec<Vec<u8>>,
+    texture: Cell<c_uint>,
+}
+
+impl IconAtlas {
+    pub fn new() -> IconAtlas {
+        IconAtlas {
+            regions: vec![],
+            images: vec![],
+            texture: Cell::new(0),
+        }
+    }
+
+    // Anything with a slash in it or ending in `.png` is a path; everything else is a glyph name.
+    pub fn load(&mut self, name: &str) -> Result<Icon, String> {
+        if let Some(&(_, glyph)) = GLYPHS.iter().find(|&&(glyph_name, _)| glyph_name == name) {
+            return Ok(Icon::Glyph(glyph))
+        }
+        if !name.contains('/') && !name.ends_with(".png") {
+            let names: Vec<_> = GLYPHS.iter().map(|&(glyph_name, _)| glyph_name).collect();
+            return Err(format!("unknown icon `{}` (try a PNG path or one of {})",
+                               name,
+                               names.join(", ")))
+        }
+        let (width, height, pixels) = png::read(Path::new(name)).map_err(|error| {
+            format!("couldn't load the icon `{}`: {}", name, error)
+        })?;
+        if width > MAX_ICON_SIZE || height > MAX_ICON_SIZE {
+            return Err(format!("the icon `{}` is bigger than {}x{}",
+                               name,
+                               MAX_ICON_SIZE,
+                               MAX_ICON_SIZE))
+        }
+        let x = self.regions.last().map_or(0, |region| region.x + region.width);
+        self.regions.push(Region {
+            x: x,
+            width: width,
+            height: height,
+        });
+        self.images.push(pixels);
+        Ok(Icon::Image(self.regions.len() - 1))
+    }
+
+    fn size(&self) -> (u32, u32) {
+        let width = self.regions.last().map_or(0, |region| region.x + region.width);
+        (width, self.regions.iter().map(|region| region.height).max().unwrap_or(0))
+    }
+
+    fn texture(&self) -> c_uint {
+        if self.texture.get() != 0 {
+            return self.texture.get()
+        }
+        let (width, height) = self.size();
+        let mut pixels = vec![0; width as usize * height as usize * 4];
+        for (region, image) in self.regions.iter().zip(self.images.iter()) {
+            let row_size = region.width as usize * 4;
+            for (y, row) in image.chunks(row_size).enumerate() {
+                let start = (y * width as usize + region.x as usize) * 4;
+                pixels[start..(start + row_size)].copy_from_slice(row)
+            }
+        }
+        unsafe {
+            let mut texture = 0;
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as c_int);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as c_int);
+            gl::TexImage2D(gl::TEXTURE_2D, 0,
+                           gl::RGBA as c_int,
+                           width as c_int, height as c_int,
+                           0,
+                           gl::RGBA,
+                           gl::UNSIGNED_BYTE,
+                           pixels.as_ptr() as *const c_void);
+            self.texture.set(texture);
+            texture
+        }
+    }
+
+    // Draws the icon in a `size` square at the cursor and leaves the cursor just after it, on
+    // the same line.
+    pub fn draw(&self, icon: Icon, size: f32) {
+        unsafe {
+            let mut origin = ImVec2 { x: 0.0, y: 0.0 };
+            imgui::igGetCursorScreenPos(&mut origin);
+            match icon {
+                Icon::Glyph(glyph) => {
+                    draw_glyph(imgui::igGetWindowDrawList(),
+                               glyph,
+                               origin,
+                               size,
+                               imgui::igGetColorU32(ImGuiCol::Text, 1.0))
+                }
+                Icon::Image(index) => {
+                    let region = &self.regions[index];
+                    let (atlas_width, atlas_height) = self.size();
+                    let (atlas_width, atlas_height) = (atlas_width as f32, atlas_height as f32);
+                    // Shrunk or grown to fit, keeping its shape, and centered in the square.
+                    let scale = size / region.width.max(region.height) as f32;
+                    let (width, height) = (region.width as f32 * scale,
+                                           region.height as f32 * scale);
+                    let corner = ImVec2 {
+                        x: origin.x + (size - width) / 2.0,
+                        y: origin.y + (size - height) / 2.0,
+                    };
+                    let (u0, u1) = (region.x as f32 / atlas_width,
+                                    (region.x + region.width) as f32 / atlas_width);
+                    let v1 = region.height as f32 / atlas_height;
+                    let white = imgui::igGetColorU32Vec(&ImVec4 {
+                        x: 1.0,
+                        y: 1.0,
+                        z: 1.0,
+                        w: 1.0,
+                    });
+                    imgui::ImDrawList_AddImage(imgui::igGetWindowDrawList(),
+                                               self.texture() as usize as ImTextureID,
+                                               corner,
+                                               ImVec2 { x: corner.x + width, y: corner.y + height },
+                                               ImVec2 { x: u0, y: 0.0 },
+                                               ImVec2 { x: u1, y: v1 },
+                                               white)
+                }
+            }
+            imgui::igDummy(&ImVec2 { x: size, y: size });
+            imgui::igSameLine(0.0, -1.0)
+        }
+    }
+}
+
+impl Drop for IconAtlas {
+    fn drop(&mut self) {
+        let texture = self.texture.get();
+        if texture != 0 {
+            unsafe {
+                gl::DeleteTextures(1, &texture)
+            }
+        }
+    }
+}
+
+unsafe fn draw_glyph(list: *mut ImDrawList,
+                     glyph: Glyph,
+                     origin: ImVec2,
+                     size: f32,
+                     color: ImU32) {
+    // Positions as fractions of the square.
+    let at = |x: f32, y: f32| ImVec2 { x: origin.x + x * size, y: origin.y + y * size };
+    let thickness = (size * 0.12).max(1.0);
+    match glyph {
+        Glyph::Play => {
+            imgui::ImDrawList_AddTriangleFilled(list,
+                                                at(0.2, 0.15),
+                                                at(0.2, 0.85),
+                                                at(0.85, 0.5),
+                                                color)
+        }
+        Glyph::Pause => {
+            imgui::ImDrawList_AddRectFilled(list, at(0.2, 0.15), at(0.42, 0.85), color, 0.0, 0);
+            imgui::ImDrawList_AddRectFilled(list, at(0.58, 0.15), at(0.8, 0.85), color, 0.0, 0)
+        }
+        Glyph::Stop => {
+            imgui::ImDrawList_AddRectFilled(list, at(0.2, 0.2), at(0.8, 0.8), color, 0.0, 0)
+        }
+        Glyph::Record => {
+            imgui::ImDrawList_AddCircleFilled(list, at(0.5, 0.5), size * 0.3, color, 16)
+        }
+        Glyph::Folder => {
+            imgui::ImDrawList_AddRectFilled(list, at(0.1, 0.2), at(0.45, 0.35), color, 0.0, 0);
+            imgui::ImDrawList_AddRectFilled(list, at(0.1, 0.3), at(0.9, 0.8), color, 0.0, 0)
+        }
+        Glyph::File => {
+            imgui::ImDrawList_AddRect(list, at(0.25, 0.1), at(0.75, 0.9), color, 0.0, 0, thickness)
+        }
+        Glyph::Check => {
+            imgui::ImDrawList_AddLine(list, at(0.15, 0.55), at(0.4, 0.8), color, thickness);
+            imgui::ImDrawList_AddLine(list, at(0.4, 0.8), at(0.85, 0.25), color, thickness)
+        }
+        Glyph::Cross => {
+            imgui::ImDrawList_AddLine(list, at(0.2, 0.2), at(0.8, 0.8), color, thickness);
+            imgui::ImDrawList_AddLine(list, at(0.8, 0.2), at(0.2, 0.8), color, thickness)
+        }
+    }
+}
@@ -0,0 +1,305 @@
+// imdialog/src/inflate.rs
+//
+// A small zlib decompressor for reading PNG icons, after Mark Adler's puff: slow, but short enough
+// to check by eye.
+
+const MAX_BITS: usize = 15;
+
+static LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+static LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+static DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+static DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order code length code lengths come in, in a dynamic block's header.
+static CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+// Unpacks a zlib stream, giving up once the output passes `limit` bytes.
+pub fn zlib(data: &[u8], limit: usize) -> Result<Vec<u8>, &'static str> {
+    if data.len() < 2 || (data[0] & 0x0f) != 8 ||
+            ((data[0] as u16) << 8 | data[1] as u16) % 31 != 0 {
+        return Err("not a zlib stream")
+    }
+    if (data[1] & 0x20) != 0 {
+        return Err("zlib preset dictionaries aren't supported")
+    }
+    let mut inflater = Inflater {
+        input: &data[2..],
+        position: 0,
+        bit_buffer: 0,
+        bit_count: 0,
+        output: vec![],
+        limit: limit,
+    };
+    loop {
+        let last = inflater.bits(1)? == 1;
+        match inflater.bits(2)? {
+            0 => inflater.stored()?,
+            1 => {
+                let (lengths, distances) = fixed_codes();
+                inflater.codes(&lengths, &distances)?
+            }
+            2 => {
+                let (lengths, distances) = inflater.dynamic_codes()?;
+                inflater.codes(&lengths, &distances)?
+            }
+            _ => return Err("bad deflate block type"),
+        }
+        if last {
+            return Ok(inflater.output)
+        }
+    }
+}
+
+struct Inflater<'a> {
+    input: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+    output: Vec<u8>,
+    limit: usize,
+}
+
+// A canonical Huffman code: how many symbols have each length, and the symbols in code order.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, &'static str> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1
+        }
+        let mut left: i32 = 1;
+        for length in 1..(MAX_BITS + 1) {
+            left = (left << 1) - counts[length] as i32;
+            if left < 0 {
+                return Err("oversubscribed Huffman code")
+            }
+        }
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for length in 1..(MAX_BITS + 1) {
+            offsets[length + 1] = offsets[length] + counts[length]
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1
+            }
+        }
+        counts[0] = 0;
+        Ok(Huffman {
+            counts: counts,
+            symbols: symbols,
+        })
+    }
+}
+
+impl<'a> Inflater<'a> {
+    fn bits(&mut self, count: u32) -> Result<u32, &'static str> {
+        while self.bit_count < count {
+            let byte = match self.input.get(self.position) {
+                Some(&byte) => byte,
+                None => return Err("deflate stream ends early"),
+            };
+            self.position += 1;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.bit_count += 8
+        }
+        let value = self.bit_buffer & ((1 << count) - 1);
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn push(&mut self, byte: u8) -> Result<(), &'static str> {
+        if self.output.len() >= self.limit {
+            return Err("image data is bigger than the image")
+        }
+        self.output.push(byte);
+        Ok(())
+    }
+
+    fn stored(&mut self) -> Result<(), &'static str> {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+        if self.position + 4 > self.input.len() {
+            return Err("deflate stream ends early")
+        }
+        let header = &self.input[self.position..self.position + 4];
+        let length = header[0] as usize | (header[1] as usize) << 8;
+        if length != (!(header[2] as usize | (header[3] as usize) << 8) & 0xffff) {
+            return Err("stored block length doesn't match its complement")
+        }
+        self.position += 4;
+        if self.position + length > self.input.len() {
+            return Err("deflate stream ends early")
+        }
+        for index in self.position..self.position + length {
+            let byte = self.input[index];
+            self.push(byte)?
+        }
+        self.position += length;
+        Ok(())
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> Result<u16, &'static str> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..(MAX_BITS + 1) {
+            code |= self.bits(1)? as i32;
+            let count = huffman.counts[length] as i32;
+            if code - count < first {
+                return Ok(huffman.symbols[(index + (code - first)) as usize])
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1
+        }
+        Err("bad Huffman code")
+    }
+
+    fn codes(&mut self, lengths: &Huffman, distances: &Huffman) -> Result<(), &'static str> {
+        loop {
+            let symbol = self.decode(lengths)? as usize;
+            if symbol < 256 {
+                self.push(symbol as u8)?;
+                continue
+            }
+            if symbol == 256 {
+                return Ok(())
+            }
+            let symbol = symbol - 257;
+            if symbol >= LENGTH_BASE.len() {
+                return Err("bad length symbol")
+            }
+            let length = LENGTH_BASE[symbol] as usize +
+                self.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+            let symbol = self.decode(distances)? as usize;
+            if symbol >= DISTANCE_BASE.len() {
+                return Err("bad distance symbol")
+            }
+            let distance = DISTANCE_BASE[symbol] as usize +
+                self.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+            if distance > self.output.len() {
+                return Err("distance reaches back before the start")
+            }
+            for _ in 0..length {
+                let byte = self.output[self.output.len() - distance];
+                self.push(byte)?
+            }
+        }
+    }
+
+    fn dynamic_codes(&mut self) -> Result<(Huffman, Huffman), &'static str> {
+        let length_count = self.bits(5)? as usize + 257;
+        let distance_count = self.bits(5)? as usize + 1;
+        let code_length_count = self.bits(4)? as usize + 4;
+        if length_count > 286 || distance_count > 30 {
+            return Err("too many codes in a dynamic block")
+        }
+        let mut code_lengths = [0u8; 19];
+        for &index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+            code_lengths[index] = self.bits(3)? as u8
+        }
+        let code_length_code = Huffman::new(&code_lengths)?;
+
+        let mut lengths = vec![];
+        while lengths.len() < length_count + distance_count {
+            let symbol = self.decode(&code_length_code)?;
+            let (value, repeat) = match symbol {
+                0..=15 => (symbol as u8, 1),
+                16 => {
+                    let previous = match lengths.last() {
+                        Some(&previous) => previous,
+                        None => return Err("repeat with no previous length"),
+                    };
+                    (previous, 3 + self.bits(2)?)
+                }
+                17 => (0, 3 + self.bits(3)?),
+                _ => (0, 11 + self.bits(7)?),
+            };
+            for _ in 0..repeat {
+                lengths.push(value)
+            }
+        }
+        if lengths.len() > length_count + distance_count {
+            return Err("code lengths run past the end")
+        }
+        if lengths[256] == 0 {
+            return Err("no end-of-block code")
+        }
+        Ok((Huffman::new(&lengths[..length_count])?, Huffman::new(&lengths[length_count..])?))
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        }
+    }
+    (Huffman::new(&lengths).unwrap(), Huffman::new(&[5; 30]).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::zlib;
+
+    // zlib's own output for "hello hello hello hello", which uses the fixed codes.
+    static FIXED: [u8; 16] = [
+        0x78, 0xda, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x01, 0x68, 0x03, 0x08,
+        0xb1,
+    ];
+    // And for `DYNAMIC_TEXT`, which gets codes of its own.
+    static DYNAMIC: [u8; 38] = [
+        0x78, 0xda, 0x25, 0x89, 0xc1, 0x0d, 0x00, 0x30, 0x10, 0x82, 0x66, 0x05, 0xf7, 0xdf, 0xa1,
+        0xd5, 0x7b, 0x18, 0x09, 0x00, 0x41, 0xc0, 0x94, 0xfe, 0x8f, 0x50, 0x8b, 0x4c, 0xba, 0xd9,
+        0x78, 0xb2, 0x35, 0x3c, 0xb9, 0x9d, 0x16, 0xdf,
+    ];
+    static DYNAMIC_TEXT: &'static [u8] =
+        b"aaacabaaabcaacaaababcaaabbbababaaacaabcaabcbaabaababaabbbaca";
+
+    #[test]
+    fn inflates_stored_blocks() {
+        let stream = [0x78, 0x01, 0, 2, 0, 0xfd, 0xff, b'a', b'b', 1, 1, 0, 0xfe, 0xff, b'c'];
+        assert_eq!(zlib(&stream, 3), Ok(b"abc".to_vec()));
+        let mut stream = stream;
+        stream[5] = 0;
+        assert!(zlib(&stream, 3).is_err());
+    }
+
+    #[test]
+    fn inflates_fixed_and_dynamic_codes() {
+        assert_eq!(zlib(&FIXED, 100), Ok(b"hello hello hello hello".to_vec()));
+        assert_eq!(zlib(&DYNAMIC, 100), Ok(DYNAMIC_TEXT.to_vec()));
+    }
+
+    #[test]
+    fn rejects_bad_streams() {
+        assert!(zlib(&[0x78, 0x00], 100).is_err());
+        assert!(zlib(&[0x78, 0xbb, 0, 0, 0, 0], 100).is_err());
+        assert!(zlib(&FIXED[..8], 100).is_err());
+        assert!(zlib(&DYNAMIC[..20], 100).is_err());
+        // Nothing is unpacked past the limit, for images that claim to be smaller than they are.
+        assert!(zlib(&FIXED, 22).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+// imdialog/src/json.rs
+//
+// Just enough JSON for the files imdialog reads. Objects keep their keys in order, and numbers
+// are kept as `f64`.
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref members) => {
+                members.iter().find(|&&(ref name, _)| name == key).map(|&(_, ref value)| value)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref string) => Some(string),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        text: text,
+        position: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.position < text.len() {
+        return Err(parser.error("text after the end"))
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn error(&self, problem: &str) -> String {
+        // The position may have been left inside a character by whatever went wrong.
+        let mut position = self.position.min(self.text.len());
+        while !self.text.is_char_boundary(position) {
+            position -= 1
+        }
+        let line = self.text[..position].matches('\n').count() + 1;
+        format!("line {}: {}", line, problem)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len()
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), String> {
+        self.skip_whitespace();
+        if !self.rest().starts_with(literal) {
+            return Err(self.error(&format!("expected `{}`", literal)))
+        }
+        self.position += literal.len();
+        Ok(())
+    }
+
+    // Consumes `literal` if it comes next.
+    fn accept(&mut self, literal: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.rest().starts_with(literal);
+        if found {
+            self.position += literal.len()
+        }
+        found
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        for &(literal, ref value) in &[("null", Value::Null),
+                                       ("true", Value::Bool(true)),
+                                       ("false", Value::Bool(false))] {
+            if self.accept(literal) {
+                return Ok(value.clone())
+            }
+        }
+        match self.rest().chars().next() {
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.position += 1;
+                let mut elements = vec![];
+                if self.accept("]") {
+                    return Ok(Value::Array(elements))
+                }
+                loop {
+                    elements.push(self.value()?);
+                    if self.accept("]") {
+                        return Ok(Value::Array(elements))
+                    }
+                    self.expect(",")?
+                }
+            }
+            Some('{') => {
+                self.position += 1;
+                let mut members = vec![];
+                if self.accept("}") {
+                    return Ok(Value::Object(members))
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(":")?;
+                    members.push((key, self.value()?));
+                    if self.accept("}") {
+                        return Ok(Value::Object(members))
+                    }
+                    self.expect(",")?
+                }
+            }
+            Some(c) if c == '-' || c.is_digit(10) => {
+                let rest = self.rest();
+                let end = rest.find(|c: char| !"+-.eE".contains(c) && !c.is_digit(10))
+                              .unwrap_or(rest.len());
+                match rest[..end].parse() {
+                    Ok(number) => {
+                        self.position += end;
+                        Ok(Value::Number(number))
+                    }
+                    Err(_) => Err(self.error(&format!("`{}` isn't a number", &rest[..end]))),
+                }
+            }
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("the text ends too soon")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if !self.rest().starts_with('"') {
+            return Err(self.error("expected a string"))
+        }
+        self.position += 1;
+        let mut string = String::new();
+        loop {
+            let c = match self.rest().chars().next() {
+                Some(c) => c,
+                None => return Err(self.error("a string isn't closed")),
+            };
+            self.position += c.len_utf8();
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escape = match self.rest().chars().next() {
+                        Some(escape) => escape,
+                        None => return Err(self.error("a string isn't closed")),
+                    };
+                    self.position += escape.len_utf8();
+                    string.push(match escape {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("unknown escape in a string")),
+                    })
+                }
+                c => string.push(c),
+            }
+        }
+    }
+
+    // The character for the `\uXXXX` whose `\u` was just read, and its low surrogate if it's a
+    // high one.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex_digits()?;
+        let code = if high >= 0xd800 && high < 0xdc00 && self.rest().starts_with("\\u") {
+            self.position += 2;
+            let low = self.hex_digits()?;
+            if low < 0xdc00 || low > 0xdfff {
+                return Err(self.error("a high surrogate needs a low one after it"))
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        ::std::char::from_u32(code).ok_or_else(|| self.error("a `\\u` escape isn't a character"))
+    }
+
+    fn hex_digits(&mut self) -> Result<u32, String> {
+        // `from_str_radix` alone would take a sign as well.
+        let digits = self.rest().get(..4).and_then(|digits| {
+            if digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                u32::from_str_radix(digits, 16).ok()
+            } else {
+                None
+            }
+        });
+        match digits {
+            Some(digits) => {
+                self.position += 4;
+                Ok(digits)
+            }
+            None => Err(self.error("a `\\u` escape needs four hex digits")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Value, parse};
+
+    fn string(text: &str) -> Result<String, String> {
+        parse(text).map(|value| value.as_str().unwrap_or("").to_string())
+    }
+
+    #[test]
+    fn parses_objects_in_order() {
+        let value = parse(r#"{"b": [1, -2.5e1, true, null], "a": {"c": "d"}}"#).unwrap();
+        match value {
+            Value::Object(ref members) => {
+                let keys: Vec<_> = members.iter().map(|&(ref key, _)| &key[..]).collect();
+                assert_eq!(keys, ["b", "a"])
+            }
+            _ => panic!("not an object"),
+        }
+        assert_eq!(format!("{:?}", value.get("b")),
+                   "Some(Array([Number(1.0), Number(-25.0), Bool(true), Null]))");
+        assert_eq!(value.get("a").and_then(|a| a.get("c")).and_then(Value::as_str), Some("d"));
+        assert!(value.get("c").is_none());
+    }
+
+    #[test]
+    fn reports_the_line_of_an_error() {
+        assert_eq!(parse("[1,\n2,\n]").err(), Some("line 3: expected a value".to_string()));
+        assert!(parse("{} {}").is_err());
+        assert!(parse("\"open").is_err());
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(string(r#""a\"\\\/\n\t""#), Ok("a\"\\/\n\t".to_string()));
+        assert_eq!(string(r#""\u00e9\u00E9""#), Ok("\u{e9}\u{e9}".to_string()));
+        assert_eq!(string(r#""\ud83d\ude00""#), Ok("\u{1f600}".to_string()));
+    }
+
+    #[test]
+    fn rejects_bad_unicode_escapes() {
+        assert!(string(r#""\u+0e9""#).is_err());
+        assert!(string(r#""\u 0e9""#).is_err());
+        assert!(string(r#""\u0e""#).is_err());
+        // A high surrogate has to be followed by a low one.
+        assert!(string(r#""\ud83d""#).is_err());
+        assert!(string(r#""\ud83dA""#).is_err());
+        assert!(string(r#""\ud83d\ud83d""#).is_err());
+        assert!(string(r#""\ude00""#).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+// imdialog/src/keymap.rs
+//
+// Keyboard layouts for `--kbdlayout`. Under X11 these are the XKB layouts, which `setxkbmap` can
+// switch to straight away for trying out; on the console they're the keymaps `loadkeys` knows,
+// which can only be tried once they're loaded.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+static XKB_RULES: [&'static str; 2] = ["/usr/share/X11/xkb/rules/evdev.lst",
+                                       "/usr/share/X11/xkb/rules/base.lst"];
+static CONSOLE_KEYMAP_DIRECTORIES: [&'static str; 3] = ["/usr/share/keymaps",
+                                                        "/usr/share/kbd/keymaps",
+                                                        "/lib/kbd/keymaps"];
+static CONSOLE_KEYMAP_EXTENSIONS: [&'static str; 4] = [".kmap.gz", ".map.gz", ".kmap", ".map"];
+static VCONSOLE_CONF: &'static str = "/etc/vconsole.conf";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    X11,
+    Console,
+}
+
+impl Kind {
+    pub fn current() -> Kind {
+        if env::var_os("DISPLAY").is_some() { Kind::X11 } else { Kind::Console }
+    }
+}
+
+pub struct Keymap {
+    pub name: String,
+    pub description: String,
+}
+
+// Sorted by name.
+pub fn list(kind: Kind) -> Vec<Keymap> {
+    let mut keymaps = match kind {
+        Kind::X11 => xkb_layouts(),
+        Kind::Console => console_keymaps(),
+    };
+    if keymaps.is_empty() {
+        let argument = match kind {
+            Kind::X11 => "list-x11-keymap-layouts",
+            Kind::Console => "list-keymaps",
+        };
+        keymaps = output_lines("localectl", &[argument]).into_iter().map(|name| {
+            Keymap {
+                name: name,
+                description: String::new(),
+            }
+        }).collect()
+    }
+    keymaps.sort_by(|a, b| a.name.cmp(&b.name));
+    keymaps.dedup_by(|a, b| a.name == b.name);
+    keymaps
+}
+
+// The `! layout` section of the XKB rules list, whose lines are a name and then a description.
+fn xkb_layouts() -> Vec<Keymap> {
+    let file = match XKB_RULES.iter().filter_map(|path| File::open(path).ok()).next() {
+        Some(file) => file,
+        None => return vec![],
+    };
+    let mut keymaps = vec![];
+    let mut in_layouts = false;
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.starts_with('!') {
+            in_layouts = line[1..].trim() == "layout";
+            continue
+        }
+        let line = line.trim();
+        if !in_layouts || line.is_empty() {
+            continue
+        }
+        let mut fields = line.splitn(2, char::is_whitespace);
+        if let Some(name) = fields.next() {
+            keymaps.push(Keymap {
+                name: name.to_string(),
+                description: fields.next().unwrap_or("").trim().to_string(),
+            })
+        }
+    }
+    keymaps
+}
+
+// Every keymap file under the kbd directories, described by the directory it's in, like `qwerty`.
+fn console_keymaps() -> Vec<Keymap> {
+    let mut keymaps = vec![];
+    for directory in CONSOLE_KEYMAP_DIRECTORIES.iter() {
+        find_console_keymaps(Path::new(directory), "", &mut keymaps)
+    }
+    keymaps
+}
+
+fn find_console_keymaps(directory: &Path, description: &str, keymaps: &mut Vec<Keymap>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let filename = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        if path.is_dir() {
+            // Only pieces the other keymaps are built from.
+            if filename != "include" {
+                find_console_keymaps(&path, &filename, keymaps)
+            }
+            continue
+        }
+        let extension = CONSOLE_KEYMAP_EXTENSIONS.iter().find(|&extension| {
+            filename.ends_with(extension)
+        });
+        if let Some(extension) = extension {
+            keymaps.push(Keymap {
+                name: filename[..filename.len() - extension.len()].to_string(),
+                description: description.to_string(),
+            })
+        }
+    }
+}
+
+// The layout in use, if it can be found out.
+pub fn current(kind: Kind) -> Option<String> {
+    let lines = match kind {
+        Kind::X11 => output_lines("setxkbmap", &["-query"]),
+        Kind::Console => {
+            let file = File::open(VCONSOLE_CONF).ok()?;
+            BufReader::new(file).lines().filter_map(|line| line.ok()).collect()
+        }
+    };
+    let key = match kind {
+        Kind::X11 => "layout:",
+        Kind::Console => "KEYMAP=",
+    };
+    lines.iter().filter_map(|line| {
+        if line.starts_with(key) { Some(line[key.len()..].trim()) } else { None }
+    }).map(|value| {
+        // Several XKB layouts are separated by commas, and the first is the one in use.
+        value.trim_matches('"').split(',').next().unwrap_or("").to_string()
+    }).find(|name| !name.is_empty())
+}
+
+// Switches the X server to `name` until something else switches it back, for trying it out.
+pub fn preview(name: &str) -> Result<(), String> {
+    run("setxkbmap", &[name])
+}
+
+// Makes `name` the layout from now on, after reboots as well.
+pub fn apply(kind: Kind, name: &str) -> Result<(), String> {
+    match kind {
+        Kind::X11 => {
+            run("localectl", &["set-x11-keymap", name])?;
+            preview(name)
+        }
+        // Without systemd, the keymap can still be loaded for now.
+        Kind::Console => run("localectl", &["set-keymap", name]).or_else(|error| {
+            writeln!(io::stderr(), "warning: {}; loading the keymap until the next boot", error)
+                .unwrap();
+            run("loadkeys", &[name])
+        }),
+    }
+}
+
+fn run(program: &str, arguments: &[&str]) -> Result<(), String> {
+    debug!("running {} {:?}", program, arguments);
+    match Command::new(program).args(arguments).stdout(Stdio::null()).status() {
+        Ok(ref status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("`{} {}` failed: {}", program, arguments.join(" "), status)),
+        Err(error) => Err(format!("couldn't run `{}`: {}", program, error)),
+    }
+}
+
+fn output_lines(program: &str, arguments: &[&str]) -> Vec<String> {
+    let output = match Command::new(program).args(arguments).stderr(Stdio::null()).output() {
+        Ok(ref output) if output.status.success() => output.stdout.clone(),
+        _ => return vec![],
+    };
+    String::from_utf8_lossy(&output).lines().map(|line| line.to_string()).collect()
+}
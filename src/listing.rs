@@ -0,0 +1,102 @@
+// imdialog/src/listing.rs
+//
+// Reads directories for the file dialog on a worker thread, so that network mounts and disks that
+// have to spin up don't hold up rendering.
+
+use std::ffi::OsString;
+use std::fs;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use {EntryDetails, FileEntry};
+
+// Entries are sent in batches of this many, so the list fills in as the directory is read.
+const BATCH_SIZE: usize = 256;
+
+pub(crate) enum Received {
+    Entries(Vec<FileEntry>),
+    Waiting,
+    Finished,
+}
+
+pub(crate) struct DirectoryLoader {
+    receiver: Receiver<Vec<FileEntry>>,
+    started: Instant,
+}
+
+impl DirectoryLoader {
+    // `make_entry` runs on the worker thread and turns each name into an entry, or `None` to leave
+    // it out. A directory that can't be read comes out empty.
+    pub(crate) fn start<F>(path: PathBuf, make_entry: F) -> DirectoryLoader
+                    where F: Fn(OsString, EntryDetails) -> Option<FileEntry> + Send + 'static {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let directory_entries = match fs::read_dir(&path) {
+                Ok(entries) => entries,
+                Err(_) => return,
+            };
+            let mut batch = vec![];
+            for entry in directory_entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(_) => continue,
+                };
+                let link = match entry.file_type() {
+                    Ok(ref file_type) if file_type.is_symlink() => fs::read_link(entry.path()).ok(),
+                    _ => None,
+                };
+                // Follows symlinks, so a link to a directory lists as one.
+                let details = match fs::metadata(entry.path()) {
+                    Ok(metadata) => {
+                        EntryDetails {
+                            is_dir: metadata.is_dir(),
+                            size: metadata.len(),
+                            modified: metadata.modified().ok(),
+                            link: link,
+                            broken: false,
+                        }
+                    }
+                    Err(_) => {
+                        EntryDetails {
+                            is_dir: false,
+                            size: 0,
+                            modified: None,
+                            broken: link.is_some(),
+                            link: link,
+                        }
+                    }
+                };
+                batch.extend(make_entry(entry.file_name(), details));
+                // The dialog has moved on once the receiver is gone.
+                if batch.len() == BATCH_SIZE &&
+                        sender.send(mem::replace(&mut batch, vec![])).is_err() {
+                    return
+                }
+            }
+            drop(sender.send(batch))
+        });
+        DirectoryLoader {
+            receiver: receiver,
+            started: Instant::now(),
+        }
+    }
+
+    // Waits up to `timeout` for the next batch, or until there is one if `timeout` is `None`.
+    pub(crate) fn receive(&self, timeout: Option<Duration>) -> Received {
+        let batch = match timeout {
+            Some(timeout) => self.receiver.recv_timeout(timeout),
+            None => self.receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match batch {
+            Ok(entries) => Received::Entries(entries),
+            Err(RecvTimeoutError::Timeout) => Received::Waiting,
+            Err(RecvTimeoutError::Disconnected) => Received::Finished,
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
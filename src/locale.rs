@@ -0,0 +1,544 @@
+// imdialog/src/locale.rs
+//
+// Everything that depends on the user's language: built-in labels, collation, text direction,
+// and how numbers and dates are written. Like the C library, labels follow LC_MESSAGES, numbers
+// LC_NUMERIC and dates LC_TIME, each overridden by LC_ALL and falling back to LANG.
+
+use libc::{self, c_char};
+use std::env;
+use std::ffi::CString;
+use std::mem;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static RTL_LANGUAGES: [&'static str; 11] = [
+    "ar", "ckb", "dv", "fa", "he", "iw", "ps", "sd", "ug", "ur", "yi",
+];
+
+// Languages that write 1.5 as 1,5.
+static DECIMAL_COMMA_LANGUAGES: [&'static str; 5] = ["de", "es", "fr", "it", "pt"];
+
+// Languages that put a space before the percent sign.
+static SPACED_PERCENT_LANGUAGES: [&'static str; 2] = ["de", "fr"];
+
+// What goes between each group of three digits; languages not listed don't group them.
+static THOUSANDS_SEPARATORS: [(&'static str, &'static str); 6] = [
+    ("de", "."),
+    ("en", ","),
+    ("es", "."),
+    ("fr", "\u{202f}"),
+    ("it", "."),
+    ("pt", "."),
+];
+
+// Languages that write the day first, with what goes between the day, month and year. American
+// English puts the month first, and everything else gets ISO 8601.
+static DAY_FIRST_LANGUAGES: [(&'static str, char); 8] = [
+    ("ar", '/'),
+    ("de", '.'),
+    ("en", '/'),
+    ("es", '/'),
+    ("fr", '/'),
+    ("he", '.'),
+    ("it", '/'),
+    ("pt", '/'),
+];
+
+type Translations = &'static [(&'static str, &'static str)];
+
+static TRANSLATIONS: [(&'static str, Translations); 7] = [
+    ("ar", &[
+        ("OK", "موافق"),
+        ("Cancel", "إلغاء"),
+        ("Back", "رجوع"),
+        ("Yes", "نعم"),
+        ("No", "لا"),
+        ("Recent", "الأخيرة"),
+        ("Up one level", "المستوى الأعلى"),
+        ("Bookmark this directory", "إضافة هذا المجلد إلى الإشارات المرجعية"),
+        ("Location", "الموقع"),
+        ("New folder...", "مجلد جديد..."),
+        ("Create", "إنشاء"),
+        ("Loading...", "جارٍ التحميل..."),
+        ("Item {} of {}", "العنصر {} من {}"),
+        ("Discard and exit?", "تجاهل التغييرات والخروج؟"),
+        ("Search", "بحث"),
+        ("Type here to try the layout", "اكتب هنا لتجربة التخطيط"),
+        ("Devices", "الأجهزة"),
+        ("{} free", "{} متاحة"),
+        ("Places", "الأماكن"),
+        ("Home", "المنزل"),
+        ("Desktop", "سطح المكتب"),
+        ("Documents", "المستندات"),
+        ("Downloads", "التنزيلات"),
+        ("Music", "الموسيقى"),
+        ("Pictures", "الصور"),
+        ("Videos", "الفيديوهات"),
+        ("Overwrite {}?", "استبدال {}؟"),
+        ("Don't ask again", "لا تسأل مرة أخرى"),
+        ("Username", "اسم المستخدم"),
+        ("Password", "كلمة المرور"),
+        ("Show password", "إظهار كلمة المرور"),
+        ("Hide password", "إخفاء كلمة المرور"),
+        ("Confirm password", "تأكيد كلمة المرور"),
+        ("The passwords don't match", "كلمتا المرور غير متطابقتين"),
+        ("Weak", "ضعيفة"),
+        ("Fair", "متوسطة"),
+        ("Strong", "قوية"),
+        ("Too small: {} is under {}", "صغير جدًا: {} أقل من {}"),
+        ("Too large: {} is over {}", "كبير جدًا: {} أكثر من {}"),
+        ("You don't have permission to read this file", "ليس لديك إذن بقراءة هذا الملف"),
+        ("You don't have permission to write to this file", "ليس لديك إذن بالكتابة في هذا الملف"),
+        ("1 byte", "بايت واحد"),
+        ("{} bytes", "{} بايت"),
+    ]),
+    ("de", &[
+        ("Cancel", "Abbrechen"),
+        ("Back", "Zurück"),
+        ("Yes", "Ja"),
+        ("No", "Nein"),
+        ("Recent", "Zuletzt verwendet"),
+        ("Up one level", "Eine Ebene höher"),
+        ("Bookmark this directory", "Lesezeichen für dieses Verzeichnis"),
+        ("Location", "Ort"),
+        ("New folder...", "Neuer Ordner..."),
+        ("Create", "Erstellen"),
+        ("Loading...", "Wird geladen..."),
+        ("Item {} of {}", "Eintrag {} von {}"),
+        ("Discard and exit?", "Verwerfen und beenden?"),
+        ("Search", "Suche"),
+        ("Type here to try the layout", "Hier tippen, um das Layout auszuprobieren"),
+        ("Devices", "Geräte"),
+        ("{} free", "{} frei"),
+        ("Places", "Orte"),
+        ("Home", "Persönlicher Ordner"),
+        ("Desktop", "Schreibtisch"),
+        ("Documents", "Dokumente"),
+        ("Music", "Musik"),
+        ("Pictures", "Bilder"),
+        ("Overwrite {}?", "{} überschreiben?"),
+        ("Don't ask again", "Nicht mehr fragen"),
+        ("Username", "Benutzername"),
+        ("Password", "Passwort"),
+        ("Show password", "Passwort anzeigen"),
+        ("Hide password", "Passwort verbergen"),
+        ("Confirm password", "Passwort bestätigen"),
+        ("The passwords don't match", "Die Passwörter stimmen nicht überein"),
+        ("Weak", "Schwach"),
+        ("Fair", "Mittel"),
+        ("Strong", "Stark"),
+        ("Too small: {} is under {}", "Zu klein: {} ist kleiner als {}"),
+        ("Too large: {} is over {}", "Zu groß: {} ist größer als {}"),
+        ("You don't have permission to read this file", "Sie dürfen diese Datei nicht lesen"),
+        ("You don't have permission to write to this file",
+         "Sie dürfen diese Datei nicht schreiben"),
+        ("1 byte", "1 Byte"),
+        ("{} bytes", "{} Bytes"),
+    ]),
+    ("es", &[
+        ("OK", "Aceptar"),
+        ("Cancel", "Cancelar"),
+        ("Back", "Atrás"),
+        ("Yes", "Sí"),
+        ("Recent", "Recientes"),
+        ("Up one level", "Subir un nivel"),
+        ("Bookmark this directory", "Añadir este directorio a marcadores"),
+        ("Location", "Ubicación"),
+        ("New folder...", "Nueva carpeta..."),
+        ("Create", "Crear"),
+        ("Loading...", "Cargando..."),
+        ("Item {} of {}", "Elemento {} de {}"),
+        ("Discard and exit?", "¿Descartar y salir?"),
+        ("Search", "Buscar"),
+        ("Type here to try the layout", "Escribe aquí para probar la distribución"),
+        ("Devices", "Dispositivos"),
+        ("{} free", "{} libres"),
+        ("Places", "Lugares"),
+        ("Home", "Carpeta personal"),
+        ("Desktop", "Escritorio"),
+        ("Documents", "Documentos"),
+        ("Downloads", "Descargas"),
+        ("Music", "Música"),
+        ("Pictures", "Imágenes"),
+        ("Videos", "Vídeos"),
+        ("Overwrite {}?", "¿Sobrescribir {}?"),
+        ("Don't ask again", "No volver a preguntar"),
+        ("Username", "Nombre de usuario"),
+        ("Password", "Contraseña"),
+        ("Show password", "Mostrar contraseña"),
+        ("Hide password", "Ocultar contraseña"),
+        ("Confirm password", "Confirmar contraseña"),
+        ("The passwords don't match", "Las contraseñas no coinciden"),
+        ("Weak", "Débil"),
+        ("Fair", "Aceptable"),
+        ("Strong", "Fuerte"),
+        ("Too small: {} is under {}", "Demasiado pequeño: {} es menos de {}"),
+        ("Too large: {} is over {}", "Demasiado grande: {} es más de {}"),
+        ("You don't have permission to read this file", "No tiene permiso para leer este archivo"),
+        ("You don't have permission to write to this file",
+         "No tiene permiso para escribir en este archivo"),
+    ]),
+    ("fr", &[
+        ("Cancel", "Annuler"),
+        ("Back", "Retour"),
+        ("Yes", "Oui"),
+        ("No", "Non"),
+        ("Recent", "Récents"),
+        ("Up one level", "Remonter d'un niveau"),
+        ("Bookmark this directory", "Ajouter ce dossier aux signets"),
+        ("Location", "Emplacement"),
+        ("New folder...", "Nouveau dossier..."),
+        ("Create", "Créer"),
+        ("Loading...", "Chargement..."),
+        ("Item {} of {}", "Élément {} sur {}"),
+        ("Discard and exit?", "Abandonner et quitter ?"),
+        ("Search", "Rechercher"),
+        ("Type here to try the layout", "Tapez ici pour essayer la disposition"),
+        ("Devices", "Périphériques"),
+        ("{} free", "{} libres"),
+        ("Places", "Emplacements"),
+        ("Home", "Dossier personnel"),
+        ("Desktop", "Bureau"),
+        ("Downloads", "Téléchargements"),
+        ("Music", "Musique"),
+        ("Pictures", "Images"),
+        ("Videos", "Vidéos"),
+        ("Overwrite {}?", "Écraser {} ?"),
+        ("Don't ask again", "Ne plus demander"),
+        ("Username", "Nom d'utilisateur"),
+        ("Password", "Mot de passe"),
+        ("Show password", "Afficher le mot de passe"),
+        ("Hide password", "Masquer le mot de passe"),
+        ("Confirm password", "Confirmer le mot de passe"),
+        ("The passwords don't match", "Les mots de passe ne correspondent pas"),
+        ("Weak", "Faible"),
+        ("Fair", "Moyen"),
+        ("Strong", "Fort"),
+        ("Too small: {} is under {}", "Trop petit : {} est inférieur à {}"),
+        ("Too large: {} is over {}", "Trop grand : {} dépasse {}"),
+        ("You don't have permission to read this file",
+         "Vous n'avez pas la permission de lire ce fichier"),
+        ("You don't have permission to write to this file",
+         "Vous n'avez pas la permission d'écrire dans ce fichier"),
+        ("1 byte", "1 octet"),
+        ("{} bytes", "{} octets"),
+    ]),
+    ("he", &[
+        ("OK", "אישור"),
+        ("Cancel", "ביטול"),
+        ("Back", "חזרה"),
+        ("Yes", "כן"),
+        ("No", "לא"),
+        ("Recent", "אחרונים"),
+        ("Up one level", "רמה אחת למעלה"),
+        ("Bookmark this directory", "הוספת תיקייה זו לסימניות"),
+        ("Location", "מיקום"),
+        ("New folder...", "תיקייה חדשה..."),
+        ("Create", "יצירה"),
+        ("Loading...", "בטעינה..."),
+        ("Item {} of {}", "פריט {} מתוך {}"),
+        ("Discard and exit?", "לבטל ולצאת?"),
+        ("Search", "חיפוש"),
+        ("Type here to try the layout", "הקלידו כאן כדי לנסות את הפריסה"),
+        ("Devices", "התקנים"),
+        ("{} free", "{} פנויים"),
+        ("Places", "מקומות"),
+        ("Home", "בית"),
+        ("Desktop", "שולחן העבודה"),
+        ("Documents", "מסמכים"),
+        ("Downloads", "הורדות"),
+        ("Music", "מוזיקה"),
+        ("Pictures", "תמונות"),
+        ("Videos", "סרטונים"),
+        ("Overwrite {}?", "לשכתב את {}?"),
+        ("Don't ask again", "אל תשאל שוב"),
+        ("Username", "שם משתמש"),
+        ("Password", "סיסמה"),
+        ("Show password", "הצגת הסיסמה"),
+        ("Hide password", "הסתרת הסיסמה"),
+        ("Confirm password", "אימות סיסמה"),
+        ("The passwords don't match", "הסיסמאות אינן תואמות"),
+        ("Weak", "חלשה"),
+        ("Fair", "בינונית"),
+        ("Strong", "חזקה"),
+        ("Too small: {} is under {}", "קטן מדי: {} קטן מ־{}"),
+        ("Too large: {} is over {}", "גדול מדי: {} גדול מ־{}"),
+        ("You don't have permission to read this file", "אין לך הרשאה לקרוא את הקובץ הזה"),
+        ("You don't have permission to write to this file", "אין לך הרשאה לכתוב לקובץ הזה"),
+        ("1 byte", "בית אחד"),
+        ("{} bytes", "{} בתים"),
+    ]),
+    ("it", &[
+        ("Cancel", "Annulla"),
+        ("Back", "Indietro"),
+        ("Yes", "Sì"),
+        ("Recent", "Recenti"),
+        ("Up one level", "Livello superiore"),
+        ("Bookmark this directory", "Aggiungi questa cartella ai segnalibri"),
+        ("Location", "Posizione"),
+        ("New folder...", "Nuova cartella..."),
+        ("Create", "Crea"),
+        ("Loading...", "Caricamento..."),
+        ("Item {} of {}", "Elemento {} di {}"),
+        ("Discard and exit?", "Scartare e uscire?"),
+        ("Search", "Cerca"),
+        ("Type here to try the layout", "Scrivi qui per provare il layout"),
+        ("Devices", "Dispositivi"),
+        ("{} free", "{} liberi"),
+        ("Places", "Risorse"),
+        ("Home", "Cartella personale"),
+        ("Desktop", "Scrivania"),
+        ("Documents", "Documenti"),
+        ("Downloads", "Scaricati"),
+        ("Music", "Musica"),
+        ("Pictures", "Immagini"),
+        ("Overwrite {}?", "Sovrascrivere {}?"),
+        ("Don't ask again", "Non chiedere più"),
+        ("Username", "Nome utente"),
+        ("Show password", "Mostra password"),
+        ("Hide password", "Nascondi password"),
+        ("Confirm password", "Conferma password"),
+        ("The passwords don't match", "Le password non corrispondono"),
+        ("Weak", "Debole"),
+        ("Fair", "Discreta"),
+        ("Strong", "Forte"),
+        ("Too small: {} is under {}", "Troppo piccolo: {} è meno di {}"),
+        ("Too large: {} is over {}", "Troppo grande: {} è più di {}"),
+        ("You don't have permission to read this file",
+         "Non hai il permesso di leggere questo file"),
+        ("You don't have permission to write to this file",
+         "Non hai il permesso di scrivere in questo file"),
+        ("{} bytes", "{} byte"),
+    ]),
+    ("pt", &[
+        ("Cancel", "Cancelar"),
+        ("Back", "Voltar"),
+        ("Yes", "Sim"),
+        ("No", "Não"),
+        ("Recent", "Recentes"),
+        ("Up one level", "Subir um nível"),
+        ("Bookmark this directory", "Adicionar este diretório aos favoritos"),
+        ("Location", "Local"),
+        ("New folder...", "Nova pasta..."),
+        ("Create", "Criar"),
+        ("Loading...", "Carregando..."),
+        ("Item {} of {}", "Item {} de {}"),
+        ("Discard and exit?", "Descartar e sair?"),
+        ("Search", "Pesquisar"),
+        ("Type here to try the layout", "Digite aqui para experimentar o layout"),
+        ("Devices", "Dispositivos"),
+        ("{} free", "{} livres"),
+        ("Places", "Locais"),
+        ("Home", "Pasta pessoal"),
+        ("Desktop", "Área de trabalho"),
+        ("Documents", "Documentos"),
+        ("Music", "Música"),
+        ("Pictures", "Imagens"),
+        ("Videos", "Vídeos"),
+        ("Overwrite {}?", "Substituir {}?"),
+        ("Don't ask again", "Não perguntar novamente"),
+        ("Username", "Nome de usuário"),
+        ("Password", "Senha"),
+        ("Show password", "Mostrar senha"),
+        ("Hide password", "Ocultar senha"),
+        ("Confirm password", "Confirmar senha"),
+        ("The passwords don't match", "As senhas não coincidem"),
+        ("Weak", "Fraca"),
+        ("Fair", "Razoável"),
+        ("Strong", "Forte"),
+        ("Too small: {} is under {}", "Muito pequeno: {} é menos de {}"),
+        ("Too large: {} is over {}", "Muito grande: {} é mais de {}"),
+        ("You don't have permission to read this file",
+         "Você não tem permissão para ler este arquivo"),
+        ("You don't have permission to write to this file",
+         "Você não tem permissão para gravar neste arquivo"),
+    ]),
+];
+
+// The language and territory of a locale name like `pt_BR.UTF-8`, lowercased.
+#[derive(Clone)]
+struct Region {
+    language: String,
+    territory: String,
+}
+
+impl Region {
+    fn new(name: &str) -> Region {
+        let mut parts = name.split(|c| c == '.' || c == '@').next().unwrap().split(|c| {
+            c == '_' || c == '-'
+        });
+        Region {
+            language: parts.next().unwrap().to_lowercase(),
+            territory: parts.next().unwrap_or("").to_lowercase(),
+        }
+    }
+
+    fn from_environment(category: &str) -> Region {
+        Region::new(&environment_locale(category))
+    }
+}
+
+fn environment_locale(category: &str) -> String {
+    ["LC_ALL", category, "LANG"].iter().filter_map(|name| env::var(name).ok())
+                                       .find(|value| !value.is_empty())
+                                       .unwrap_or("C".to_string())
+}
+
+#[derive(Clone)]
+pub struct Locale {
+    // As it would appear in `LANG`, like `de_DE.UTF-8`, or `None` if it came from the environment.
+    name: Option<String>,
+    language: String,
+    rtl: bool,
+    numeric: Region,
+    time: Region,
+}
+
+impl Locale {
+    pub fn from_environment() -> Locale {
+        Locale {
+            name: None,
+            numeric: Region::from_environment("LC_NUMERIC"),
+            time: Region::from_environment("LC_TIME"),
+            ..Locale::new(&environment_locale("LC_MESSAGES"))
+        }
+    }
+
+    pub fn new(name: &str) -> Locale {
+        let region = Region::new(name);
+        Locale {
+            name: Some(name.to_string()),
+            rtl: RTL_LANGUAGES.contains(&&region.language[..]),
+            language: region.language.clone(),
+            numeric: region.clone(),
+            time: region,
+        }
+    }
+
+    // Writes numbers and dates as `name` does, whatever language the labels are in.
+    pub fn set_formats(&mut self, name: &str) {
+        self.numeric = Region::new(name);
+        self.time = Region::new(name)
+    }
+
+    pub fn rtl(&self) -> bool {
+        self.rtl
+    }
+
+    pub fn force_rtl(&mut self) {
+        self.rtl = true
+    }
+
+    // Labels without a translation come back unchanged.
+    pub fn translate(&self, text: &'static str) -> &'static str {
+        TRANSLATIONS.iter().find(|&&(language, _)| language == self.language)
+                           .and_then(|&(_, translations)| {
+                               translations.iter().find(|&&(english, _)| english == text)
+                           })
+                           .map(|&(_, translation)| translation)
+                           .unwrap_or(text)
+    }
+
+    pub fn format_decimal(&self, value: f64, places: usize) -> String {
+        let formatted = format!("{:.*}", places, value);
+        if DECIMAL_COMMA_LANGUAGES.contains(&&self.numeric.language[..]) {
+            formatted.replace('.', ",")
+        } else {
+            formatted
+        }
+    }
+
+    pub fn format_integer(&self, value: u64) -> String {
+        let digits = value.to_string();
+        let separator = match THOUSANDS_SEPARATORS.iter().find(|&&(language, _)| {
+            language == self.numeric.language
+        }) {
+            Some(&(_, separator)) if digits.len() > 4 => separator,
+            // Four-digit numbers read better without a separator.
+            _ => return digits,
+        };
+        let mut formatted = String::new();
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index) % 3 == 0 {
+                formatted.push_str(separator)
+            }
+            formatted.push(digit)
+        }
+        formatted
+    }
+
+    pub fn format_percent(&self, percent: u32) -> String {
+        if SPACED_PERCENT_LANGUAGES.contains(&&self.numeric.language[..]) {
+            format!("{}\u{a0}%", percent)
+        } else {
+            format!("{}%", percent)
+        }
+    }
+
+    // A date and time to the minute, in local time.
+    pub fn format_date_time(&self, time: SystemTime) -> String {
+        let seconds = match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as libc::time_t,
+            Err(_) => return String::new(),
+        };
+        let tm = unsafe {
+            let mut tm = mem::zeroed();
+            if libc::localtime_r(&seconds, &mut tm).is_null() {
+                return String::new()
+            }
+            tm
+        };
+        let (year, month, day) = (tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday);
+        if self.time.language == "en" && (self.time.territory == "us" ||
+                                          self.time.territory.is_empty()) {
+            let hour = match tm.tm_hour % 12 { 0 => 12, hour => hour };
+            let half = if tm.tm_hour < 12 { "AM" } else { "PM" };
+            return format!("{}/{}/{} {}:{:02} {}", month, day, year, hour, tm.tm_min, half)
+        }
+        match DAY_FIRST_LANGUAGES.iter().find(|&&(language, _)| language == self.time.language) {
+            Some(&(_, separator)) => {
+                format!("{:02}{}{:02}{}{} {:02}:{:02}",
+                        day,
+                        separator,
+                        month,
+                        separator,
+                        year,
+                        tm.tm_hour,
+                        tm.tm_min)
+            }
+            // As in `ls -l --time-style=long-iso`.
+            None => {
+                format!("{:04}-{:02}-{:02} {:02}:{:02}",
+                        year,
+                        month,
+                        day,
+                        tm.tm_hour,
+                        tm.tm_min)
+            }
+        }
+    }
+
+    // Picks up LC_COLLATE for this locale. `setlocale` wants the exact name of an installed
+    // locale, so a bare `de_DE` is retried as `de_DE.UTF-8`, and failing that the environment's
+    // collation is used.
+    pub fn set_collation(&self) {
+        let mut names = vec![];
+        if let Some(ref name) = self.name {
+            names.push(name.clone());
+            if !name.contains('.') {
+                names.push(format!("{}.UTF-8", name))
+            }
+        }
+        names.push(String::new());
+        for name in names {
+            let name = match CString::new(name) {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            unsafe {
+                if !libc::setlocale(libc::LC_COLLATE, name.as_ptr() as *const c_char).is_null() {
+                    return
+                }
+            }
+        }
+    }
+}
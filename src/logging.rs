@@ -0,0 +1,100 @@
+// imdialog/src/logging.rs
+//
+// Debug logging for when stderr can't be seen, as on a kiosk that has the console to itself. The
+// level comes from `--verbose`, given up to three times, or from `IMDIALOG_LOG` as one of `error`,
+// `warn`, `info`, `debug` or `trace`; `--log-file` sends the lines to a file instead of stderr.
+//
+// Lines are written straight to a file descriptor, so they can come from any thread.
+//
+// This is only for diagnostics. Anything the user needs to know about, like a setting that couldn't
+// be saved, is printed as a `warning:` line on stderr whatever the level.
+
+use libc::{self, c_int, c_void};
+use std::env;
+use std::fmt::Arguments;
+use std::fs::OpenOptions;
+use std::os::unix::io::IntoRawFd;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+static LEVELS: [(&'static str, Level); 6] = [
+    ("off", Level::Off),
+    ("error", Level::Error),
+    ("warn", Level::Warn),
+    ("info", Level::Info),
+    ("debug", Level::Debug),
+    ("trace", Level::Trace),
+];
+
+static LEVEL: AtomicUsize = AtomicUsize::new(Level::Off as usize);
+static FD: AtomicUsize = AtomicUsize::new(2);
+
+macro_rules! log {
+    ($level:expr, $($argument:tt)*) => {
+        if ::logging::enabled($level) {
+            ::logging::write($level, format_args!($($argument)*))
+        }
+    }
+}
+macro_rules! error { ($($argument:tt)*) => { log!(::logging::Level::Error, $($argument)*) } }
+macro_rules! warn { ($($argument:tt)*) => { log!(::logging::Level::Warn, $($argument)*) } }
+macro_rules! info { ($($argument:tt)*) => { log!(::logging::Level::Info, $($argument)*) } }
+macro_rules! debug { ($($argument:tt)*) => { log!(::logging::Level::Debug, $($argument)*) } }
+macro_rules! trace { ($($argument:tt)*) => { log!(::logging::Level::Trace, $($argument)*) } }
+
+// `verbosity` is how many times `--verbose` was given. A log file with nothing else asked for
+// gets `info`, since it was surely wanted for something.
+pub fn init(verbosity: u64, path: Option<&Path>) -> Result<(), String> {
+    let level = match (verbosity, env::var("IMDIALOG_LOG")) {
+        (0, Ok(name)) => {
+            match LEVELS.iter().find(|&&(level_name, _)| level_name == name.trim()) {
+                Some(&(_, level)) => level,
+                None => return Err(format!("`IMDIALOG_LOG` can't be `{}`", name)),
+            }
+        }
+        (0, Err(_)) if path.is_some() => Level::Info,
+        (0, Err(_)) => Level::Off,
+        (1, _) => Level::Info,
+        (2, _) => Level::Debug,
+        _ => Level::Trace,
+    };
+    if let Some(path) = path {
+        let file = OpenOptions::new().append(true).create(true).open(path).map_err(|error| {
+            format!("couldn't open the log file `{}`: {}", path.display(), error)
+        })?;
+        FD.store(file.into_raw_fd() as usize, Ordering::SeqCst)
+    }
+    LEVEL.store(level as usize, Ordering::SeqCst);
+    Ok(())
+}
+
+pub fn enabled(level: Level) -> bool {
+    level != Level::Off && level as usize <= LEVEL.load(Ordering::SeqCst)
+}
+
+pub fn write(level: Level, message: Arguments) {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let name = LEVELS.iter().find(|&&(_, named)| named == level).map_or("", |&(name, _)| name);
+    let line = format!("{}.{:03} {:5} {}\n",
+                       time.as_secs(),
+                       time.subsec_millis(),
+                       name.to_uppercase(),
+                       message);
+    // Nothing can be done about a log line that can't be written.
+    unsafe {
+        libc::write(FD.load(Ordering::SeqCst) as c_int,
+                    line.as_ptr() as *const c_void,
+                    line.len());
+    }
+}
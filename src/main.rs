@@ -3,6 +3,8 @@
 #![feature(link_args)]
 
 extern crate clap;
+#[cfg(feature = "server")]
+extern crate dbus;
 extern crate gl;
 extern crate libc;
 extern crate imgui_sys;
@@ -13,25 +15,104 @@ extern crate xdg;
 #[cfg(target_os="linux")]
 extern crate ioctl_rs as ioctl;
 
-use clap::{App, Arg, Values};
+use a11y::{FocusTracker, Role};
+use clap::{App, AppSettings, Arg, ArgMatches, Values};
+use collation::Collation;
+use commands::{Command, CommandChannel};
+use controller::Controllers;
+use filter::FileFilter;
+use form::{Field, Value};
+use icons::{Icon, IconAtlas};
 use imgui_sys as imgui;
-use imgui_sys::{ImDrawData, ImDrawIdx, ImDrawVert, ImFont, ImGuiSelectableFlags, ImGuiSetCond};
-use imgui_sys::{ImVec2, ImVec4};
+use imgui_sys::{ImDrawData, ImDrawIdx, ImDrawVert, ImFont, ImGuiCol, ImGuiSelectableFlags};
+use imgui_sys::ImGuiSetCond;
+use imgui_sys::{ImGuiTextEditCallbackData, ImU32, ImVec2, ImVec4, ImWchar};
 use libc::{c_char, c_int, c_uchar, c_uint, intptr_t};
+use listing::{DirectoryLoader, Received};
+use locale::Locale;
+use metrics::Metrics;
 use num::ToPrimitive;
-use sdl2::Sdl;
+use osk::{KeyPress, KeypadPress, NumericKeypad, OnScreenKeyboard};
+use output::{Fields, ResultWriter};
+use password::PasswordPolicy;
+use places::{Device, Place};
+use script::{Frame, Script};
+use sdl2::{EventPump, Sdl, VideoSubsystem};
+use sdl2::clipboard::ClipboardUtil;
 use sdl2::event::Event;
-use sdl2::keyboard::{self, Scancode};
-use sdl2::video::Window;
+use sdl2::keyboard::{self, Scancode, TextInputUtil};
+use sdl2::rect::Rect;
+use sdl2::video::{GLContext, Window};
+use sound::{Sound, Speaker};
+use spawn::SpawnOptions;
+use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
-use std::ffi::{CStr, CString};
+use std::collections::VecDeque;
+use std::env;
+use std::ffi::{CStr, CString, OsString};
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::mem;
 use std::os::raw::c_void;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::io::FromRawFd;
+use std::panic;
 use std::path::{Path, PathBuf};
 use std::process;
 use std::ptr;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use touch::Touch;
+use validate::Rule;
+use watch::{DirectoryWatch, MountWatch};
+use watchdog::Watchdog;
+use wizard::Wizard;
+
+#[macro_use]
+mod logging;
+
+mod a11y;
+mod bidi;
+mod bookmarks;
+mod collation;
+mod commands;
+mod compat;
+mod controller;
+mod doctor;
+mod filter;
+mod form;
+mod icons;
+mod inflate;
+mod json;
+mod keymap;
+mod listing;
+mod locale;
+mod metrics;
+mod osk;
+mod output;
+mod password;
+mod places;
+mod png;
+#[cfg(feature = "server")]
+mod recovery;
+#[cfg(target_os="linux")]
+mod sandbox;
+#[cfg(feature = "server")]
+mod server;
+mod script;
+mod signals;
+mod sound;
+mod spawn;
+mod state;
+mod touch;
+mod validate;
+mod watch;
+mod watchdog;
+mod whiptail;
+mod wizard;
+mod zenity;
 
 #[cfg(unix)]
 use xdg::BaseDirectories;
@@ -48,14 +129,93 @@ const FRAMEBUFFER_HEIGHT: u32 = 600;
 #[cfg(target_os="linux")]
 const K_XLATE: c_int = 0x01;
 
+// Rows in the file list before it's been fitted to the dialog, and the fewest it's fitted to.
 const LIST_HEIGHT: c_int = 5;
+const MIN_LIST_HEIGHT: c_int = 3;
+
+// Where the size and modification time columns of `--details` start, as fractions of the list
+// width.
+const SIZE_COLUMN_START: f32 = 0.55;
+const MODIFIED_COLUMN_START: f32 = 0.72;
+
+const SCROLL_LINES: f32 = 5.0;
+const MULTILINE_INPUT_LINES: f32 = 6.0;
+const INSTALL_LOG_LINES: f32 = 10.0;
+const TOUCH_STYLE_SCALE: f32 = 2.0;
+const ANIMATION_FRAME_INTERVAL: u32 = 16;
+const MIN_FRAME_TIME: f32 = 0.0001;
+const MAX_FRAME_TIME: f32 = 0.1;
+const DEFAULT_ANIMATION_TIME: f32 = 0.25;
+// How far a sliding dialog starts below where it settles, as a fraction of the screen height.
+const SLIDE_DISTANCE: f32 = 0.25;
+// How dark `--dim-schedule` and `--idle-dim` make the screen, from 0 for not at all to 1 for black.
+const DIM_OPACITY: f32 = 0.75;
+// `--pixel-shift` moves the dialog to the next of these offsets every few minutes, in steps of a
+// couple of pixels, so that no pixel shows the same thing for long.
+static PIXEL_SHIFT_ORBIT: [(c_int, c_int); 8] =
+    [(0, 0), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1)];
+const PIXEL_SHIFT_STEP: c_int = 2;
+const PIXEL_SHIFT_INTERVAL: u64 = 180;
+// How often the screen is checked for needing a shift or a scheduled dimming, in milliseconds.
+const BURN_IN_CHECK_INTERVAL: u32 = 1000;
+const DEFAULT_GPU_TIMEOUT: u64 = 10;
+const GPU_HANG_EXIT_CODE: c_int = 124;
+// What dialog exits with when Escape is pressed, and for bad arguments.
+const DEFAULT_ESC_CODE: c_int = 255;
+const ARGUMENT_ERROR_EXIT_CODE: c_int = 255;
+// What `--back-button` exits with, as dialog's extra button does.
+const BACK_EXIT_CODE: c_int = 3;
+const COMMAND_POLL_INTERVAL: u32 = 50;
+// What a headless dialog exits with when its `--script` runs out before the dialog closes.
+const SCRIPT_FINISHED_EXIT_CODE: c_int = 125;
+// Not in the sdl2 crate yet; SDL 2.0.5 added it.
+const SDL_WINDOW_ALWAYS_ON_TOP: u32 = 0x00008000;
+// How long to wait for a directory to be read before showing the list as it fills in.
+const DIRECTORY_LOAD_WAIT: u64 = 100;
+const SPINNER_FRAMES_PER_SECOND: f32 = 8.0;
 
 const MAX_TEXT_LENGTH: usize = 1024;
+// Bigger than any screen it might be shown on, and within what GL ES 2 drivers tend to allow.
+const MAX_BACKGROUND_SIZE: u32 = 4096;
+const MAX_RECENT_FILES: usize = 8;
+const MAX_INPUT_HISTORY: usize = 100;
+// Older lines drop out of an installbox's log.
+const MAX_INSTALL_LOG_LINES: usize = 1000;
 
 static FONT_FILENAME: &'static str = "Muli.ttf";
+static VERTEX_SHADER_FILENAME: &'static str = "imgui.vs.glsl";
+static FRAGMENT_SHADER_FILENAME: &'static str = "imgui.fs.glsl";
+static REMEMBERED_ANSWERS_FILENAME: &'static str = "remembered-answers";
+static RECENT_FILES_FILENAME: &'static str = "recent-files";
+static LAST_DIRECTORY_FILENAME: &'static str = "last-directory";
+static INPUT_HISTORY_FILENAME: &'static str = "input-history";
+static PANIC_LOG_FILENAME: &'static str = "panic.log";
+
+static DIALOG_USAGE: [(&'static str, &'static str); 14] = [
+    ("fselect", "PATH WIDTH HEIGHT"),
+    ("fsave", "PATH WIDTH HEIGHT"),
+    ("inputbox", "TEXT WIDTH HEIGHT [INIT]"),
+    ("passwordbox", "TEXT WIDTH HEIGHT [INIT]"),
+    ("menu", "TEXT WIDTH HEIGHT MENU-HEIGHT [TAG ITEM]..."),
+    ("form", "TEXT WIDTH HEIGHT [KEY LABEL]..."),
+    ("login", "TEXT WIDTH HEIGHT [USERNAME]"),
+    ("yesno", "TEXT WIDTH HEIGHT"),
+    ("msgbox", "TEXT WIDTH HEIGHT"),
+    ("gauge", "TEXT WIDTH HEIGHT [PERCENT]"),
+    ("installbox", "TEXT WIDTH HEIGHT [PERCENT]"),
+    ("numberbox", "TEXT WIDTH HEIGHT MIN MAX DEFAULT"),
+    ("pinbox", "TEXT DIGITS"),
+    ("kbdlayout", "TEXT WIDTH HEIGHT MENU-HEIGHT"),
+];
+static SPINNER: [&'static str; 4] = ["|", "/", "-", "\\"];
 static STANDARD_FONT_SIZE: f32 = (FRAMEBUFFER_HEIGHT as f32) / 16.66666;
 static LABEL_FONT_SIZE: f32 = (FRAMEBUFFER_HEIGHT as f32) / 25.0;
 
+// Latin-1 plus Hebrew, Arabic, and their presentation forms, for right-to-left mode.
+static RTL_GLYPH_RANGES: [ImWchar; 11] = [
+    0x0020, 0x00ff, 0x0590, 0x06ff, 0xfb1d, 0xfb4f, 0xfb50, 0xfdff, 0xfe70, 0xfeff, 0,
+];
+
 static ZERO_VERTEX: ImDrawVert = ImDrawVert {
     pos: ImVec2 { x: 0.0, y: 0.0, },
     uv: ImVec2 { x: 0.0, y: 0.0, },
@@ -74,7 +234,31 @@ static LABEL_COLOR: ImVec4 = ImVec4 {
     w: 1.0,
 };
 
+static ERROR_COLOR: ImVec4 = ImVec4 {
+    x: 1.0,
+    y: 0.4,
+    z: 0.4,
+    w: 1.0,
+};
+
+static FAIR_COLOR: ImVec4 = ImVec4 {
+    x: 1.0,
+    y: 0.8,
+    z: 0.3,
+    w: 1.0,
+};
+
+static GOOD_COLOR: ImVec4 = ImVec4 {
+    x: 0.4,
+    y: 0.8,
+    z: 0.4,
+    w: 1.0,
+};
+
 static mut RENDERER: *const Renderer = 0 as *const Renderer;
+static mut CLIPBOARD: *const ClipboardUtil = 0 as *const ClipboardUtil;
+static mut CLIPBOARD_TEXT: *mut c_char = 0 as *mut c_char;
+static mut TEXT_INPUT: *const TextInputUtil = 0 as *const TextInputUtil;
 
 static SCANCODES: [Scancode; 19] = [
     Scancode::Tab,
@@ -98,6 +282,10 @@ static SCANCODES: [Scancode; 19] = [
     Scancode::Z,
 ];
 
+fn duration_to_seconds(duration: Duration) -> f32 {
+    (duration.as_secs() as f32) + (duration.subsec_nanos() as f32) / 1_000_000_000.0
+}
+
 fn button_size() -> ImVec2 {
     ImVec2 {
         x: FRAMEBUFFER_WIDTH.to_pixels() * 0.8,
@@ -136,13 +324,7 @@ impl BaseDirectories {
 struct Shader(c_uint);
 
 fn get_data_file_path(filename: &str, base_directories: &BaseDirectories) -> PathBuf {
-    match base_directories.find_data_file(Path::new(filename)) {
-        Some(path) => return path,
-        None => {}
-    }
-
-    let path = PathBuf::from(filename);
-    if path.exists() {
+    if let Some(path) = find_data_file(filename, base_directories) {
         return path
     }
 
@@ -155,6 +337,23 @@ fn get_data_file_path(filename: &str, base_directories: &BaseDirectories) -> Pat
     process::exit(0);
 }
 
+fn find_data_file(filename: &str, base_directories: &BaseDirectories) -> Option<PathBuf> {
+    match base_directories.find_data_file(Path::new(filename)) {
+        Some(path) => {
+            debug!("found `{}` at `{}`", filename, path.display());
+            return Some(path)
+        }
+        None => {}
+    }
+
+    let path = PathBuf::from(filename);
+    if path.exists() {
+        debug!("found `{}` in the current directory", filename);
+        return Some(path)
+    }
+    None
+}
+
 impl Shader {
     pub fn new(filename: &str, kind: c_uint, base_directories: &BaseDirectories) -> Shader {
         let path = get_data_file_path(filename, base_directories);
@@ -172,93 +371,316 @@ impl Shader {
     }
 }
 
+// A yes-or-no question asked in a modal popup over the dialog, like whether to overwrite a file.
+// Dialogs hold one for as long as it's waiting for an answer.
+struct Confirmation {
+    question: String,
+    opened: bool,
+    // Given from the keyboard: Return is Yes and Escape is No.
+    answer: Option<bool>,
+}
+
+impl Confirmation {
+    fn new(question: String) -> Confirmation {
+        Confirmation {
+            question: question,
+            opened: false,
+            answer: None,
+        }
+    }
+}
+
+static MENU_HOTKEYS: &'static str = "123456789abcdefghijklmnopqrstuvwxyz";
+
 struct MenuItem {
     tag: String,
     item: String,
+    confirm: Option<String>,
+    // Chooses the item when pressed, from an `&` in the tag or given out by `--hotkeys`.
+    hotkey: Option<char>,
+    // A line between groups of items, from a tag of `---`.
+    separator: bool,
+    // Shown greyed out and can't be chosen, from `{disabled="true"}`.
+    disabled: bool,
+    icon: Option<Icon>,
 }
 
-struct FileDialogEntries {
-    entries: Vec<*const c_char>,
-    index: c_int,
+impl MenuItem {
+    fn choosable(&self) -> bool {
+        !self.separator && !self.disabled
+    }
+
+    // What the item's selectable says, leaving out its hotkey.
+    fn text(&self, options: &Options) -> &str {
+        if options.no_tags { &self.item } else { &self.tag }
+    }
+
+    // What the item's selectable says, with its hotkey in front if it has one.
+    fn label(&self, options: &Options) -> String {
+        let label = self.text(options);
+        match self.hotkey {
+            Some(hotkey) => format!("{}  {}", hotkey, label),
+            None => label.to_string(),
+        }
+    }
 }
 
-impl Drop for FileDialogEntries {
-    fn drop(&mut self) {
-        unsafe {
-            for &entry in &self.entries {
-                libc::free(entry as *mut _)
+// Takes the `&` out of a tag like `&Reboot`, returning the letter after it. `&&` is a plain `&`.
+fn parse_mnemonic(tag: &str) -> (String, Option<char>) {
+    let mut plain = String::new();
+    let mut mnemonic = None;
+    let mut characters = tag.chars();
+    while let Some(character) = characters.next() {
+        if character != '&' {
+            plain.push(character);
+            continue
+        }
+        match characters.next() {
+            Some('&') => plain.push('&'),
+            Some(character) => {
+                if mnemonic.is_none() {
+                    mnemonic = character.to_lowercase().next()
+                }
+                plain.push(character)
             }
+            None => plain.push('&'),
         }
     }
+    (plain, mnemonic)
 }
 
-impl FileDialogEntries {
-    fn new(path: &Path) -> FileDialogEntries {
-        let metadata = match fs::metadata(path) {
-            Ok(metadata) => metadata,
-            Err(_) => return FileDialogEntries::none(),
+fn parse_item_attributes(item: &str) -> (String, Vec<(String, String)>) {
+    let plain = (item.to_string(), vec![]);
+    if !item.ends_with("}") {
+        return plain
+    }
+    let start = match item.rfind(" {") {
+        Some(start) => start,
+        None => return plain,
+    };
+
+    let mut attributes = vec![];
+    let mut rest = item[(start + 2)..(item.len() - 1)].trim();
+    while !rest.is_empty() {
+        let equals = match rest.find("=\"") {
+            Some(equals) => equals,
+            None => return plain,
         };
-        if !metadata.is_dir() {
-            return FileDialogEntries::none()
+        let key = &rest[..equals];
+        if key.is_empty() || key.contains(" ") {
+            return plain
         }
-        let directory_entries = match fs::read_dir(path) {
-            Ok(entries) => entries,
-            Err(_) => return FileDialogEntries::none(),
+        let value_start = equals + 2;
+        let value_end = match rest[value_start..].find("\"") {
+            Some(length) => value_start + length,
+            None => return plain,
         };
+        attributes.push((key.to_string(), rest[value_start..value_end].to_string()));
+        rest = rest[(value_end + 1)..].trim();
+    }
+    (item[..start].to_string(), attributes)
+}
 
-        unsafe {
-            let mut entries = vec![];
-            for entry in directory_entries {
-                let entry = match entry {
-                    Ok(entry) => entry,
-                    Err(_) => continue,
-                };
-                let path = entry.path();
-                let filename = match path.file_name() {
-                    Some(filename) => filename,
-                    None => continue,
-                };
-                let mut string = match filename.to_str() {
-                    Some(string) => string.to_string(),
-                    None => continue,
-                };
-                if path.is_dir() {
-                    string.push_str("/")
-                }
-                let c_string = match CString::new(string) {
-                    Ok(c_string) => c_string,
-                    Err(_) => continue,
-                };
-                entries.push(libc::strdup(c_string.as_ptr()) as *const c_char)
-            }
+#[derive(Copy, Clone, PartialEq)]
+enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
 
-            entries.sort_by(|&a, &b| {
-                match libc::strcmp(a, b) {
-                    0 => Ordering::Equal,
-                    x if x < 0 => Ordering::Less,
-                    _ => Ordering::Greater,
-                }
-            });
+impl SortKey {
+    fn from_name(name: &str) -> Option<SortKey> {
+        match name {
+            "name" => Some(SortKey::Name),
+            "size" => Some(SortKey::Size),
+            "mtime" => Some(SortKey::Modified),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct EntryOrder {
+    key: SortKey,
+    collation: Collation,
+    dirs_first: bool,
+    ignore_case: bool,
+}
+
+impl EntryOrder {
+    // The largest and newest files come first; anything else that ties goes by name.
+    fn compare(&self, a_entry: &FileEntry, b_entry: &FileEntry) -> Ordering {
+        let (a, b) = (&a_entry.details, &b_entry.details);
+        if self.dirs_first && a.is_dir != b.is_dir {
+            return b.is_dir.cmp(&a.is_dir)
+        }
+        let ordering = match self.key {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Size => b.size.cmp(&a.size),
+            SortKey::Modified => b.modified.cmp(&a.modified),
+        };
+        // Without the slash after directories, which would otherwise sort `a/` after `a.txt`.
+        ordering.then_with(|| {
+            self.compare_names(a_entry.label.trim_end_matches('/'),
+                               b_entry.label.trim_end_matches('/'))
+        })
+    }
 
-            if path.parent().is_some() {
-                let c_string = libc::strdup(b"Up one level\0" as *const c_uchar as *const c_char);
-                entries.insert(0, c_string as *const c_char)
+    fn compare_names(&self, a: &str, b: &str) -> Ordering {
+        if self.ignore_case {
+            let ordering = self.collation.compare(&a.to_lowercase(), &b.to_lowercase());
+            if ordering != Ordering::Equal {
+                return ordering
             }
-            FileDialogEntries {
-                entries: entries,
-                index: 0,
+        }
+        self.collation.compare(a, b)
+    }
+}
+
+#[derive(Clone)]
+struct EntryDetails {
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+    // Where it points, if it's a symlink.
+    link: Option<PathBuf>,
+    // A symlink to something that doesn't exist.
+    broken: bool,
+}
+
+// `name` is the name on disk, which needn't be UTF-8. `label` is how it's shown, with replacement
+// characters where it isn't and a slash after directories.
+struct FileEntry {
+    name: OsString,
+    label: String,
+    details: EntryDetails,
+    // Why the filter rules it out, if it does.
+    reason: Option<String>,
+    // Whether it's ticked in `--fselect-multi` mode.
+    checked: bool,
+}
+
+struct FileDialogEntries {
+    entries: Vec<FileEntry>,
+    // 1 if "Up one level" is listed first, and 0 otherwise.
+    first_entry: usize,
+    index: c_int,
+}
+
+impl FileDialogEntries {
+    // Just "Up one level", if `up` is set. The rest come from a `DirectoryLoader`.
+    fn new(up: bool, locale: &Locale) -> FileDialogEntries {
+        let mut entries = vec![];
+        if up {
+            entries.push(FileEntry {
+                name: OsString::from(".."),
+                label: locale.translate("Up one level").to_string(),
+                details: EntryDetails {
+                    is_dir: true,
+                    size: 0,
+                    modified: None,
+                    link: None,
+                    broken: false,
+                },
+                reason: None,
+                checked: false,
+            })
+        }
+        FileDialogEntries {
+            first_entry: entries.len(),
+            entries: entries,
+            index: 0,
+        }
+    }
+
+    // Sorts newly read entries in with the rest, keeping the same one selected. The ones already
+    // listed are in order, so this is mostly a merge.
+    fn add(&mut self, entries: Vec<FileEntry>, order: &EntryOrder) {
+        let selection = self.entries.get(self.index as usize).map(|entry| entry.label.clone());
+        self.entries.extend(entries);
+        self.entries[self.first_entry..].sort_by(|a, b| order.compare(a, b));
+        if let Some(selection) = selection {
+            self.select(&selection);
+        }
+    }
+
+    // Searches from the end so that a file named like the "Up one level" entry still matches.
+    fn select(&mut self, name: &str) -> bool {
+        let position = self.entries.iter().rposition(|entry| entry.label == name);
+        match position {
+            Some(index) => {
+                self.index = index as c_int;
+                true
             }
+            None => false,
         }
     }
 
     fn none() -> FileDialogEntries {
         FileDialogEntries {
             entries: vec![],
+            first_entry: 0,
             index: 0,
         }
     }
 }
 
+fn buffer_text(buffer: &[u8]) -> String {
+    let length = buffer.iter().position(|&byte| byte == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..length]).into_owned()
+}
+
+// Like fs::canonicalize(), but a file that doesn't exist yet is fine as long as its directory does.
+fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    if let Ok(path) = fs::canonicalize(path) {
+        return Ok(path)
+    }
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut absolute = fs::canonicalize(directory)?;
+    absolute.extend(path.file_name());
+    Ok(absolute)
+}
+
+// Whether `path` is inside `root` once symlinks are resolved. Anything is, without a root.
+fn within_root(path: &Path, root: Option<&Path>) -> bool {
+    match root {
+        Some(root) => absolute_path(path).map(|path| path.starts_with(root)).unwrap_or(false),
+        None => true,
+    }
+}
+
+// Completes the last component of `typed` as far as the names in its directory agree, adding a
+// slash once it names a single directory.
+fn complete_path(directory: &Path, typed: &str, root: Option<&Path>) -> Option<String> {
+    let (head, prefix) = match typed.rfind('/') {
+        Some(slash) => (&typed[..(slash + 1)], &typed[(slash + 1)..]),
+        None => ("", typed),
+    };
+    if !within_root(&directory.join(head), root) {
+        return None
+    }
+    let entries = fs::read_dir(directory.join(head)).ok()?;
+    let candidates: Vec<(String, bool)> = entries.filter_map(|entry| {
+        let entry = entry.ok()?;
+        let name = entry.file_name().into_string().ok()?;
+        if name.starts_with(prefix) { Some((name, entry.path().is_dir())) } else { None }
+    }).collect();
+    let mut common = candidates.first()?.0.clone();
+    for &(ref name, _) in &candidates[1..] {
+        while !name.starts_with(&common[..]) {
+            common.pop();
+        }
+    }
+    let mut completed = format!("{}{}", head, common);
+    if candidates.len() == 1 && candidates[0].1 {
+        completed.push('/')
+    }
+    Some(completed)
+}
+
 #[derive(Copy, Clone, PartialEq)]
 enum SelectedFileType {
     File,
@@ -268,357 +690,4186 @@ enum SelectedFileType {
 struct FileDialog {
     path: PathBuf,
     entries: FileDialogEntries,
+    order: EntryOrder,
+    locale: Locale,
+    // Removable disks, kept up to date as they come and go.
+    devices: Vec<Device>,
+    mount_watch: Option<MountWatch>,
+    places: Vec<Place>,
+    bookmarks: Vec<PathBuf>,
+    recent_files: Vec<PathBuf>,
+    // A recent file or a typed path, chosen instead of the selected entry.
+    chosen_file: Option<PathBuf>,
+    location: Vec<u8>,
+    location_shown: String,
+    location_error: Option<String>,
+    // The name being typed for a new directory, while the user is making one.
+    new_directory: Option<Vec<u8>>,
+    new_directory_error: Option<String>,
+    focus_new_directory: bool,
+    scroll_to_selection: bool,
+    scroll: f32,
+    restored_scroll: Option<f32>,
+    filter: FileFilter,
+    // With `--root`, the canonical directory the dialog can't leave.
+    root: Option<PathBuf>,
+    follow_symlinks: bool,
+    show_hidden: bool,
+    // Still reading `path`, if it's `Some`.
+    loader: Option<DirectoryLoader>,
+    // An entry to select once it's been read.
+    pending_selection: Option<String>,
+    watch: Option<DirectoryWatch>,
+    // A fresh read of `path` after it changed, and what's come in so far. The old entries stay up
+    // until it's finished.
+    refresh: Option<(DirectoryLoader, Vec<FileEntry>)>,
+    // Whether typing goes to `search` rather than to a text field.
+    list_focused: bool,
+    search: String,
+    // Set by Return while the list has the focus, to act as if the selection were clicked.
+    activate_selection: bool,
+    // In `--fsave` mode, an existing file that was chosen, while asking whether to overwrite it.
+    pending_overwrite: Option<(PathBuf, Confirmation)>,
+    // The height asked for on the command line, in lines, and the rows that fit it.
+    height: u32,
+    list_rows: c_int,
 }
 
 impl FileDialog {
-    fn selected_path(&self) -> (PathBuf, SelectedFileType) {
-        unsafe {
-            let index = self.entries.index as usize;
-            let mut entry_string = CStr::from_ptr(self.entries.entries[index]).to_str()
-                                                                              .unwrap()
-                                                                              .to_string();
-            let mut path = self.path.clone();
-            let file_type = if entry_string.ends_with("/") {
-                entry_string.pop();
-                SelectedFileType::Directory
-            } else {
-                SelectedFileType::File
-            };
-            path.push(entry_string);
-            (path, file_type)
+    fn chosen_path(&self) -> PathBuf {
+        match self.chosen_file {
+            Some(ref path) => path.clone(),
+            // An empty listing leaves nothing selected, so the directory itself is chosen.
+            None => self.selected_path().map_or_else(|| self.path.clone(), |(path, _)| path),
         }
     }
-}
-
-struct InputDialog {
-    text: String,
-    data: Vec<u8>,
-}
 
-#[allow(dead_code)]
-struct MenuDialog {
-    text: String,
-    menu_height: u32,
-    items: Vec<MenuItem>,
-}
+    fn change_directory(&mut self, path: PathBuf) {
+        if !within_root(&path, self.root()) {
+            return
+        }
+        self.path = match self.root {
+            Some(_) => fs::canonicalize(&path).unwrap_or(path),
+            None => path,
+        };
+        self.entries = FileDialogEntries::new(self.has_parent(), &self.locale);
+        // Watched first, so nothing that changes while it's being read is missed.
+        self.watch = DirectoryWatch::new(&self.path);
+        self.refresh = None;
+        self.loader = Some(self.start_loader());
+        self.pending_selection = None;
+        self.search.clear();
+        // Most directories are read by then, which saves flashing up a half-empty list.
+        self.receive_entries(Some(Duration::from_millis(DIRECTORY_LOAD_WAIT)));
+        let mut location = self.path.display().to_string();
+        if !location.ends_with('/') {
+            location.push('/')
+        }
+        self.set_location(location)
+    }
 
-enum Subdialog {
-    File(FileDialog),
-    Input(InputDialog),
-    Menu(MenuDialog),
-}
+    // Goes into a listed directory. Symlinks are resolved with `--follow-symlinks`, and always when
+    // they lead back up, which would otherwise make paths like `a/b/b/b` without end.
+    fn enter_directory(&mut self, path: PathBuf) {
+        let is_link = fs::symlink_metadata(&path).map(|metadata| {
+            metadata.file_type().is_symlink()
+        }).unwrap_or(false);
+        if is_link {
+            if let (Ok(target), Ok(current)) = (fs::canonicalize(&path),
+                                                fs::canonicalize(&self.path)) {
+                if self.follow_symlinks || current.starts_with(&target) {
+                    return self.change_directory(target)
+                }
+            }
+        }
+        self.change_directory(path)
+    }
 
-fn usage(help_string: &[u8]) -> ! {
-    io::stdout().write_all(&help_string).unwrap();
-    io::stdout().write_all(b"\n").unwrap();
-    process::exit(0)
-}
+    fn refresh_devices_if_changed(&mut self) {
+        if self.mount_watch.as_ref().map_or(false, |watch| watch.changed()) {
+            self.devices = places::devices().into_iter().filter(|device| {
+                within_root(&device.path, self.root())
+            }).collect();
+        }
+    }
 
-#[allow(dead_code)]
-struct Dialog {
-    width: u32,
-    height: u32,
-    subdialog: Subdialog,
-}
+    fn root(&self) -> Option<&Path> {
+        self.root.as_ref().map(|root| root.as_path())
+    }
 
-impl Dialog {
-    fn new() -> Dialog {
-        let app = App::new("imdialog").version("0.1")
-                                      .author("Patrick Walton <pcwalton@mimiga.net>")
-                                      .about("Display dialogs using IMGUI")
-                                      .arg(Arg::with_name("fselect").long("fselect")
-                                                                    .takes_value(true)
-                                                                    .number_of_values(3))
-                                      .arg(Arg::with_name("inputbox").long("inputbox")
-                                                                     .takes_value(true)
-                                                                     .min_values(3)
-                                                                     .max_values(4))
-                                      .arg(Arg::with_name("menu").long("menu")
-                                                                 .takes_value(true)
-                                                                 .min_values(3));
+    // Whether "Up one level" goes anywhere.
+    fn has_parent(&self) -> bool {
+        self.path.parent().is_some() && self.root() != Some(self.path.as_path())
+    }
 
-        let mut help_string = vec![];
-        app.write_help(&mut help_string).unwrap();
-        let matches = app.get_matches();
+    fn start_loader(&self) -> DirectoryLoader {
+        let (directory, show_hidden) = (self.path.clone(), self.show_hidden);
+        let (filter, locale, root) = (self.filter.clone(), self.locale.clone(), self.root.clone());
+        DirectoryLoader::start(self.path.clone(), move |name, details| {
+            let label = name.to_string_lossy().into_owned();
+            if (!show_hidden && label.starts_with('.')) || !filter.shows(&label, details.is_dir) {
+                return None
+            }
+            // Symlinks that lead out of the root aren't listed at all.
+            let root = root.as_ref().map(|root| root.as_path());
+            if root.is_some() && !within_root(&directory.join(&name), root) {
+                return None
+            }
+            let reason = if details.broken {
+                Some("This link points to something that doesn't exist".to_string())
+            } else if details.is_dir {
+                None
+            } else {
+                filter.reason(&directory.join(&name), &locale)
+            };
+            Some(FileEntry {
+                label: if details.is_dir { format!("{}/", label) } else { label },
+                name: name,
+                details: details,
+                reason: reason,
+                checked: false,
+            })
+        })
+    }
 
-        if let Some(values) = matches.values_of("fselect") {
-            return Dialog::fselect(values)
+    // Re-reads the directory if files have come or gone, then swaps the new entries in, keeping
+    // the selection and the ticks.
+    fn refresh_if_changed(&mut self) {
+        if self.watch.as_ref().map_or(false, |watch| watch.changed()) {
+            self.refresh = Some((self.start_loader(), vec![]))
         }
-        if let Some(values) = matches.values_of("inputbox") {
-            return Dialog::inputbox(values)
+        let finished = match self.refresh {
+            Some((ref loader, ref mut entries)) => {
+                loop {
+                    match loader.receive(Some(Duration::from_secs(0))) {
+                        Received::Entries(batch) => entries.extend(batch),
+                        Received::Waiting => break false,
+                        Received::Finished => break true,
+                    }
+                }
+            }
+            None => false,
+        };
+        if !finished {
+            return
         }
-        if let Some(values) = matches.values_of("menu") {
-            if let Some(menu) = Dialog::menu(values) {
-                return menu
+        let (_, mut entries) = self.refresh.take().unwrap();
+        let checked: Vec<String> = self.entries.entries.iter().filter(|entry| entry.checked)
+                                                              .map(|entry| entry.label.clone())
+                                                              .collect();
+        for entry in &mut entries {
+            entry.checked = checked.contains(&entry.label)
+        }
+        let selection = self.entries.entries.get(self.entries.index as usize).map(|entry| {
+            entry.label.clone()
+        });
+        self.entries = FileDialogEntries::new(self.has_parent(), &self.locale);
+        self.entries.add(entries, &self.order);
+        // Anything the first read hadn't got to yet is in this one.
+        self.loader = None;
+        if let Some(name) = self.pending_selection.take().or(selection) {
+            self.entries.select(&name);
+        }
+    }
+
+    // Takes whatever has been read so far, waiting up to `timeout` for the rest, or for as long as
+    // it takes if `timeout` is `None`.
+    fn receive_entries(&mut self, timeout: Option<Duration>) {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut received = false;
+        loop {
+            let remaining = deadline.map(|deadline| {
+                let now = Instant::now();
+                if deadline > now { deadline - now } else { Duration::from_secs(0) }
+            });
+            let batch = match self.loader {
+                Some(ref loader) => loader.receive(remaining),
+                None => break,
+            };
+            match batch {
+                Received::Entries(entries) => {
+                    self.entries.add(entries, &self.order);
+                    received = true
+                }
+                Received::Waiting => break,
+                Received::Finished => self.loader = None,
             }
         }
+        if !received {
+            return
+        }
+        if let Some(name) = self.pending_selection.take() {
+            self.select(&name)
+        }
+        self.find_search()
+    }
 
-        usage(&help_string)
+    // Selects the entry called `name`, now or once it's been read.
+    fn select(&mut self, name: &str) {
+        if self.entries.select(name) {
+            self.scroll_to_selection = true
+        } else if self.loader.is_some() {
+            self.pending_selection = Some(name.to_string())
+        }
     }
 
-    fn fselect(mut values: Values) -> Dialog {
-        let path = fs::canonicalize(Path::new(values.next().unwrap())).unwrap();
-        let width: u32 = values.next().unwrap().parse().unwrap();
-        let height: u32 = values.next().unwrap().parse().unwrap();
-        let entries = FileDialogEntries::new(&path);
-        Dialog {
-            width: width,
-            height: height,
-            subdialog: Subdialog::File(FileDialog {
-                path: path,
-                entries: entries,
-            }),
+    fn location_text(&self) -> String {
+        buffer_text(&self.location)
+    }
+
+    fn set_location(&mut self, text: String) {
+        for byte in &mut self.location {
+            *byte = 0
         }
+        let length = text.len().min(self.location.len() - 1);
+        self.location[..length].copy_from_slice(&text.as_bytes()[..length]);
+        self.location_shown = text;
+        self.location_error = None
     }
 
-    fn inputbox(mut values: Values) -> Dialog {
-        let text = values.next().unwrap();
-        let width: u32 = values.next().unwrap().parse().unwrap();
-        let height: u32 = values.next().unwrap().parse().unwrap();
+    fn location_edited(&self) -> bool {
+        self.location_text() != self.location_shown
+    }
 
-        let mut data = vec![];
-        if let Some(initial_data) = values.next() {
-            io::copy(&mut CString::new(initial_data).unwrap().as_bytes_with_nul(),
-                     &mut data).unwrap();
+    // Makes the directory named in the new directory field, then shows and selects it.
+    fn create_directory(&mut self) {
+        let name = match self.new_directory {
+            Some(ref buffer) => buffer_text(buffer).trim().to_string(),
+            None => return,
+        };
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            self.new_directory_error = Some(format!("`{}` isn't a valid folder name", name));
+            return
+        }
+        if let Err(error) = fs::create_dir(self.path.join(&name)) {
+            self.new_directory_error = Some(error.to_string());
+            return
         }
-        data.resize(MAX_TEXT_LENGTH - 1, 0);
-        data.push(0);
+        self.new_directory = None;
+        self.new_directory_error = None;
+        if name.starts_with('.') {
+            self.show_hidden = true
+        }
+        let path = self.path.clone();
+        self.change_directory(path);
+        self.select(&format!("{}/", name))
+    }
 
-        Dialog {
-            width: width,
-            height: height,
-            subdialog: Subdialog::Input(InputDialog {
-                text: text.to_string(),
-                data: data,
-            }),
+    // Goes to a typed directory, or chooses a typed file, which needn't exist yet. Paths are
+    // relative to the current directory.
+    fn enter_location(&mut self) -> Option<c_int> {
+        let path = match absolute_path(&self.path.join(self.location_text())) {
+            Ok(path) => path,
+            Err(error) => {
+                self.location_error = Some(error.to_string());
+                return None
+            }
+        };
+        if !within_root(&path, self.root()) {
+            self.location_error = Some(format!("`{}` is outside `{}`",
+                                               path.display(),
+                                               self.root.as_ref().unwrap().display()));
+            return None
+        }
+        if path.is_dir() {
+            self.change_directory(path);
+            return None
         }
+        if path.exists() {
+            if let Some(reason) = self.filter.reason(&path, &self.locale) {
+                self.location_error = Some(reason);
+                return None
+            }
+        }
+        self.chosen_file = Some(path);
+        Some(0)
     }
 
-    fn menu(mut values: Values) -> Option<Dialog> {
-        let text = values.next().unwrap();
-        let width: u32 = values.next().unwrap().parse().unwrap();
-        let height: u32 = values.next().unwrap().parse().unwrap();
-        let menu_height: u32 = values.next().unwrap().parse().unwrap();
+    // Re-reads the directory, keeping the selection if it's still listed.
+    fn toggle_hidden_files(&mut self) {
+        let selection = self.entries.entries.get(self.entries.index as usize).map(|entry| {
+            entry.label.clone()
+        });
+        self.show_hidden = !self.show_hidden;
+        let path = self.path.clone();
+        self.change_directory(path);
+        if let Some(selection) = selection {
+            self.select(&selection)
+        }
+    }
 
-        let mut items = vec![];
-        loop {
-            let tag = match values.next() {
-                Some(tag) => tag,
-                None => break,
-            };
-            let item = match values.next() {
-                Some(item) => item,
-                None => return None,
-            };
-            items.push(MenuItem {
-                tag: tag.to_string(),
-                item: item.to_string(),
-            })
+    fn type_ahead(&mut self, text: &str) {
+        self.search.push_str(text);
+        self.find_search()
+    }
+
+    fn erase_search(&mut self) {
+        self.search.pop();
+        self.find_search()
+    }
+
+    // Selects the first entry starting with the search string, ignoring case. The selection stays
+    // put if nothing matches.
+    fn find_search(&mut self) {
+        if self.search.is_empty() {
+            return
+        }
+        let search = self.search.to_lowercase();
+        let first_entry = self.entries.first_entry;
+        let position = (first_entry..self.entries.entries.len()).find(|&index| {
+            self.entries.entries[index].label.to_lowercase().starts_with(&search)
+        });
+        if let Some(index) = position {
+            self.entries.index = index as c_int;
+            self.scroll_to_selection = true
         }
+    }
 
-        Some(Dialog {
-            width: width,
-            height: height,
-            subdialog: Subdialog::Menu(MenuDialog {
-                text: text.to_string(),
-                menu_height: menu_height,
-                items: items,
-            })
+    fn reason(&self, index: usize) -> Option<&str> {
+        self.entries.entries.get(index).and_then(|entry| entry.reason.as_ref()).map(|reason| {
+            &reason[..]
         })
     }
-}
 
-#[allow(dead_code)]
-struct Renderer {
-    standard_font: *mut ImFont,
-    label_font: *mut ImFont,
-    texture: c_uint,
-    vertex_shader: Shader,
-    fragment_shader: Shader,
-    program: c_uint,
-    u_window_size: c_int,
-    u_texture: c_int,
-    a_position: c_int,
-    a_texture_uv: c_int,
-    a_color: c_int,
-    vbo: c_uint,
-}
+    fn selected_path(&self) -> Option<(PathBuf, SelectedFileType)> {
+        self.entry_path(self.entries.index as usize)
+    }
 
-impl Renderer {
-    fn new(base_directories: &BaseDirectories) -> Renderer {
+    fn entry_path(&self, index: usize) -> Option<(PathBuf, SelectedFileType)> {
+        let entry = self.entries.entries.get(index)?;
+        let file_type = if entry.details.is_dir {
+            SelectedFileType::Directory
+        } else {
+            SelectedFileType::File
+        };
+        Some((self.path.join(&entry.name), file_type))
+    }
+
+    fn toggle_checked(&mut self, index: usize) {
+        let first_entry = self.entries.first_entry;
+        if index < first_entry || index >= self.entries.entries.len() ||
+                self.reason(index).is_some() {
+            return
+        }
+        if let Some((_, SelectedFileType::File)) = self.entry_path(index) {
+            let entry = &mut self.entries.entries[index];
+            entry.checked = !entry.checked
+        }
+    }
+
+    fn print_chosen_paths(&self, options: &Options) {
+        let mut paths = if options.fselect_multi {
+            self.chosen_paths()
+        } else {
+            vec![self.chosen_path()]
+        };
+        // Looked up before symlinks are followed, which would take them out of the listing.
+        let indices: Vec<_> = paths.iter().map(|path| self.listing_index(path)).collect();
+        if self.follow_symlinks {
+            for path in &mut paths {
+                if let Ok(target) = fs::canonicalize(&*path) {
+                    *path = target
+                }
+            }
+        }
+        let history_id = options.history_id.as_ref().map(|id| &id[..]);
+        for path in &paths {
+            add_recent_file(history_id, path)
+        }
+        set_last_directory(history_id, &self.path);
+        let mut output = vec![];
+        for (position, (path, &index)) in paths.iter().zip(indices.iter()).enumerate() {
+            if position > 0 {
+                output.extend_from_slice(options.separator.as_bytes())
+            }
+            let bytes = path.as_os_str().as_bytes();
+            // A path that isn't in the listing, like a new file to save, has an index of -1.
+            let index_string = index.map_or("-1".to_string(), |index| index.to_string());
+            let mut default = vec![];
+            match options.index_output {
+                IndexOutput::None => default.extend_from_slice(bytes),
+                IndexOutput::WithTag => {
+                    default.extend_from_slice(bytes);
+                    default.push(b'\t');
+                    default.extend_from_slice(index_string.as_bytes())
+                }
+                IndexOutput::Only => default.extend_from_slice(index_string.as_bytes()),
+            }
+            output.extend(options.results.format(&default, &Fields {
+                value: Some(bytes),
+                index: index,
+                path: Some(path),
+                ..Fields::default()
+            }))
+        }
+        info!("printing {} chosen path(s)", paths.len());
+        output::print_line(output)
+    }
+
+    // Where `path` comes among the directory's entries as they're sorted, not counting the
+    // parent directory.
+    fn listing_index(&self, path: &Path) -> Option<usize> {
+        let first_entry = self.entries.first_entry;
+        (first_entry..self.entries.entries.len()).find(|&index| {
+            self.entry_path(index).map_or(false, |(entry_path, _)| entry_path == path)
+        }).map(|index| index - first_entry)
+    }
+
+    // The ticked files, or the chosen one if none are.
+    fn chosen_paths(&self) -> Vec<PathBuf> {
+        let checked: Vec<PathBuf> = (0..self.entries.entries.len()).filter(|&index| {
+            self.entries.entries[index].checked
+        }).filter_map(|index| self.entry_path(index)).map(|(path, _)| path).collect();
+        if checked.is_empty() { vec![self.chosen_path()] } else { checked }
+    }
+
+    fn single_file(&self) -> Option<PathBuf> {
+        let first_entry = self.entries.first_entry;
+        if self.entries.entries.len() != first_entry + 1 {
+            return None
+        }
+        match self.entry_path(first_entry) {
+            Some((path, SelectedFileType::File)) => {
+                if self.reason(first_entry).is_none() { Some(path) } else { None }
+            }
+            Some((_, SelectedFileType::Directory)) | None => None,
+        }
+    }
+}
+
+struct InputDialog {
+    text: String,
+    data: Vec<u8>,
+    password: bool,
+    policy: Option<PasswordPolicy>,
+    rules: Vec<Rule>,
+    // Shown greyed out inside the field while it's empty.
+    hint: Option<String>,
+    history: Option<InputHistory>,
+    // From `--max-input`, which also limits `data`.
+    max_length: Option<usize>,
+    // Applied the first time the field is focused, then cleared.
+    cursor_placement: Option<CursorPlacement>,
+    // The second field of `--confirm`, NUL-padded like `data`.
+    confirmation: Option<Vec<u8>>,
+    // Whether a password is being shown as typed.
+    revealed: bool,
+    // Bumped when the text is replaced, so imgui starts a fresh field showing it rather than
+    // keeping what was being typed.
+    generation: c_int,
+    keyboard: Option<OnScreenKeyboard>,
+    composition: String,
+}
+
+// Where the cursor goes when an input box is first focused.
+#[derive(Clone, Copy)]
+enum CursorPlacement {
+    SelectAll,
+    // In characters from the start.
+    Offset(usize),
+}
+
+// Earlier answers to `--history NAME` prompts, newest first.
+struct InputHistory {
+    name: String,
+    entries: Vec<String>,
+    // Which entry is showing, if one is, and what had been typed before going back to it.
+    position: Option<usize>,
+    draft: String,
+}
+
+impl InputDialog {
+    fn entered_text(&self) -> &[u8] {
+        let length = self.data.iter().position(|&x| x == 0).unwrap_or(self.data.len());
+        &self.data[..length]
+    }
+
+    fn set_entered_text(&mut self, text: &str) {
+        for byte in &mut self.data {
+            *byte = 0
+        }
+        for character in text.chars() {
+            self.insert_character(character)
+        }
+        self.generation += 1
+    }
+
+    // Steps back through the history for Up and forward for Down, like readline.
+    fn recall(&mut self, older: bool) {
+        let text = String::from_utf8_lossy(self.entered_text()).into_owned();
+        let recalled = {
+            let history = match self.history {
+                Some(ref mut history) => history,
+                None => return,
+            };
+            let position = match (history.position, older) {
+                (None, true) if !history.entries.is_empty() => {
+                    history.draft = text;
+                    Some(0)
+                }
+                (Some(position), true) if position + 1 < history.entries.len() => {
+                    Some(position + 1)
+                }
+                (Some(0), false) => None,
+                (Some(position), false) => Some(position - 1),
+                _ => return,
+            };
+            history.position = position;
+            match position {
+                Some(position) => history.entries[position].clone(),
+                None => history.draft.clone(),
+            }
+        };
+        self.set_entered_text(&recalled)
+    }
+
+    fn insert_character(&mut self, character: char) {
+        let length = self.entered_text().len();
+        let encoded = character.to_string().into_bytes();
+        if length + encoded.len() >= self.data.len() {
+            return
+        }
+        self.data[length..(length + encoded.len())].copy_from_slice(&encoded)
+    }
+
+    fn delete_character(&mut self) {
+        let old_length = self.entered_text().len();
+        let mut length = old_length;
+        while length > 0 {
+            length -= 1;
+            if (self.data[length] & 0xc0) != 0x80 {
+                break
+            }
+        }
+        for byte in &mut self.data[length..old_length] {
+            *byte = 0
+        }
+    }
+}
+
+struct MenuDialog {
+    text: String,
+    menu_height: u32,
+    items: Vec<MenuItem>,
+    pending_confirmation: Option<(usize, Confirmation)>,
+    // The item that Enter chooses, moved with the arrow keys.
+    highlighted: usize,
+    activate_highlighted: bool,
+    scroll_to_highlighted: bool,
+    // Typed to narrow the menu down to the items containing it.
+    filter: String,
+    icons: IconAtlas,
+    // For `--kbdlayout`, whose items are keyboard layouts.
+    keymap: Option<KeymapPreview>,
+}
+
+struct KeymapPreview {
+    kind: keymap::Kind,
+    // The layout in use when the dialog opened, put back if another one was tried and not kept.
+    original: Option<String>,
+    // The item whose layout is switched to, under X11.
+    previewed: Option<usize>,
+    test: Vec<u8>,
+}
+
+impl MenuDialog {
+    fn shows(&self, index: usize) -> bool {
+        let filter = self.filter.to_lowercase();
+        let item = &self.items[index];
+        if item.separator {
+            return filter.is_empty()
+        }
+        item.tag.to_lowercase().contains(&filter) || item.item.to_lowercase().contains(&filter)
+    }
+
+    // Whether the item can be highlighted and chosen right now.
+    fn choosable(&self, index: usize) -> bool {
+        self.items[index].choosable() && self.shows(index)
+    }
+
+    // Moves by `delta` shown items, stopping at the ends.
+    fn move_highlight(&mut self, delta: c_int) {
+        let mut index = self.highlighted;
+        for _ in 0..delta.abs() {
+            let next = if delta < 0 {
+                (0..index).rev().find(|&index| self.choosable(index))
+            } else {
+                (index + 1..self.items.len()).find(|&index| self.choosable(index))
+            };
+            match next {
+                Some(next) => index = next,
+                None => break,
+            }
+        }
+        self.highlighted = index;
+        self.scroll_to_highlighted = true
+    }
+
+    // For `--cancel-prints-selection`: prints the highlighted item, as choosing it would have.
+    fn print_highlighted(&self, options: &Options) {
+        match self.items.get(self.highlighted) {
+            Some(item) if item.choosable() => {
+                output::print_line(format_choice(self.highlighted, item, options))
+            }
+            _ => {}
+        }
+    }
+
+    fn set_filter(&mut self, filter: String) {
+        self.filter = filter;
+        if self.highlighted < self.items.len() && !self.choosable(self.highlighted) {
+            if let Some(index) = (0..self.items.len()).find(|&index| self.choosable(index)) {
+                self.highlighted = index
+            }
+        }
+        self.scroll_to_highlighted = true
+    }
+}
+
+struct YesNoDialog {
+    text: String,
+    dont_ask_again: bool,
+}
+
+struct MessageDialog {
+    text: String,
+}
+
+struct GaugeDialog {
+    text: String,
+    percent: u32,
+    // An installbox is a gauge with a label for the step it's on, and a log under the bar.
+    install: Option<InstallLog>,
+}
+
+struct InstallLog {
+    step: String,
+    lines: VecDeque<String>,
+    // Set when a line comes in, so the log can follow it if it was scrolled to the bottom.
+    grew: bool,
+}
+
+struct FormDialog {
+    text: String,
+    fields: Vec<Field>,
+}
+
+struct NumberDialog {
+    text: String,
+    value: c_int,
+    min: c_int,
+    max: c_int,
+    // Bumped when the arrow keys change the value, so imgui starts a fresh text field showing it
+    // rather than keeping what was being typed.
+    generation: c_int,
+    entered: bool,
+}
+
+impl NumberDialog {
+    fn step(&mut self, delta: c_int) {
+        self.value = self.value.saturating_add(delta).max(self.min).min(self.max);
+        self.generation += 1
+    }
+}
+
+struct PinDialog {
+    text: String,
+    digits: usize,
+    entered: String,
+    keypad: NumericKeypad,
+}
+
+impl PinDialog {
+    fn enter_digit(&mut self, digit: char) {
+        if self.entered.len() < self.digits {
+            self.entered.push(digit)
+        }
+    }
+}
+
+enum Subdialog {
+    File(FileDialog),
+    Input(InputDialog),
+    Menu(MenuDialog),
+    YesNo(YesNoDialog),
+    Message(MessageDialog),
+    Gauge(GaugeDialog),
+    Number(NumberDialog),
+    Form(FormDialog),
+    Pin(PinDialog),
+}
+
+fn remembered_answer(key: &str) -> Option<c_int> {
+    for line in state::read_lines(REMEMBERED_ANSWERS_FILENAME) {
+        let mut fields = line.splitn(2, '\t');
+        if let (Some(answer), Some(line_key)) = (fields.next(), fields.next()) {
+            if line_key == key {
+                return answer.parse().ok()
+            }
+        }
+    }
+    None
+}
+
+// The state file called `name`, or the one for `--history-id` if there is one.
+fn history_filename(name: &str, history_id: Option<&str>) -> String {
+    match history_id {
+        None => name.to_string(),
+        Some(history_id) => {
+            let history_id: String = history_id.chars().map(|character| {
+                if character.is_alphanumeric() || character == '-' { character } else { '_' }
+            }).collect();
+            format!("{}-{}", name, history_id)
+        }
+    }
+}
+
+fn recent_files(history_id: Option<&str>) -> Vec<PathBuf> {
+    let filename = history_filename(RECENT_FILES_FILENAME, history_id);
+    state::read_lines(&filename).into_iter()
+                                .map(PathBuf::from)
+                                .filter(|path| path.is_file())
+                                .collect()
+}
+
+fn add_recent_file(history_id: Option<&str>, path: &Path) {
+    let filename = history_filename(RECENT_FILES_FILENAME, history_id);
+    // The history is a text file, so names that aren't UTF-8 are left out of it.
+    let line = match path.to_str() {
+        Some(line) => line.to_string(),
+        None => return,
+    };
+    let mut lines = state::read_lines(&filename);
+    lines.retain(|existing_line| *existing_line != line);
+    lines.insert(0, line);
+    lines.truncate(MAX_RECENT_FILES);
+    if let Err(error) = state::write_lines(&filename, &lines) {
+        writeln!(io::stderr(), "warning: couldn't save recent files: {}", error).unwrap();
+    }
+}
+
+fn last_directory(history_id: Option<&str>) -> Option<PathBuf> {
+    let lines = state::read_lines(&history_filename(LAST_DIRECTORY_FILENAME, history_id));
+    lines.into_iter().next().map(PathBuf::from).filter(|path| path.is_dir())
+}
+
+fn set_last_directory(history_id: Option<&str>, path: &Path) {
+    let line = match path.to_str() {
+        Some(line) => line.to_string(),
+        None => return,
+    };
+    let filename = history_filename(LAST_DIRECTORY_FILENAME, history_id);
+    if let Err(error) = state::write_lines(&filename, &[line]) {
+        writeln!(io::stderr(), "warning: couldn't save the last directory: {}", error).unwrap();
+    }
+}
+
+fn input_history(name: &str) -> Vec<String> {
+    state::read_lines(&history_filename(INPUT_HISTORY_FILENAME, Some(name)))
+}
+
+fn add_input_history(name: &str, text: &str) {
+    // One answer per line, so answers with newlines in them aren't kept.
+    if text.is_empty() || text.contains('\n') {
+        return
+    }
+    let filename = history_filename(INPUT_HISTORY_FILENAME, Some(name));
+    let mut lines = state::read_lines(&filename);
+    lines.retain(|line| line != text);
+    lines.insert(0, text.to_string());
+    lines.truncate(MAX_INPUT_HISTORY);
+    if let Err(error) = state::write_lines(&filename, &lines) {
+        writeln!(io::stderr(), "warning: couldn't save input history: {}", error).unwrap();
+    }
+}
+
+fn set_remembered_answer(key: &str, answer: Option<c_int>) {
+    let mut lines: Vec<String> =
+        state::read_lines(REMEMBERED_ANSWERS_FILENAME).into_iter()
+                                                      .filter(|line| {
+                                                          line.splitn(2, '\t').nth(1) != Some(key)
+                                                      })
+                                                      .collect();
+    if let Some(answer) = answer {
+        lines.push(format!("{}\t{}", answer, key))
+    }
+    if let Err(error) = state::write_lines(REMEMBERED_ANSWERS_FILENAME, &lines) {
+        writeln!(io::stderr(), "warning: couldn't save remembered answers: {}", error).unwrap();
+    }
+}
+
+fn usage(help_string: &[u8]) -> ! {
+    io::stdout().write_all(&help_string).unwrap();
+    io::stdout().write_all(b"\n").unwrap();
+    process::exit(0)
+}
+
+// Says what's wrong with the arguments to a dialog option, like `menu`, and how it's used, then
+// exits the way dialog does for bad arguments.
+fn argument_error(option: &str, problem: &str) -> ! {
+    writeln!(io::stderr(), "error: `--{}` {}", option, problem).unwrap();
+    if let Some(&(_, arguments)) = DIALOG_USAGE.iter().find(|&&(name, _)| name == option) {
+        writeln!(io::stderr(), "usage: imdialog --{} {}", option, arguments).unwrap();
+    }
+    process::exit(ARGUMENT_ERROR_EXIT_CODE)
+}
+
+fn next_argument<'a>(values: &mut Values<'a>, option: &str, name: &str) -> &'a str {
+    match values.next() {
+        Some(value) => value,
+        None => argument_error(option, &format!("is missing its {}", name)),
+    }
+}
+
+fn number_argument<T>(values: &mut Values, option: &str, name: &str) -> T where T: FromStr {
+    let value = next_argument(values, option, name);
+    match value.trim().parse() {
+        Ok(number) => number,
+        Err(_) => {
+            argument_error(option, &format!("needs a number for its {}, not `{}`", name, value))
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum IndexOutput {
+    None,
+    WithTag,
+    Only,
+}
+
+// What's drawn behind the dialog window.
+#[derive(Clone)]
+enum Background {
+    // Red, green and blue, from 0 to 1.
+    Color(f32, f32, f32),
+    // A PNG, scaled to cover the screen and cropped evenly from both sides.
+    Image(PathBuf),
+}
+
+impl Background {
+    // `#rrggbb` is a color, and anything else is the path to an image.
+    fn parse(value: &str) -> Option<Background> {
+        if !value.starts_with('#') {
+            return Some(Background::Image(PathBuf::from(value)))
+        }
+        parse_color(value).map(|(red, green, blue)| Background::Color(red, green, blue))
+    }
+}
+
+// Which screen the dialog goes on, when there's more than one.
+#[derive(Clone, Copy)]
+enum Screen {
+    Index(i32),
+    // The one SDL puts at the origin of the desktop.
+    Primary,
+}
+
+// How dialogs come in and go away.
+#[derive(Clone, Copy, PartialEq)]
+enum Animation {
+    Fade,
+    // Rises into place from below, and sinks away again.
+    Slide,
+}
+
+#[derive(Clone)]
+struct Options {
+    touch: bool,
+    auto_select_single: bool,
+    osk: bool,
+    hint: Option<String>,
+    max_input: Option<usize>,
+    cursor_placement: Option<CursorPlacement>,
+    // Remembers input box answers under this name for recalling with Up and Down.
+    input_history: Option<String>,
+    // Input boxes take several lines, confirmed with Ctrl+Enter.
+    multiline: bool,
+    // Draws a password's strength, and a checklist of the rules it has to keep to.
+    strength_meter: bool,
+    // Asks for a password twice.
+    confirm_password: bool,
+    // Runs keyboard layout tools, so the sandbox has to let them be run.
+    kbdlayout: bool,
+    // Sets the layout chosen from `--kbdlayout` for the system.
+    apply_keymap: bool,
+    // Input boxes print newlines as `\n` and backslashes as `\\`, keeping the answer on one line.
+    escape_newlines: bool,
+    remember: Option<String>,
+    index_output: IndexOutput,
+    animation: Option<Animation>,
+    // In seconds, each way.
+    animation_time: f32,
+    gpu_timeout: u64,
+    hide_cursor: bool,
+    // Beeps when the dialog appears.
+    beep: bool,
+    // Beeps when the dialog is answered.
+    beep_after: bool,
+    click_sounds: bool,
+    // How long without any input before the screen is dimmed.
+    idle_dim: Option<Duration>,
+    stdin_commands: bool,
+    on_ok: Option<String>,
+    // Run once the dialog closes, however it was answered.
+    exec: Option<String>,
+    // Where the answer goes for `--on-ok` or `--exec`, instead of standard output.
+    export_env: Option<String>,
+    spawn: SpawnOptions,
+    sandbox: bool,
+    collation: Collation,
+    sort_menu: bool,
+    // Takes `&` mnemonics out of menu tags, and gives items without one the next free key of 1-9
+    // and a-z.
+    hotkeys: bool,
+    sort_key: SortKey,
+    dirs_first: bool,
+    ignore_case: bool,
+    details: bool,
+    bookmarks: Vec<PathBuf>,
+    shortcuts: Vec<Place>,
+    no_cancel: bool,
+    back_button: bool,
+    no_tags: bool,
+    // Menu items are given as tags alone, and only the tags are shown.
+    no_items: bool,
+    // Menus show each description under its tag, as they used to, instead of in a second column.
+    compact: bool,
+    // Cancelling a menu still prints the highlighted item, though the exit code is 1.
+    cancel_prints_selection: bool,
+    confirm_cancel: bool,
+    // What Escape exits with.
+    esc_code: c_int,
+    // Forms print `key=value` lines instead of just the values.
+    key_value: bool,
+    history_id: Option<String>,
+    // Start file dialogs in the directory the last one finished in.
+    resume: bool,
+    locale: Locale,
+    a11y_fd: Option<c_int>,
+    // Where form passwords are written instead of standard output.
+    password_fd: Option<c_int>,
+    speak: bool,
+    file_filter: FileFilter,
+    show_hidden: bool,
+    fselect_multi: bool,
+    // Choosing a file to write rather than one to read.
+    save: bool,
+    root: Option<PathBuf>,
+    // Choosing a symlink gives where it leads rather than the link itself.
+    follow_symlinks: bool,
+    // Rows in the file list, instead of fitting it to the dialog's height.
+    list_height: Option<u32>,
+    separator: String,
+    // Rewrites each line of the answer, for `--output-format`.
+    results: ResultWriter,
+    screen: Option<Screen>,
+    // Where the window's top-left corner goes, from the top-left of its screen; it's centered
+    // otherwise.
+    position: Option<(i32, i32)>,
+    background: Option<Background>,
+    // Takes over the whole screen at its current resolution.
+    fullscreen: bool,
+    borderless: bool,
+    always_on_top: bool,
+    headless: bool,
+    script: Option<PathBuf>,
+    dump_frames: Option<PathBuf>,
+    screenshot: Option<PathBuf>,
+    // When the screen is dimmed, as minutes past local midnight, from and until.
+    dim_schedule: Option<(u32, u32)>,
+    pixel_shift: bool,
+}
+
+impl Options {
+    fn entry_order(&self) -> EntryOrder {
+        EntryOrder {
+            key: self.sort_key,
+            collation: self.collation,
+            dirs_first: self.dirs_first,
+            ignore_case: self.ignore_case,
+        }
+    }
+
+    fn new(matches: &ArgMatches) -> Options {
+        let collation = match matches.value_of("collation") {
+            None => Collation::Locale,
+            Some(name) => Collation::from_name(name).unwrap(),
+        };
+        let mut locale = match matches.value_of("lang") {
+            Some(code) => Locale::new(code),
+            None => Locale::from_environment(),
+        };
+        if matches.is_present("rtl") {
+            locale.force_rtl()
+        }
+        if let Some(name) = matches.value_of("locale") {
+            locale.set_formats(name)
+        }
+        collation.init(&locale);
+        let size = |name: &str| {
+            matches.value_of(name).map(|size| {
+                match FileFilter::parse_size(size) {
+                    Some(size) => size,
+                    None => {
+                        argument_error(name,
+                                       "needs a size in bytes, optionally followed by K, M or G")
+                    }
+                }
+            })
+        };
+        let mut patterns = matches.value_of("fselect-filter").map(FileFilter::parse_patterns)
+                                                             .unwrap_or(vec![]);
+        if let Some(extensions) = matches.value_of("extensions") {
+            patterns.extend(extensions.split(',').map(|extension| extension.trim())
+                                                 .filter(|extension| !extension.is_empty())
+                                                 .map(|extension| {
+                format!("*.{}", extension.trim_start_matches('.'))
+            }))
+        }
+
+        Options {
+            touch: matches.is_present("touch"),
+            auto_select_single: matches.is_present("auto-select-single"),
+            osk: matches.is_present("osk") || !osk::keyboard_present(),
+            hint: matches.value_of("hint").map(|hint| hint.to_string()),
+            input_history: matches.value_of("history").map(|name| name.to_string()),
+            cursor_placement: if matches.is_present("select-all") {
+                Some(CursorPlacement::SelectAll)
+            } else {
+                matches.value_of("cursor").map(|offset| {
+                    match offset.parse() {
+                        Ok(offset) => CursorPlacement::Offset(offset),
+                        Err(_) => {
+                            argument_error("cursor", "needs a character offset")
+                        }
+                    }
+                })
+            },
+            max_input: matches.value_of("max-input").map(|length| {
+                match length.parse() {
+                    Ok(length) if length > 0 => length,
+                    _ => {
+                        argument_error("max-input", "needs a number of bytes")
+                    }
+                }
+            }),
+            multiline: matches.is_present("multiline"),
+            strength_meter: matches.is_present("strength-meter"),
+            confirm_password: matches.is_present("confirm"),
+            kbdlayout: matches.is_present("kbdlayout"),
+            apply_keymap: matches.is_present("apply"),
+            escape_newlines: matches.is_present("escape-newlines"),
+            remember: matches.value_of("remember").map(|key| key.to_string()),
+            index_output: if matches.is_present("print-index-only") {
+                IndexOutput::Only
+            } else if matches.is_present("print-index") {
+                IndexOutput::WithTag
+            } else {
+                IndexOutput::None
+            },
+            // `--fade-in` is older than `--animation`.
+            animation: match matches.value_of("animation") {
+                _ if matches.is_present("no-animation") => None,
+                Some("fade") => Some(Animation::Fade),
+                Some("slide") => Some(Animation::Slide),
+                Some("none") => None,
+                Some(_) => {
+                    argument_error("animation", "needs fade, slide or none")
+                }
+                None if matches.is_present("fade-in") => Some(Animation::Fade),
+                None => None,
+            },
+            animation_time: match matches.value_of("animation-time") {
+                Some(time) => {
+                    match time.parse() {
+                        Ok(time) if time >= 0.0 => time,
+                        _ => {
+                            argument_error("animation-time", "needs seconds")
+                        }
+                    }
+                }
+                None => DEFAULT_ANIMATION_TIME,
+            },
+            gpu_timeout: match matches.value_of("gpu-timeout") {
+                None => DEFAULT_GPU_TIMEOUT,
+                Some(seconds) => {
+                    match seconds.parse() {
+                        Ok(seconds) => seconds,
+                        Err(_) => {
+                            argument_error("gpu-timeout", "needs a number of seconds")
+                        }
+                    }
+                }
+            },
+            hide_cursor: matches.is_present("hide-cursor"),
+            beep: matches.is_present("beep"),
+            beep_after: matches.is_present("beep-after"),
+            click_sounds: matches.is_present("click-sounds"),
+            idle_dim: matches.value_of("idle-dim").map(|seconds| {
+                match seconds.parse() {
+                    Ok(seconds) if seconds > 0 => Duration::from_secs(seconds),
+                    _ => {
+                        argument_error("idle-dim", "needs a number of seconds")
+                    }
+                }
+            }),
+            stdin_commands: matches.is_present("stdin-commands") || matches.is_present("gauge") ||
+                matches.is_present("installbox"),
+            on_ok: matches.value_of("on-ok").map(|command| command.to_string()),
+            exec: matches.value_of("exec").map(|command| command.to_string()),
+            export_env: match matches.value_of("export-env") {
+                Some(_) if !matches.is_present("on-ok") && !matches.is_present("exec") => {
+                    argument_error("export-env", "needs `--exec` or `--on-ok` to export to")
+                }
+                Some(name) if name.is_empty() || name.contains(|c| c == '=' || c == '\0') => {
+                    argument_error("export-env", &format!("can't name a variable `{}`", name))
+                }
+                name => name.map(|name| name.to_string()),
+            },
+            spawn: SpawnOptions {
+                directory: matches.value_of("chdir").map(PathBuf::from),
+                allowed_env: matches.value_of("env-allow").map(|names| {
+                    names.split(',').map(|name| name.to_string()).collect()
+                }),
+                umask: matches.value_of("umask").map(|mask| {
+                    match u32::from_str_radix(mask, 8) {
+                        Ok(mask) if mask <= 0o777 => mask,
+                        _ => {
+                            argument_error("umask", "needs an octal mode")
+                        }
+                    }
+                }),
+            },
+            sandbox: matches.is_present("sandbox"),
+            collation: collation,
+            sort_menu: matches.is_present("sort"),
+            hotkeys: matches.is_present("hotkeys"),
+            sort_key: matches.value_of("sort").and_then(SortKey::from_name)
+                                              .unwrap_or(SortKey::Name),
+            dirs_first: matches.is_present("dirs-first"),
+            ignore_case: matches.is_present("ignore-case"),
+            details: matches.is_present("details"),
+            bookmarks: match matches.values_of("bookmark") {
+                Some(paths) => paths.map(PathBuf::from).collect(),
+                None => vec![],
+            },
+            shortcuts: matches.values_of("shortcut").map(|shortcuts| {
+                shortcuts.map(|shortcut| {
+                    match Place::parse(shortcut) {
+                        Some(place) => place,
+                        None => {
+                            argument_error("shortcut", "needs LABEL=PATH")
+                        }
+                    }
+                }).collect()
+            }).unwrap_or(vec![]),
+            no_cancel: matches.is_present("no-cancel"),
+            back_button: matches.is_present("back-button"),
+            // As dialog and whiptail spell them.
+            no_tags: matches.is_present("no-tags") || matches.is_present("notags"),
+            no_items: matches.is_present("no-items") || matches.is_present("noitem"),
+            compact: matches.is_present("compact"),
+            cancel_prints_selection: matches.is_present("cancel-prints-selection"),
+            confirm_cancel: matches.is_present("confirm-cancel"),
+            esc_code: match matches.value_of("esc-code") {
+                None => DEFAULT_ESC_CODE,
+                Some(code) => {
+                    match code.parse() {
+                        Ok(code) => code,
+                        Err(_) => {
+                            argument_error("esc-code", "needs an exit code")
+                        }
+                    }
+                }
+            },
+            key_value: matches.is_present("key-value"),
+            history_id: matches.value_of("history-id").map(|id| id.to_string()),
+            resume: matches.is_present("resume"),
+            locale: locale,
+            a11y_fd: matches.value_of("a11y-fd").map(|fd| {
+                match fd.parse() {
+                    Ok(fd) => fd,
+                    Err(_) => {
+                        argument_error("a11y-fd", "needs a file descriptor number")
+                    }
+                }
+            }),
+            password_fd: matches.value_of("password-fd").map(|fd| {
+                match fd.parse() {
+                    Ok(fd) => fd,
+                    Err(_) => {
+                        argument_error("password-fd", "needs a file descriptor number")
+                    }
+                }
+            }),
+            speak: matches.is_present("speak"),
+            file_filter: FileFilter {
+                min_size: size("min-size"),
+                max_size: size("max-size"),
+                require_readable: matches.is_present("require-readable"),
+                require_writable: matches.is_present("require-writable"),
+                patterns: patterns,
+            },
+            show_hidden: matches.is_present("show-hidden"),
+            fselect_multi: matches.is_present("fselect-multi"),
+            save: matches.is_present("fsave"),
+            follow_symlinks: matches.is_present("follow-symlinks"),
+            list_height: matches.value_of("list-height").map(|rows| {
+                match rows.parse() {
+                    Ok(rows) if rows > 0 => rows,
+                    _ => {
+                        argument_error("list-height", "needs a number of rows")
+                    }
+                }
+            }),
+            root: matches.value_of("root").map(|root| {
+                match fs::canonicalize(root) {
+                    Ok(ref root) if root.is_dir() => root.clone(),
+                    Ok(_) => argument_error("root", &format!("needs a directory, not `{}`", root)),
+                    Err(error) => argument_error("root", &format!("can't be found: {}", error)),
+                }
+            }),
+            separator: matches.value_of("separator").unwrap_or("\n").to_string(),
+            results: match ResultWriter::new(matches.value_of("output-format")) {
+                Ok(results) => results,
+                Err(message) => {
+                    argument_error("output-format", &format!("has a problem: {}", message))
+                }
+            },
+            screen: match matches.value_of("display") {
+                Some(index) => {
+                    match index.parse() {
+                        Ok(index) if index >= 0 => Some(Screen::Index(index)),
+                        _ => {
+                            argument_error("display", "needs a display number")
+                        }
+                    }
+                }
+                None if matches.is_present("monitor-primary") => Some(Screen::Primary),
+                None => None,
+            },
+            position: matches.value_of("position").map(|position| {
+                let mut coordinates = position.splitn(2, ',').map(|coordinate| {
+                    coordinate.trim().parse()
+                });
+                match (coordinates.next(), coordinates.next()) {
+                    (Some(Ok(x)), Some(Ok(y))) => (x, y),
+                    _ => {
+                        argument_error("position", "needs X,Y")
+                    }
+                }
+            }),
+            background: matches.value_of("background").map(|background| {
+                match Background::parse(background) {
+                    Some(background) => background,
+                    None => {
+                        argument_error("background", "needs #RRGGBB or a PNG")
+                    }
+                }
+            }),
+            fullscreen: matches.is_present("fullscreen"),
+            borderless: matches.is_present("borderless"),
+            always_on_top: matches.is_present("always-on-top"),
+            headless: matches.is_present("headless"),
+            script: matches.value_of("script").map(PathBuf::from),
+            dump_frames: matches.value_of("dump-frames").map(PathBuf::from),
+            screenshot: matches.value_of("screenshot").map(PathBuf::from),
+            dim_schedule: matches.value_of("dim-schedule").map(|schedule| {
+                match parse_dim_schedule(schedule) {
+                    Some(schedule) => schedule,
+                    None => {
+                        argument_error("dim-schedule", "needs a time range like `23:00-07:00`")
+                    }
+                }
+            }),
+            pixel_shift: matches.is_present("pixel-shift"),
+        }
+    }
+}
+
+fn format_choice(index: usize, item: &MenuItem, options: &Options) -> Vec<u8> {
+    let default = match options.index_output {
+        IndexOutput::None => item.tag.clone(),
+        IndexOutput::WithTag => format!("{}\t{}", item.tag, index),
+        IndexOutput::Only => index.to_string(),
+    };
+    options.results.format(default.as_bytes(), &Fields {
+        tag: Some(&item.tag),
+        value: Some(item.item.as_bytes()),
+        index: Some(index),
+        ..Fields::default()
+    })
+}
+
+#[allow(dead_code)]
+struct Dialog {
+    width: u32,
+    height: u32,
+    subdialog: Subdialog,
+    options: Options,
+    // With `--confirm-cancel`, asks before Cancel or Escape closes the dialog.
+    pending_cancel: Option<Confirmation>,
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("imdialog").version("0.1")
+                                  .author("Patrick Walton <pcwalton@mimiga.net>")
+                                  .about("Display dialogs using IMGUI")
+                                  .arg(Arg::with_name("fselect").long("fselect")
+                                                                .takes_value(true)
+                                                                .number_of_values(3))
+                                  .arg(Arg::with_name("fsave").long("fsave")
+                                                              .takes_value(true)
+                                                              .number_of_values(3))
+                                  .arg(Arg::with_name("inputbox").long("inputbox")
+                                                                 .takes_value(true)
+                                                                 .min_values(3)
+                                                                 .max_values(4))
+                                  .arg(Arg::with_name("passwordbox").long("passwordbox")
+                                                                    .takes_value(true)
+                                                                    .min_values(3)
+                                                                    .max_values(4))
+                                  .arg(Arg::with_name("policy").long("policy")
+                                                               .takes_value(true))
+                                  .arg(Arg::with_name("strength-meter").long("strength-meter"))
+                                  .arg(Arg::with_name("confirm").long("confirm")
+                                                                .requires("passwordbox"))
+                                  .arg(Arg::with_name("validate").long("validate")
+                                                                 .takes_value(true)
+                                                                 .multiple(true)
+                                                                 .number_of_values(1))
+                                  .arg(Arg::with_name("menu").long("menu")
+                                                             .takes_value(true)
+                                                             .min_values(3))
+                                  .arg(Arg::with_name("form").long("form")
+                                                             .takes_value(true)
+                                                             .min_values(3))
+                                  .arg(Arg::with_name("key-value").long("key-value"))
+                                  .arg(Arg::with_name("hotkeys").long("hotkeys"))
+                                  .arg(Arg::with_name("login").long("login")
+                                                              .takes_value(true)
+                                                              .min_values(3)
+                                                              .max_values(4))
+                                  .arg(Arg::with_name("yesno").long("yesno")
+                                                              .takes_value(true)
+                                                              .number_of_values(3))
+                                  .arg(Arg::with_name("remember").long("remember")
+                                                                 .takes_value(true))
+                                  .arg(Arg::with_name("forget").long("forget")
+                                                               .takes_value(true))
+                                  .arg(Arg::with_name("touch").long("touch"))
+                                  .arg(Arg::with_name("auto-select-single")
+                                           .long("auto-select-single"))
+                                  .arg(Arg::with_name("osk").long("osk"))
+                                  .arg(Arg::with_name("hint").long("hint").takes_value(true))
+                                  .arg(Arg::with_name("history").long("history")
+                                                                .takes_value(true))
+                                  .arg(Arg::with_name("max-input").long("max-input")
+                                                                  .takes_value(true))
+                                  .arg(Arg::with_name("select-all").long("select-all"))
+                                  .arg(Arg::with_name("cursor").long("cursor").takes_value(true))
+                                  .arg(Arg::with_name("multiline").long("multiline"))
+                                  .arg(Arg::with_name("escape-newlines")
+                                           .long("escape-newlines"))
+                                  .arg(Arg::with_name("print-index").long("print-index"))
+                                  .arg(Arg::with_name("print-index-only")
+                                           .long("print-index-only"))
+                                  .arg(Arg::with_name("fade-in").long("fade-in"))
+                                  .arg(Arg::with_name("hide-cursor").long("hide-cursor"))
+                                  .arg(Arg::with_name("beep").long("beep"))
+                                  .arg(Arg::with_name("beep-after").long("beep-after"))
+                                  .arg(Arg::with_name("click-sounds").long("click-sounds"))
+                                  .arg(Arg::with_name("idle-dim").long("idle-dim")
+                                                                 .takes_value(true))
+                                  .arg(Arg::with_name("animation").long("animation")
+                                                                  .takes_value(true))
+                                  .arg(Arg::with_name("animation-time")
+                                           .long("animation-time")
+                                           .takes_value(true))
+                                  .arg(Arg::with_name("no-animation").long("no-animation"))
+                                  .arg(Arg::with_name("gpu-timeout").long("gpu-timeout")
+                                                                    .takes_value(true))
+                                  .arg(Arg::with_name("gauge").long("gauge")
+                                                              .takes_value(true)
+                                                              .min_values(3)
+                                                              .max_values(4))
+                                  .arg(Arg::with_name("installbox").long("installbox")
+                                                                   .takes_value(true)
+                                                                   .min_values(3)
+                                                                   .max_values(4))
+                                  .arg(Arg::with_name("numberbox").long("numberbox")
+                                                                  .takes_value(true)
+                                                                  .number_of_values(6))
+                                  .arg(Arg::with_name("pinbox").long("pinbox")
+                                                               .takes_value(true)
+                                                               .number_of_values(2))
+                                  .arg(Arg::with_name("kbdlayout").long("kbdlayout")
+                                                                  .takes_value(true)
+                                                                  .number_of_values(4))
+                                  .arg(Arg::with_name("apply").long("apply")
+                                                              .requires("kbdlayout"))
+                                  .arg(Arg::with_name("stdin-commands")
+                                           .long("stdin-commands"))
+                                  .arg(Arg::with_name("msgbox").long("msgbox")
+                                                               .takes_value(true)
+                                                               .number_of_values(3))
+                                  .arg(Arg::with_name("on-ok").long("on-ok")
+                                                              .takes_value(true))
+                                  .arg(Arg::with_name("exec").long("exec")
+                                                             .takes_value(true))
+                                  .arg(Arg::with_name("export-env").long("export-env")
+                                                                   .takes_value(true))
+                                  .arg(Arg::with_name("chdir").long("chdir")
+                                                              .takes_value(true))
+                                  .arg(Arg::with_name("env-allow").long("env-allow")
+                                                                  .takes_value(true))
+                                  .arg(Arg::with_name("umask").long("umask")
+                                                              .takes_value(true))
+                                  .arg(Arg::with_name("sandbox").long("sandbox"))
+                                  .arg(Arg::with_name("collation")
+                                           .long("collation")
+                                           .takes_value(true)
+                                           .possible_values(&["locale", "codepoint"]))
+                                  // A bare `--sort` sorts menu items; a key sorts files.
+                                  .arg(Arg::with_name("sort").long("sort")
+                                                             .takes_value(true)
+                                                             .min_values(0)
+                                                             .max_values(1)
+                                                             .possible_values(&["name",
+                                                                                "size",
+                                                                                "mtime"]))
+                                  .arg(Arg::with_name("dirs-first").long("dirs-first"))
+                                  .arg(Arg::with_name("ignore-case").long("ignore-case"))
+                                  .arg(Arg::with_name("details").long("details"))
+                                  .arg(Arg::with_name("no-cancel").long("no-cancel"))
+                                  .arg(Arg::with_name("back-button").long("back-button"))
+                                  .arg(Arg::with_name("no-tags").long("no-tags"))
+                                  .arg(Arg::with_name("notags").long("notags"))
+                                  .arg(Arg::with_name("no-items").long("no-items"))
+                                  .arg(Arg::with_name("noitem").long("noitem"))
+                                  .arg(Arg::with_name("compact").long("compact"))
+                                  .arg(Arg::with_name("cancel-prints-selection")
+                                           .long("cancel-prints-selection"))
+                                  .arg(Arg::with_name("confirm-cancel").long("confirm-cancel"))
+                                  .arg(Arg::with_name("esc-code").long("esc-code")
+                                                                 .takes_value(true))
+                                  .arg(Arg::with_name("verbose").long("verbose")
+                                                                .multiple(true))
+                                  .arg(Arg::with_name("log-file").long("log-file")
+                                                                 .takes_value(true))
+                                  .arg(Arg::with_name("rtl").long("rtl"))
+                                  .arg(Arg::with_name("lang").long("lang").takes_value(true))
+                                  .arg(Arg::with_name("locale").long("locale")
+                                                               .takes_value(true))
+                                  .arg(Arg::with_name("display").long("display")
+                                                                .takes_value(true))
+                                  .arg(Arg::with_name("monitor-primary")
+                                           .long("monitor-primary")
+                                           .conflicts_with("display"))
+                                  .arg(Arg::with_name("position").long("position")
+                                                                 .takes_value(true))
+                                  .arg(Arg::with_name("background").long("background")
+                                                                   .takes_value(true))
+                                  .arg(Arg::with_name("fullscreen").long("fullscreen"))
+                                  .arg(Arg::with_name("borderless").long("borderless"))
+                                  .arg(Arg::with_name("always-on-top").long("always-on-top"))
+                                  .arg(Arg::with_name("headless").long("headless"))
+                                  .arg(Arg::with_name("script").long("script")
+                                                               .takes_value(true))
+                                  .arg(Arg::with_name("dump-frames").long("dump-frames")
+                                                                    .takes_value(true))
+                                  .arg(Arg::with_name("screenshot").long("screenshot")
+                                                                   .takes_value(true))
+                                  .arg(Arg::with_name("dim-schedule").long("dim-schedule")
+                                                                     .takes_value(true))
+                                  .arg(Arg::with_name("pixel-shift").long("pixel-shift"))
+                                  .arg(Arg::with_name("a11y-fd").long("a11y-fd")
+                                                                .takes_value(true))
+                                  .arg(Arg::with_name("password-fd").long("password-fd")
+                                                                    .takes_value(true))
+                                  .arg(Arg::with_name("speak").long("speak"))
+                                  .arg(Arg::with_name("output-fd").long("output-fd")
+                                                                  .takes_value(true))
+                                  .arg(Arg::with_name("output-format")
+                                           .long("output-format")
+                                           .takes_value(true))
+                                  .arg(Arg::with_name("history-id").long("history-id")
+                                                                   .takes_value(true))
+                                  .arg(Arg::with_name("resume").long("resume"))
+                                  .arg(Arg::with_name("min-size").long("min-size")
+                                                                 .takes_value(true))
+                                  .arg(Arg::with_name("max-size").long("max-size")
+                                                                 .takes_value(true))
+                                  .arg(Arg::with_name("require-readable")
+                                           .long("require-readable"))
+                                  .arg(Arg::with_name("require-writable")
+                                           .long("require-writable"))
+                                  .arg(Arg::with_name("show-hidden").long("show-hidden"))
+                                  .arg(Arg::with_name("root").long("root").takes_value(true))
+                                  .arg(Arg::with_name("follow-symlinks")
+                                           .long("follow-symlinks"))
+                                  .arg(Arg::with_name("list-height").long("list-height")
+                                                                    .takes_value(true))
+                                  .arg(Arg::with_name("fselect-multi").long("fselect-multi")
+                                                                      .requires("fselect"))
+                                  .arg(Arg::with_name("separator").long("separator")
+                                                                  .takes_value(true))
+                                  .arg(Arg::with_name("fselect-filter").long("fselect-filter")
+                                                                       .takes_value(true))
+                                  .arg(Arg::with_name("extensions").long("extensions")
+                                                                   .takes_value(true))
+                                  .arg(Arg::with_name("bookmark").long("bookmark")
+                                                                 .takes_value(true)
+                                                                 .multiple(true)
+                                                                 .number_of_values(1))
+                                  .arg(Arg::with_name("shortcut").long("shortcut")
+                                                                 .takes_value(true)
+                                                                 .multiple(true)
+                                                                 .number_of_values(1))
+                                  .arg(Arg::with_name("doctor").long("doctor"))
+                                  .arg(Arg::with_name("compat-test").long("compat-test")
+                                                                    .takes_value(true))
+                                  .arg(Arg::with_name("wizard").long("wizard")
+                                                               .takes_value(true))
+                                  .arg(Arg::with_name("wizard-resume").long("wizard-resume")
+                                                                      .requires("wizard"))
+                                  .arg(Arg::with_name("server").long("server")
+                                                               .takes_value(true)
+                                                               .possible_values(&["session",
+                                                                                  "system"]))
+}
+
+fn command_line_arguments() -> Vec<String> {
+    let arguments: Vec<String> = env::args().collect();
+    if zenity::invoked_as_zenity() {
+        zenity::translate_arguments(arguments)
+    } else if whiptail::invoked_as_whiptail() {
+        whiptail::translate_arguments(arguments)
+    } else {
+        arguments
+    }
+}
+
+fn parse_command_line() -> (ArgMatches<'static>, Vec<u8>) {
+    let app = app();
+    let mut help_string = vec![];
+    app.write_help(&mut help_string).unwrap();
+    (app.get_matches_from(command_line_arguments()), help_string)
+}
+
+// Each step gets the command line imdialog was given, without `--wizard` and `--wizard-resume`,
+// and then its own arguments.
+// Parses a wizard step's arguments after `base`, the rest of imdialog's.
+fn parse_wizard_step(base: &[String], arguments: Vec<String>)
+                     -> Result<ArgMatches<'static>, String> {
+    app().setting(AppSettings::ColorNever)
+         .get_matches_from_safe(base.iter().cloned().chain(arguments))
+         .map_err(|error| {
+        let message = error.message.lines().next().unwrap_or("");
+        message.trim_start_matches("error: ").to_string()
+    })
+}
+
+fn run_wizard(path: &str, resume: bool, options: &Options, help_string: &[u8]) -> c_int {
+    let mut base = vec![];
+    let mut arguments = command_line_arguments().into_iter();
+    while let Some(argument) = arguments.next() {
+        if argument == "--wizard" {
+            arguments.next();
+        } else if argument != "--wizard-resume" && !argument.starts_with("--wizard=") {
+            base.push(argument)
+        }
+    }
+    let parse = |arguments: Vec<String>| parse_wizard_step(&base, arguments);
+    match Wizard::load(Path::new(path), &parse) {
+        Ok(wizard) => wizard.run(options, help_string, resume, parse),
+        Err(message) => {
+            writeln!(io::stderr(), "error: `--wizard`: {}", message).unwrap();
+            process::exit(1)
+        }
+    }
+}
+
+impl Dialog {
+    fn new(matches: &ArgMatches, help_string: &[u8]) -> Dialog {
+        let options = Options::new(matches);
+
+        if let Some(key) = matches.value_of("forget") {
+            set_remembered_answer(key, None)
+        }
+
+        if let Some(values) = matches.values_of("fselect") {
+            return Dialog::fselect("fselect", values, options)
+        }
+        if let Some(values) = matches.values_of("fsave") {
+            return Dialog::fselect("fsave", values, options)
+        }
+        let rules = matches.values_of("validate").map(|specs| {
+            specs.map(|spec| {
+                match Rule::parse(spec) {
+                    Ok(rule) => rule,
+                    Err(message) => {
+                        argument_error("validate", &format!("has a problem: {}", message))
+                    }
+                }
+            }).collect()
+        }).unwrap_or(vec![]);
+        if let Some(values) = matches.values_of("inputbox") {
+            return Dialog::inputbox(values, false, None, rules, options)
+        }
+        if let Some(values) = matches.values_of("passwordbox") {
+            let policy = matches.value_of("policy").map(|spec| {
+                match PasswordPolicy::parse(spec) {
+                    Ok(policy) => policy,
+                    Err(message) => {
+                        argument_error("policy", &format!("has a problem: {}", message))
+                    }
+                }
+            });
+            return Dialog::inputbox(values, true, policy, rules, options)
+        }
+        if let Some(values) = matches.values_of("menu") {
+            return Dialog::menu(values, options)
+        }
+        if let Some(values) = matches.values_of("login") {
+            return Dialog::login(values, options)
+        }
+        if let Some(values) = matches.values_of("form") {
+            return Dialog::form(values, options)
+        }
+        if let Some(values) = matches.values_of("yesno") {
+            return Dialog::yesno(values, options)
+        }
+        if let Some(values) = matches.values_of("msgbox") {
+            return Dialog::msgbox(values, options)
+        }
+        if let Some(values) = matches.values_of("gauge") {
+            return Dialog::gauge("gauge", values, options)
+        }
+        if let Some(values) = matches.values_of("installbox") {
+            return Dialog::gauge("installbox", values, options)
+        }
+        if let Some(values) = matches.values_of("numberbox") {
+            return Dialog::numberbox(values, options)
+        }
+        if let Some(values) = matches.values_of("pinbox") {
+            return Dialog::pinbox(values, options)
+        }
+        if let Some(values) = matches.values_of("kbdlayout") {
+            return Dialog::kbdlayout(values, options)
+        }
+
+        if matches.is_present("forget") {
+            process::exit(0)
+        }
+        usage(help_string)
+    }
+
+    fn single_choice(&self) -> Option<Vec<u8>> {
+        match self.subdialog {
+            Subdialog::File(ref subdialog) => {
+                subdialog.single_file().map(|path| {
+                    let bytes = path.as_os_str().as_bytes();
+                    let default = match self.options.index_output {
+                        IndexOutput::None => bytes.to_vec(),
+                        IndexOutput::WithTag => [bytes, b"\t0"].concat(),
+                        IndexOutput::Only => b"0".to_vec(),
+                    };
+                    self.options.results.format(&default, &Fields {
+                        value: Some(bytes),
+                        index: Some(0),
+                        path: Some(&path),
+                        ..Fields::default()
+                    })
+                })
+            }
+            Subdialog::Menu(ref subdialog) => {
+                // Separators and disabled items don't count, since they can't be chosen anyway.
+                let mut choosable = subdialog.items.iter().enumerate().filter(|&(_, item)| {
+                    item.choosable()
+                });
+                match (choosable.next(), choosable.next()) {
+                    (Some((index, item)), None) => Some(format_choice(index, item, &self.options)),
+                    _ => None,
+                }
+            }
+            Subdialog::Input(_) |
+            Subdialog::YesNo(_) |
+            Subdialog::Message(_) |
+            Subdialog::Gauge(_) |
+            Subdialog::Number(_) |
+            Subdialog::Form(_) |
+            Subdialog::Pin(_) => None,
+        }
+    }
+
+    fn set_composition(&mut self, text: String) {
+        if let Subdialog::Input(ref mut subdialog) = self.subdialog {
+            subdialog.composition = text
+        }
+    }
+
+    fn set_text(&mut self, text: String) {
+        match self.subdialog {
+            Subdialog::Input(ref mut subdialog) => subdialog.text = text,
+            Subdialog::Menu(ref mut subdialog) => subdialog.text = text,
+            Subdialog::YesNo(ref mut subdialog) => subdialog.text = text,
+            Subdialog::Message(ref mut subdialog) => subdialog.text = text,
+            Subdialog::Gauge(ref mut subdialog) => subdialog.text = text,
+            Subdialog::Number(ref mut subdialog) => subdialog.text = text,
+            Subdialog::Form(ref mut subdialog) => subdialog.text = text,
+            Subdialog::Pin(ref mut subdialog) => subdialog.text = text,
+            Subdialog::File(_) => {}
+        }
+    }
+
+    fn toggle_hidden_files(&mut self) {
+        if let Subdialog::File(ref mut subdialog) = self.subdialog {
+            subdialog.toggle_hidden_files()
+        }
+    }
+
+    fn loading(&self) -> bool {
+        match self.subdialog {
+            Subdialog::File(ref subdialog) => {
+                subdialog.loader.is_some() || subdialog.refresh.is_some()
+            }
+            _ => false,
+        }
+    }
+
+    // Whether the directory being shown or the mounted disks need checking for changes now and
+    // then.
+    fn watching(&self) -> bool {
+        match self.subdialog {
+            Subdialog::File(ref subdialog) => {
+                subdialog.watch.is_some() || subdialog.mount_watch.is_some()
+            }
+            _ => false,
+        }
+    }
+
+    fn list_focused(&self) -> bool {
+        match self.subdialog {
+            Subdialog::File(ref subdialog) => subdialog.list_focused,
+            _ => false,
+        }
+    }
+
+    // Typing goes to the field for trying out a layout rather than to the menu's filter.
+    fn typing_in_keymap_test(&self) -> bool {
+        match self.subdialog {
+            Subdialog::Menu(MenuDialog { keymap: Some(_), .. }) => unsafe {
+                imgui::igIsAnyItemActive()
+            },
+            _ => false,
+        }
+    }
+
+    // Returns true if the text went to the file list's search. In `--fselect-multi` mode a space
+    // ticks the selection instead.
+    fn type_ahead(&mut self, text: &str) -> bool {
+        if self.typing_in_keymap_test() {
+            return false
+        }
+        if let Subdialog::Pin(ref mut subdialog) = self.subdialog {
+            for digit in text.chars().filter(|character| character.is_digit(10)) {
+                subdialog.enter_digit(digit)
+            }
+            return true
+        }
+        if let Subdialog::Menu(ref mut subdialog) = self.subdialog {
+            if subdialog.pending_confirmation.is_some() {
+                return false
+            }
+            // Hotkeys only work before a filter's been started.
+            let mut characters = text.chars().flat_map(|character| character.to_lowercase());
+            if let (Some(key), None) = (characters.next(), characters.next()) {
+                match subdialog.items.iter().position(|item| item.hotkey == Some(key)) {
+                    Some(index) if subdialog.filter.is_empty() && subdialog.choosable(index) => {
+                        subdialog.highlighted = index;
+                        subdialog.activate_highlighted = true;
+                        return true
+                    }
+                    _ => {}
+                }
+            }
+            let filter = format!("{}{}", subdialog.filter, text);
+            subdialog.set_filter(filter);
+            return true
+        }
+        if !self.list_focused() || unsafe { imgui::igIsAnyItemActive() } {
+            return false
+        }
+        if let Subdialog::File(ref mut subdialog) = self.subdialog {
+            if !(self.options.fselect_multi && text == " ") {
+                subdialog.type_ahead(text)
+            }
+        }
+        true
+    }
+
+    fn erase_search(&mut self) {
+        if self.typing_in_keymap_test() {
+            return
+        }
+        match self.subdialog {
+            Subdialog::File(ref mut subdialog) if subdialog.list_focused => {
+                subdialog.erase_search()
+            }
+            Subdialog::Menu(ref mut subdialog) => {
+                let mut filter = subdialog.filter.clone();
+                filter.pop();
+                subdialog.set_filter(filter)
+            }
+            Subdialog::Pin(ref mut subdialog) => {
+                subdialog.entered.pop();
+            }
+            _ => {}
+        }
+    }
+
+    // Called when Escape closes the dialog. Nothing else is printed: an abort never gives a
+    // result, except for `--cancel-prints-selection`.
+    fn cancel(&self) {
+        match self.subdialog {
+            Subdialog::Menu(ref subdialog) if self.options.cancel_prints_selection => {
+                subdialog.print_highlighted(&self.options)
+            }
+            _ => {}
+        }
+    }
+
+    fn ask_to_cancel(&mut self) {
+        let question = self.options.locale.translate("Discard and exit?").to_string();
+        self.pending_cancel = Some(Confirmation::new(question))
+    }
+
+    fn confirmation(&mut self) -> Option<&mut Confirmation> {
+        if let Some(ref mut confirmation) = self.pending_cancel {
+            return Some(confirmation)
+        }
+        match self.subdialog {
+            Subdialog::File(FileDialog { pending_overwrite: Some((_, ref mut confirmation)), .. }) |
+            Subdialog::Menu(MenuDialog {
+                pending_confirmation: Some((_, ref mut confirmation)),
+                ..
+            }) => Some(confirmation),
+            _ => None,
+        }
+    }
+
+    fn confirming(&mut self) -> bool {
+        self.confirmation().is_some()
+    }
+
+    fn answer_confirmation(&mut self, answer: bool) {
+        if let Some(confirmation) = self.confirmation() {
+            confirmation.answer = Some(answer)
+        }
+    }
+
+    // Returns false if there was no search to clear, so Escape should close the dialog.
+    fn clear_search(&mut self) -> bool {
+        match self.subdialog {
+            Subdialog::File(ref mut subdialog) if !subdialog.search.is_empty() => {
+                subdialog.search.clear();
+                true
+            }
+            Subdialog::Menu(ref mut subdialog) if !subdialog.filter.is_empty() => {
+                subdialog.set_filter(String::new());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn activate_selection(&mut self) {
+        match self.subdialog {
+            Subdialog::Number(ref mut subdialog) => {
+                subdialog.entered = true;
+                return
+            }
+            Subdialog::Pin(ref mut subdialog) => {
+                subdialog.keypad.press_highlighted();
+                return
+            }
+            Subdialog::Menu(ref mut subdialog) if subdialog.pending_confirmation.is_none() => {
+                subdialog.activate_highlighted = true;
+                return
+            }
+            _ => {}
+        }
+        if !self.list_focused() || unsafe { imgui::igIsAnyItemActive() } {
+            return
+        }
+        if let Subdialog::File(ref mut subdialog) = self.subdialog {
+            subdialog.activate_selection = true
+        }
+    }
+
+    fn toggle_checked(&mut self) {
+        if !self.options.fselect_multi {
+            return
+        }
+        if let Subdialog::File(ref mut subdialog) = self.subdialog {
+            let index = subdialog.entries.index as usize;
+            subdialog.toggle_checked(index)
+        }
+    }
+
+    fn toggle_revealed(&mut self) {
+        if let Subdialog::Input(ref mut subdialog) = self.subdialog {
+            subdialog.revealed = subdialog.password && !subdialog.revealed
+        }
+    }
+
+    // Up is 1 and Down is -1.
+    fn arrow_key(&mut self, delta: c_int) {
+        match self.subdialog {
+            Subdialog::Input(ref mut subdialog) => subdialog.recall(delta > 0),
+            Subdialog::Number(ref mut subdialog) => subdialog.step(delta),
+            Subdialog::Pin(ref mut subdialog) => {
+                subdialog.keypad.move_highlight(-delta as isize, 0)
+            }
+            Subdialog::Menu(ref mut subdialog) if subdialog.pending_confirmation.is_none() => {
+                subdialog.move_highlight(-delta)
+            }
+            _ => {}
+        }
+    }
+
+    // Left is -1 and Right is 1.
+    fn sideways_key(&mut self, delta: isize) {
+        if let Subdialog::Pin(ref mut subdialog) = self.subdialog {
+            subdialog.keypad.move_highlight(0, delta)
+        }
+    }
+
+    // Page Up is 1 and Page Down is -1.
+    fn page_key(&mut self, delta: c_int) {
+        match self.subdialog {
+            Subdialog::Menu(ref mut subdialog) if subdialog.pending_confirmation.is_none() => {
+                // A menu that isn't scrolling has everything on one page.
+                let page = if subdialog.menu_height > 0 {
+                    subdialog.menu_height as c_int
+                } else {
+                    subdialog.items.len() as c_int
+                };
+                subdialog.move_highlight(-delta * page)
+            }
+            _ => {}
+        }
+    }
+
+    fn set_percent(&mut self, percent: u32) {
+        if let Subdialog::Gauge(ref mut subdialog) = self.subdialog {
+            subdialog.percent = percent.min(100)
+        }
+    }
+
+    fn set_step(&mut self, step: String) {
+        if let Subdialog::Gauge(GaugeDialog { install: Some(ref mut install), .. }) =
+                self.subdialog {
+            install.step = step
+        }
+    }
+
+    fn log(&mut self, line: String) {
+        if let Subdialog::Gauge(GaugeDialog { install: Some(ref mut install), .. }) =
+                self.subdialog {
+            if install.lines.len() == MAX_INSTALL_LOG_LINES {
+                install.lines.pop_front();
+            }
+            install.lines.push_back(line);
+            install.grew = true
+        }
+    }
+
+    fn is_gauge(&self) -> bool {
+        match self.subdialog {
+            Subdialog::Gauge(_) => true,
+            _ => false,
+        }
+    }
+
+    // Whether the answer has a password in it, and so mustn't be saved anywhere.
+    fn has_secrets(&self) -> bool {
+        match self.subdialog {
+            Subdialog::Input(ref subdialog) => subdialog.password,
+            Subdialog::Form(ref subdialog) => subdialog.fields.iter().any(|field| field.password),
+            Subdialog::Pin(_) => true,
+            _ => false,
+        }
+    }
+
+    #[cfg(feature = "server")]
+    fn selected_file(&self) -> Option<PathBuf> {
+        match self.subdialog {
+            Subdialog::File(ref subdialog) => Some(subdialog.chosen_path()),
+            _ => None,
+        }
+    }
+
+    // What the user has done so far, as key-value pairs. Secrets are left out.
+    fn snapshot(&self) -> Vec<(String, String)> {
+        let mut state = vec![];
+        match self.subdialog {
+            Subdialog::File(ref subdialog) => {
+                // Before the directory, so that restoring it lists the same entries.
+                state.push(("show-hidden".to_string(), subdialog.show_hidden.to_string()));
+                state.push(("directory".to_string(), subdialog.path.display().to_string()));
+                let index = subdialog.entries.index as usize;
+                if let Some(entry) = subdialog.entries.entries.get(index) {
+                    state.push(("selection".to_string(), entry.label.clone()))
+                }
+                state.push(("scroll".to_string(), subdialog.scroll.to_string()))
+            }
+            Subdialog::Input(ref subdialog) if !subdialog.password => {
+                let text = String::from_utf8_lossy(subdialog.entered_text()).into_owned();
+                state.push(("text".to_string(), text))
+            }
+            Subdialog::YesNo(ref subdialog) => {
+                state.push(("dont-ask-again".to_string(), subdialog.dont_ask_again.to_string()))
+            }
+            Subdialog::Menu(ref subdialog) => {
+                state.push(("highlighted".to_string(), subdialog.highlighted.to_string()))
+            }
+            Subdialog::Number(ref subdialog) => {
+                state.push(("value".to_string(), subdialog.value.to_string()))
+            }
+            Subdialog::Form(ref subdialog) => {
+                for field in subdialog.fields.iter().filter(|field| !field.password) {
+                    state.push((format!("field:{}", field.key), field.output()))
+                }
+            }
+            _ => {}
+        }
+        state
+    }
+
+    fn restore(&mut self, state: &[(String, String)]) {
+        for &(ref key, ref value) in state {
+            match (&mut self.subdialog, &key[..]) {
+                (&mut Subdialog::File(ref mut subdialog), "show-hidden") => {
+                    subdialog.show_hidden = value == "true"
+                }
+                (&mut Subdialog::File(ref mut subdialog), "directory") => {
+                    let path = PathBuf::from(value);
+                    if path.is_dir() {
+                        subdialog.change_directory(path)
+                    }
+                }
+                (&mut Subdialog::File(ref mut subdialog), "selection") => subdialog.select(value),
+                (&mut Subdialog::File(ref mut subdialog), "scroll") => {
+                    subdialog.restored_scroll = value.parse().ok()
+                }
+                (&mut Subdialog::Input(ref mut subdialog), "text") if !subdialog.password => {
+                    subdialog.set_entered_text(value)
+                }
+                (&mut Subdialog::YesNo(ref mut subdialog), "dont-ask-again") => {
+                    subdialog.dont_ask_again = value == "true"
+                }
+                (&mut Subdialog::Menu(ref mut subdialog), "highlighted") => {
+                    match value.parse() {
+                        Ok(index) if index < subdialog.items.len() &&
+                                     subdialog.choosable(index) => {
+                            subdialog.highlighted = index;
+                            subdialog.scroll_to_highlighted = true
+                        }
+                        _ => {}
+                    }
+                }
+                (&mut Subdialog::Number(ref mut subdialog), "value") => {
+                    if let Ok(value) = value.parse::<c_int>() {
+                        subdialog.value = value.max(subdialog.min).min(subdialog.max);
+                        subdialog.generation += 1
+                    }
+                }
+                (&mut Subdialog::Form(ref mut subdialog), key) if key.starts_with("field:") => {
+                    let key = &key["field:".len()..];
+                    let field = subdialog.fields.iter_mut().find(|field| field.key == key);
+                    if let Some(field) = field {
+                        field.restore(value)
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn remembered_answer(&self) -> Option<c_int> {
+        match (&self.subdialog, &self.options.remember) {
+            (&Subdialog::YesNo(_), &Some(ref key)) => remembered_answer(key),
+            _ => None,
+        }
+    }
+
+    fn fselect(option: &str, mut values: Values, options: Options) -> Dialog {
+        let argument = Path::new(next_argument(&mut values, option, "a path"));
+        // Like dialog, a file that doesn't exist yet starts the dialog in its directory.
+        let mut path = match absolute_path(argument) {
+            Ok(path) => path,
+            Err(error) => {
+                argument_error(option, &format!("can't find `{}`: {}", argument.display(), error))
+            }
+        };
+        // `-` means wherever the last file dialog finished, as does `--resume`, though that keeps
+        // the name of a file to select or save as.
+        if argument == Path::new("-") || options.resume {
+            let history_id = options.history_id.as_ref().map(|id| &id[..]);
+            if let Some(directory) = last_directory(history_id) {
+                path = match path.file_name() {
+                    Some(filename) if argument != Path::new("-") && !path.is_dir() => {
+                        directory.join(filename)
+                    }
+                    _ => directory,
+                }
+            } else if argument == Path::new("-") {
+                path = match env::current_dir() {
+                    Ok(directory) => directory,
+                    Err(error) => {
+                        argument_error(option, &format!("can't start in the current directory: {}",
+                                                        error))
+                    }
+                }
+            }
+        }
+        let width: u32 = number_argument(&mut values, option, "width");
+        let height: u32 = number_argument(&mut values, option, "height");
+        Dialog::file(path, width, height, options)
+    }
+
+    fn file(path: PathBuf, width: u32, height: u32, options: Options) -> Dialog {
+        // Given a file, start in its directory with the file selected.
+        let (path, filename) = match (path.is_dir(), path.parent(), path.file_name()) {
+            (false, Some(parent), Some(filename)) => {
+                (parent.to_owned(), filename.to_str().map(|filename| filename.to_string()))
+            }
+            _ => (path.clone(), None),
+        };
+        let root = options.root.as_ref().map(|root| root.as_path());
+        let (path, filename) = match options.root {
+            Some(ref root) if !within_root(&path, Some(root)) => (root.clone(), None),
+            _ => (path, filename),
+        };
+        // Hidden files are left out unless asked for, or unless one was asked to be selected.
+        let show_hidden = options.show_hidden ||
+            filename.as_ref().map_or(false, |filename| filename.starts_with('.'));
+        let mut places = options.shortcuts.clone();
+        for place in places::standard(&options.locale) {
+            if !places.iter().any(|existing| existing.path == place.path) {
+                places.push(place)
+            }
+        }
+        places.retain(|place| within_root(&place.path, root));
+        let mut bookmarks = options.bookmarks.clone();
+        for bookmark in bookmarks::load() {
+            if !bookmarks.contains(&bookmark) {
+                bookmarks.push(bookmark)
+            }
+        }
+        bookmarks.retain(|bookmark| within_root(bookmark, root));
+        let recent_files = recent_files(options.history_id.as_ref().map(|id| &id[..]));
+        let mut subdialog = FileDialog {
+            path: path.clone(),
+            entries: FileDialogEntries::none(),
+            order: options.entry_order(),
+            locale: options.locale.clone(),
+            devices: places::devices().into_iter().filter(|device| {
+                within_root(&device.path, root)
+            }).collect(),
+            mount_watch: MountWatch::new(),
+            places: places,
+            bookmarks: bookmarks,
+            recent_files: recent_files.into_iter().filter(|path| {
+                let filename = path.file_name().and_then(|filename| filename.to_str());
+                options.file_filter.shows(filename.unwrap_or(""), false) &&
+                    options.file_filter.reason(path, &options.locale).is_none() &&
+                    within_root(path, root)
+            }).collect(),
+            chosen_file: None,
+            location: vec![0; MAX_TEXT_LENGTH],
+            location_shown: String::new(),
+            location_error: None,
+            new_directory: None,
+            new_directory_error: None,
+            focus_new_directory: false,
+            scroll_to_selection: false,
+            scroll: 0.0,
+            restored_scroll: None,
+            filter: options.file_filter.clone(),
+            root: options.root.clone(),
+            follow_symlinks: options.follow_symlinks,
+            show_hidden: show_hidden,
+            loader: None,
+            pending_selection: None,
+            watch: None,
+            refresh: None,
+            // There's no location field to type into when choosing several files.
+            list_focused: options.fselect_multi,
+            search: String::new(),
+            activate_selection: false,
+            pending_overwrite: None,
+            height: height,
+            list_rows: LIST_HEIGHT,
+        };
+        subdialog.change_directory(path);
+        // Whether there's only one file can't be known until they've all been read.
+        if options.auto_select_single {
+            subdialog.receive_entries(None)
+        }
+        if let Some(ref filename) = filename {
+            subdialog.select(filename);
+            let location = format!("{}{}", subdialog.location_shown, filename);
+            subdialog.set_location(location)
+        }
+        Dialog {
+            width: width,
+            height: height,
+            subdialog: Subdialog::File(subdialog),
+            options: options,
+            pending_cancel: None,
+        }
+    }
+
+    fn inputbox(mut values: Values,
+                password: bool,
+                policy: Option<PasswordPolicy>,
+                rules: Vec<Rule>,
+                options: Options)
+                -> Dialog {
+        let option = if password { "passwordbox" } else { "inputbox" };
+        let text = next_argument(&mut values, option, "text");
+        let width: u32 = number_argument(&mut values, option, "width");
+        let height: u32 = number_argument(&mut values, option, "height");
+
+        // `--max-input` counts bytes, like dialog's, and sizes the buffer so imgui stops there.
+        let buffer_length = options.max_input.map_or(MAX_TEXT_LENGTH, |length| length + 1);
+        let mut initial_data = values.next().unwrap_or("");
+        while initial_data.len() >= buffer_length {
+            let mut end = initial_data.len() - 1;
+            while !initial_data.is_char_boundary(end) {
+                end -= 1
+            }
+            initial_data = &initial_data[..end]
+        }
+        let mut data = initial_data.as_bytes().to_vec();
+        data.resize(buffer_length, 0);
+
+        Dialog {
+            width: width,
+            height: height,
+            subdialog: Subdialog::Input(InputDialog {
+                text: text.to_string(),
+                data: data,
+                password: password,
+                policy: policy,
+                rules: rules,
+                hint: options.hint.clone(),
+                // Passwords are never written down, and multiline boxes need Up and Down to move
+                // between lines.
+                history: match options.input_history {
+                    Some(ref name) if !password && !options.multiline => {
+                        Some(InputHistory {
+                            name: name.clone(),
+                            entries: input_history(name),
+                            position: None,
+                            draft: String::new(),
+                        })
+                    }
+                    _ => None,
+                },
+                max_length: options.max_input,
+                cursor_placement: options.cursor_placement,
+                confirmation: if password && options.confirm_password {
+                    Some(vec![0; buffer_length])
+                } else {
+                    None
+                },
+                revealed: false,
+                generation: 0,
+                keyboard: if options.osk { Some(OnScreenKeyboard::new()) } else { None },
+                composition: String::new(),
+            }),
+            options: options,
+            pending_cancel: None,
+        }
+    }
+
+    fn yesno(mut values: Values, options: Options) -> Dialog {
+        let text = next_argument(&mut values, "yesno", "text");
+        let width: u32 = number_argument(&mut values, "yesno", "width");
+        let height: u32 = number_argument(&mut values, "yesno", "height");
+        Dialog::question(text, width, height, options)
+    }
+
+    fn question(text: &str, width: u32, height: u32, options: Options) -> Dialog {
+        Dialog {
+            width: width,
+            height: height,
+            subdialog: Subdialog::YesNo(YesNoDialog {
+                text: text.to_string(),
+                dont_ask_again: false,
+            }),
+            options: options,
+            pending_cancel: None,
+        }
+    }
+
+    fn msgbox(mut values: Values, options: Options) -> Dialog {
+        let text = next_argument(&mut values, "msgbox", "text");
+        let width: u32 = number_argument(&mut values, "msgbox", "width");
+        let height: u32 = number_argument(&mut values, "msgbox", "height");
+        Dialog::message(text, width, height, options)
+    }
+
+    fn message(text: &str, width: u32, height: u32, options: Options) -> Dialog {
+        Dialog {
+            width: width,
+            height: height,
+            subdialog: Subdialog::Message(MessageDialog {
+                text: text.to_string(),
+            }),
+            options: options,
+            pending_cancel: None,
+        }
+    }
+
+    fn gauge(option: &str, mut values: Values, options: Options) -> Dialog {
+        let text = next_argument(&mut values, option, "text");
+        let width: u32 = number_argument(&mut values, option, "width");
+        let height: u32 = number_argument(&mut values, option, "height");
+        let percent: u32 = match values.next() {
+            Some(percent) => {
+                match percent.trim().parse() {
+                    Ok(percent) => percent,
+                    Err(_) => {
+                        argument_error(option, &format!("needs a number, not `{}`", percent))
+                    }
+                }
+            }
+            None => 0,
+        };
+        let install = if option == "installbox" {
+            Some(InstallLog {
+                step: String::new(),
+                lines: VecDeque::new(),
+                grew: false,
+            })
+        } else {
+            None
+        };
+        Dialog {
+            width: width,
+            height: height,
+            subdialog: Subdialog::Gauge(GaugeDialog {
+                text: text.to_string(),
+                percent: percent.min(100),
+                install: install,
+            }),
+            options: options,
+            pending_cancel: None,
+        }
+    }
+
+    fn form(mut values: Values, options: Options) -> Dialog {
+        let text = next_argument(&mut values, "form", "text");
+        let width: u32 = number_argument(&mut values, "form", "width");
+        let height: u32 = number_argument(&mut values, "form", "height");
+
+        let mut fields = vec![];
+        loop {
+            let key = match values.next() {
+                Some(key) => key,
+                None => break,
+            };
+            let label = match values.next() {
+                Some(label) => label,
+                None => {
+                    argument_error("form", "needs key/label pairs; got an odd number of arguments")
+                }
+            };
+            let (label, attributes) = parse_item_attributes(label);
+            match Field::new(key, &label, &attributes) {
+                Ok(field) => fields.push(field),
+                Err(message) => {
+                    argument_error("form", &format!("has an invalid field: {}", message))
+                }
+            }
+        }
+
+        Dialog {
+            width: width,
+            height: height,
+            subdialog: Subdialog::Form(FormDialog {
+                text: text.to_string(),
+                fields: fields,
+            }),
+            options: options,
+            pending_cancel: None,
+        }
+    }
+
+    // A form with a username, which can be filled in to start with, and a password.
+    fn login(mut values: Values, options: Options) -> Dialog {
+        let text = next_argument(&mut values, "login", "text");
+        let width: u32 = number_argument(&mut values, "login", "width");
+        let height: u32 = number_argument(&mut values, "login", "height");
+        let username = values.next().unwrap_or("");
+        let username = match Field::new("username",
+                                        options.locale.translate("Username"),
+                                        &[("default".to_string(), username.to_string())]) {
+            Ok(field) => field,
+            Err(message) => argument_error("login", &format!("has a problem: {}", message)),
+        };
+        let password = Field::new("password",
+                                  options.locale.translate("Password"),
+                                  &[("type".to_string(), "password".to_string())]).unwrap();
+        Dialog {
+            width: width,
+            height: height,
+            subdialog: Subdialog::Form(FormDialog {
+                text: text.to_string(),
+                fields: vec![username, password],
+            }),
+            options: options,
+            pending_cancel: None,
+        }
+    }
+
+    fn numberbox(mut values: Values, options: Options) -> Dialog {
+        let text = next_argument(&mut values, "numberbox", "text");
+        let width: u32 = number_argument(&mut values, "numberbox", "width");
+        let height: u32 = number_argument(&mut values, "numberbox", "height");
+        let min: c_int = number_argument(&mut values, "numberbox", "minimum");
+        let max: c_int = number_argument(&mut values, "numberbox", "maximum");
+        let value: c_int = number_argument(&mut values, "numberbox", "default");
+        if min > max {
+            argument_error("numberbox", "has a minimum that's more than its maximum")
+        }
+        Dialog {
+            width: width,
+            height: height,
+            subdialog: Subdialog::Number(NumberDialog {
+                text: text.to_string(),
+                value: value.max(min).min(max),
+                min: min,
+                max: max,
+                generation: 0,
+                entered: false,
+            }),
+            options: options,
+            pending_cancel: None,
+        }
+    }
+
+    fn kbdlayout(mut values: Values, options: Options) -> Dialog {
+        let text = next_argument(&mut values, "kbdlayout", "text");
+        let width: u32 = number_argument(&mut values, "kbdlayout", "width");
+        let height: u32 = number_argument(&mut values, "kbdlayout", "height");
+        let menu_height: u32 = number_argument(&mut values, "kbdlayout", "menu height");
+        let kind = keymap::Kind::current();
+        let keymaps = keymap::list(kind);
+        if keymaps.is_empty() {
+            writeln!(io::stderr(), "error: couldn't find any keyboard layouts").unwrap();
+            process::exit(1)
+        }
+        let original = keymap::current(kind);
+        let highlighted = keymaps.iter().position(|keymap| {
+            Some(&keymap.name) == original.as_ref()
+        }).unwrap_or(0);
+        let items = keymaps.into_iter().map(|keymap| {
+            MenuItem {
+                tag: keymap.name,
+                item: keymap.description,
+                confirm: None,
+                hotkey: None,
+                separator: false,
+                disabled: false,
+                icon: None,
+            }
+        }).collect();
+        Dialog {
+            width: width,
+            height: height,
+            subdialog: Subdialog::Menu(MenuDialog {
+                text: text.to_string(),
+                menu_height: menu_height,
+                items: items,
+                pending_confirmation: None,
+                highlighted: highlighted,
+                activate_highlighted: false,
+                scroll_to_highlighted: true,
+                filter: String::new(),
+                icons: IconAtlas::new(),
+                keymap: Some(KeymapPreview {
+                    kind: kind,
+                    previewed: original.as_ref().map(|_| highlighted),
+                    original: original,
+                    test: vec![0; MAX_TEXT_LENGTH],
+                }),
+            }),
+            options: options,
+            pending_cancel: None,
+        }
+    }
+
+    // Applies the layout that was chosen, if it's to be applied, or puts back the one there was.
+    fn settle_keymap(&self, exit_code: c_int) -> c_int {
+        let subdialog = match self.subdialog {
+            Subdialog::Menu(ref subdialog) => subdialog,
+            _ => return exit_code,
+        };
+        let preview = match subdialog.keymap {
+            Some(ref preview) => preview,
+            None => return exit_code,
+        };
+        if exit_code == 0 && self.options.apply_keymap {
+            let name = &subdialog.items[subdialog.highlighted].tag;
+            if let Err(message) = keymap::apply(preview.kind, name) {
+                writeln!(io::stderr(), "error: couldn't apply the layout: {}", message).unwrap();
+                return 1
+            }
+            info!("applied the layout `{}`", name);
+            return exit_code
+        }
+        let original = preview.original.as_ref().and_then(|original| {
+            subdialog.items.iter().position(|item| item.tag == *original)
+        });
+        if preview.previewed != original {
+            if let Some(ref original) = preview.original {
+                if let Err(message) = keymap::preview(original) {
+                    writeln!(io::stderr(), "warning: couldn't put the layout back: {}", message)
+                        .unwrap()
+                }
+            }
+        }
+        exit_code
+    }
+
+    fn pinbox(mut values: Values, options: Options) -> Dialog {
+        let text = next_argument(&mut values, "pinbox", "text");
+        let digits: usize = number_argument(&mut values, "pinbox", "number of digits");
+        if digits == 0 {
+            argument_error("pinbox", "needs at least one digit")
+        }
+        Dialog {
+            width: 0,
+            height: 0,
+            subdialog: Subdialog::Pin(PinDialog {
+                text: text.to_string(),
+                digits: digits,
+                entered: String::new(),
+                keypad: NumericKeypad::new(),
+            }),
+            options: options,
+            pending_cancel: None,
+        }
+    }
+
+    fn menu(mut values: Values, options: Options) -> Dialog {
+        let text = next_argument(&mut values, "menu", "text");
+        let width: u32 = number_argument(&mut values, "menu", "width");
+        let height: u32 = number_argument(&mut values, "menu", "height");
+        let menu_height: u32 = number_argument(&mut values, "menu", "menu height");
+
+        let mut items = vec![];
+        let mut icons = IconAtlas::new();
+        loop {
+            let tag = match values.next() {
+                Some(tag) => tag,
+                None => break,
+            };
+            let item = if options.no_items {
+                ""
+            } else {
+                match values.next() {
+                    Some(item) => item,
+                    None => {
+                        argument_error("menu",
+                                       "needs tag/item pairs; got an odd number of arguments")
+                    }
+                }
+            };
+            let (item, attributes) = parse_item_attributes(item);
+            let mut confirm = None;
+            let mut disabled = false;
+            let mut icon = None;
+            for (key, value) in attributes {
+                match &key[..] {
+                    "confirm" => confirm = Some(value),
+                    "disabled" => disabled = value == "true",
+                    "icon" => {
+                        match icons.load(&value) {
+                            Ok(loaded) => icon = Some(loaded),
+                            Err(message) => {
+                                argument_error("menu", &format!("has a problem: {}", message))
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            let (tag, hotkey) = if options.hotkeys {
+                parse_mnemonic(tag)
+            } else {
+                (tag.to_string(), None)
+            };
+            items.push(MenuItem {
+                separator: tag == "---",
+                tag: tag,
+                item: item,
+                confirm: confirm,
+                hotkey: hotkey,
+                disabled: disabled,
+                icon: icon,
+            })
+        }
+        // Groups mean nothing once the items are sorted.
+        if options.sort_menu {
+            items.retain(|item| !item.separator);
+            items.sort_by(|a, b| options.collation.compare(a.text(&options), b.text(&options)))
+        }
+        if options.hotkeys {
+            let mut free = MENU_HOTKEYS.chars().filter(|&key| {
+                !items.iter().any(|item| item.hotkey == Some(key))
+            }).collect::<Vec<_>>().into_iter();
+            for item in &mut items {
+                if item.hotkey.is_none() && item.choosable() {
+                    item.hotkey = free.next()
+                }
+            }
+        }
+        let highlighted = items.iter().position(|item| item.choosable()).unwrap_or(0);
+
+        Dialog {
+            width: width,
+            height: height,
+            subdialog: Subdialog::Menu(MenuDialog {
+                text: text.to_string(),
+                menu_height: menu_height,
+                items: items,
+                pending_confirmation: None,
+                highlighted: highlighted,
+                activate_highlighted: false,
+                scroll_to_highlighted: false,
+                filter: String::new(),
+                icons: icons,
+                keymap: None,
+            }),
+            options: options,
+            pending_cancel: None,
+        }
+    }
+}
+
+#[allow(dead_code)]
+struct Renderer {
+    standard_font: *mut ImFont,
+    label_font: *mut ImFont,
+    texture: c_uint,
+    vertex_shader: Shader,
+    fragment_shader: Shader,
+    program: c_uint,
+    u_window_size: c_int,
+    u_texture: c_int,
+    a_position: c_int,
+    a_texture_uv: c_int,
+    a_color: c_int,
+    vbo: c_uint,
+    last_frame_time: Cell<Instant>,
+    cancel_requested: Cell<bool>,
+    locale: Locale,
+    focus: RefCell<FocusTracker>,
+    // How far below its place the dialog is drawn, as a fraction of the screen height.
+    slide: Cell<f32>,
+    // How far right and down the dialog is drawn for `--pixel-shift`, in framebuffer pixels.
+    shift: Cell<(c_int, c_int)>,
+    dimmed: Cell<bool>,
+    background_color: (f32, f32, f32),
+    // The texture, width and height of `--background` when it's an image.
+    background_image: Option<(c_uint, u32, u32)>,
+    // Times the GPU's work on each frame, from the first draw call until it's on the screen.
+    watchdog: Option<Watchdog>,
+}
+
+impl Renderer {
+    fn new(base_directories: &BaseDirectories, locale: Locale, focus: FocusTracker) -> Renderer {
+        unsafe {
+            let io = imgui::igGetIO();
+            let data_file_path = get_data_file_path(FONT_FILENAME, base_directories).to_str()
+                                                                                    .unwrap()
+                                                                                    .to_string();
+            let data_file_path = CString::new(data_file_path).unwrap();
+            let glyph_ranges = if locale.rtl() { RTL_GLYPH_RANGES.as_ptr() } else { ptr::null() };
+            let standard_font = imgui::ImFontAtlas_AddFontFromFileTTF((*io).fonts,
+                                                                      data_file_path.as_ptr(),
+                                                                      STANDARD_FONT_SIZE,
+                                                                      ptr::null(),
+                                                                      glyph_ranges);
+            let label_font = imgui::ImFontAtlas_AddFontFromFileTTF((*io).fonts,
+                                                                   data_file_path.as_ptr(),
+                                                                   LABEL_FONT_SIZE,
+                                                                   ptr::null(),
+                                                                   glyph_ranges);
+            if standard_font.is_null() || label_font.is_null() {
+                writeln!(io::stderr(),
+                         "warning: couldn't load the font `{}`",
+                         data_file_path.to_string_lossy()).unwrap()
+            } else {
+                info!("loaded the font `{}` at {} and {} pixels{}",
+                      data_file_path.to_string_lossy(),
+                      STANDARD_FONT_SIZE,
+                      LABEL_FONT_SIZE,
+                      if locale.rtl() { ", with Arabic and Hebrew glyphs" } else { "" })
+            }
+
+            init_keys();
+            let texture = init_texture();
+
+            let vertex_shader = Shader::new(VERTEX_SHADER_FILENAME,
+                                            gl::VERTEX_SHADER,
+                                            base_directories);
+            let fragment_shader = Shader::new(FRAGMENT_SHADER_FILENAME,
+                                              gl::FRAGMENT_SHADER,
+                                              base_directories);
+            let program = gl::CreateProgram();
+            gl::AttachShader(program, vertex_shader.0);
+            gl::AttachShader(program, fragment_shader.0);
+            gl::LinkProgram(program);
+            gl::UseProgram(program);
+
+            let u_window_size =
+                gl::GetUniformLocation(program,
+                                       b"uWindowSize\0" as *const c_uchar as *const c_char);
+            let u_texture =
+                gl::GetUniformLocation(program, b"uTexture\0" as *const c_uchar as *const c_char);
+            let a_position =
+                gl::GetAttribLocation(program, b"aPosition\0" as *const c_uchar as *const c_char);
+            let a_texture_uv =
+                gl::GetAttribLocation(program, b"aTextureUV\0" as *const c_uchar as *const c_char);
+            let a_color = gl::GetAttribLocation(program,
+                                                b"aColor\0" as *const c_uchar as *const c_char);
+
+            let mut vbo = 0;
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+            let offset_of = |field_addr| {
+                (field_addr - (&ZERO_VERTEX as *const _ as usize)) as *const c_void
+            };
+
+            gl::VertexAttribPointer(a_position as c_uint,
+                                    2,
+                                    gl::FLOAT,
+                                    gl::FALSE,
+                                    mem::size_of::<ImDrawVert>() as c_int,
+                                    offset_of(&ZERO_VERTEX.pos as *const _ as usize));
+            gl::VertexAttribPointer(a_texture_uv as c_uint,
+                                    2,
+                                    gl::FLOAT,
+                                    gl::FALSE,
+                                    mem::size_of::<ImDrawVert>() as c_int,
+                                    offset_of(&ZERO_VERTEX.uv as *const _ as usize));
+            gl::VertexAttribPointer(a_color as c_uint,
+                                    4,
+                                    gl::UNSIGNED_BYTE,
+                                    gl::TRUE,
+                                    mem::size_of::<ImDrawVert>() as c_int,
+                                    offset_of(&ZERO_VERTEX.col as *const _ as usize));
+
+            gl::EnableVertexAttribArray(a_position as c_uint);
+            gl::EnableVertexAttribArray(a_texture_uv as c_uint);
+            gl::EnableVertexAttribArray(a_color as c_uint);
+
+            Renderer {
+                standard_font: standard_font,
+                label_font: label_font,
+                texture: texture,
+                vertex_shader: vertex_shader,
+                fragment_shader: fragment_shader,
+                program: program,
+                u_window_size: u_window_size,
+                u_texture: u_texture,
+                a_position: a_position,
+                a_texture_uv: a_texture_uv,
+                a_color: a_color,
+                vbo: vbo,
+                last_frame_time: Cell::new(Instant::now()),
+                cancel_requested: Cell::new(false),
+                locale: locale,
+                focus: RefCell::new(focus),
+                slide: Cell::new(0.0),
+                shift: Cell::new((0, 0)),
+                dimmed: Cell::new(false),
+                background_color: (0.0, 0.0, 0.0),
+                background_image: None,
+                watchdog: None,
+            }
+        }
+    }
+
+    fn set_background(&mut self, background: &Background) -> Result<(), String> {
+        let path = match *background {
+            Background::Color(red, green, blue) => {
+                self.background_color = (red, green, blue);
+                return Ok(())
+            }
+            Background::Image(ref path) => path,
+        };
+        let (width, height, pixels) = png::read(path).map_err(|error| {
+            format!("couldn't load `{}`: {}", path.display(), error)
+        })?;
+        if width > MAX_BACKGROUND_SIZE || height > MAX_BACKGROUND_SIZE {
+            return Err(format!("`{}` is bigger than {}x{}",
+                               path.display(),
+                               MAX_BACKGROUND_SIZE,
+                               MAX_BACKGROUND_SIZE))
+        }
+        unsafe {
+            let mut texture = 0;
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as c_int);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as c_int);
+            gl::TexImage2D(gl::TEXTURE_2D, 0,
+                           gl::RGBA as c_int,
+                           width as c_int, height as c_int,
+                           0,
+                           gl::RGBA,
+                           gl::UNSIGNED_BYTE,
+                           pixels.as_ptr() as *const c_void);
+            self.background_image = Some((texture, width, height))
+        }
+        Ok(())
+    }
+
+    // Covers the screen with the background image, as one quad through the imgui shaders.
+    fn draw_background_image(&self) {
+        let (texture, image_width, image_height) = match self.background_image {
+            Some(image) => image,
+            None => return,
+        };
+        let (screen_width, screen_height) = (FRAMEBUFFER_WIDTH as f32, FRAMEBUFFER_HEIGHT as f32);
+        // Whichever way the image is too big for the screen's shape gets cropped.
+        let scale = (screen_width / image_width as f32).max(screen_height / image_height as f32);
+        let u = (1.0 - screen_width / (image_width as f32 * scale)) / 2.0;
+        let v = (1.0 - screen_height / (image_height as f32 * scale)) / 2.0;
+        self.draw_screen_quad(texture,
+                              ImVec2 { x: u, y: v },
+                              ImVec2 { x: 1.0 - u, y: 1.0 - v },
+                              0xffffffff)
+    }
+
+    // Darkens everything drawn so far, for `--dim-schedule` and `--idle-dim`.
+    fn draw_dimming(&self) {
+        if !self.dimmed.get() {
+            return
+        }
+        // Any texture will do for a solid color, as long as the texture coordinates land on
+        // something white.
+        let white = unsafe { (*(*imgui::igGetIO()).fonts).tex_uv_white_pixel };
+        let alpha = (DIM_OPACITY * 255.0) as ImU32;
+        self.draw_screen_quad(self.texture, white, white, alpha << 24)
+    }
+
+    // Fills the screen with the part of `texture` from `uv_min` to `uv_max`, times `color`,
+    // through the imgui shaders.
+    fn draw_screen_quad(&self, texture: c_uint, uv_min: ImVec2, uv_max: ImVec2, color: ImU32) {
+        let (screen_width, screen_height) = (FRAMEBUFFER_WIDTH as f32, FRAMEBUFFER_HEIGHT as f32);
+        let vertex = |x: f32, y: f32, u: f32, v: f32| {
+            ImDrawVert {
+                pos: ImVec2 { x: x, y: y },
+                uv: ImVec2 { x: u, y: v },
+                col: color,
+            }
+        };
+        let (top_left, top_right) = (vertex(0.0, 0.0, uv_min.x, uv_min.y),
+                                     vertex(screen_width, 0.0, uv_max.x, uv_min.y));
+        let (bottom_left, bottom_right) = (vertex(0.0, screen_height, uv_min.x, uv_max.y),
+                                           vertex(screen_width, screen_height, uv_max.x, uv_max.y));
+        let vertices = [top_left, bottom_left, top_right, top_right, bottom_left, bottom_right];
+        unsafe {
+            gl::UseProgram(self.program);
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::Uniform2f(self.u_window_size, screen_width, screen_height);
+            gl::Uniform1i(self.u_texture, 0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(gl::ARRAY_BUFFER,
+                           mem::size_of_val(&vertices) as intptr_t,
+                           vertices.as_ptr() as *const c_void,
+                           gl::DYNAMIC_DRAW);
+            gl::DrawArrays(gl::TRIANGLES, 0, vertices.len() as c_int)
+        }
+    }
+
+    // In right-to-left mode, moves the cursor so that an item of the given width ends at the
+    // right edge of the window.
+    fn align_right(&self, width: f32) {
+        if !self.locale.rtl() {
+            return
+        }
+        unsafe {
+            let mut region_max = ZERO_SIZE;
+            imgui::igGetWindowContentRegionMax(&mut region_max);
+            let mut cursor = ZERO_SIZE;
+            imgui::igGetCursorPos(&mut cursor);
+            imgui::igSetCursorPosX(cursor.x.max(region_max.x - width))
+        }
+    }
+
+    fn text_width(&self, text: &CStr) -> f32 {
+        unsafe {
+            let mut size = ZERO_SIZE;
+            imgui::igCalcTextSize(&mut size, text.as_ptr(), ptr::null(), false, -1.0);
+            size.x
+        }
+    }
+
+    fn text(&self, color: Option<ImVec4>, text: &str) {
+        let text = bidi::visual(text, self.locale.rtl());
+        let lines = if self.locale.rtl() { text.split('\n').collect() } else { vec![&text[..]] };
+        for line in lines {
+            let line = CString::new(line).unwrap();
+            self.align_right(self.text_width(&line));
+            unsafe {
+                match color {
+                    Some(color) => {
+                        imgui::igTextColored(color,
+                                             b"%s\0" as *const c_uchar as *const c_char,
+                                             line.as_ptr())
+                    }
+                    None => {
+                        imgui::igText(b"%s\0" as *const c_uchar as *const c_char, line.as_ptr())
+                    }
+                }
+            }
+        }
+    }
+
+    // Draws `text` inside the field that was just drawn, where its own text would start.
+    fn hint(&self, text: &str) {
+        let text = CString::new(bidi::visual(text, self.locale.rtl())).unwrap();
+        unsafe {
+            let padding = (*imgui::igGetStyle()).frame_padding;
+            let (mut min, mut max) = (ZERO_SIZE, ZERO_SIZE);
+            imgui::igGetItemRectMin(&mut min);
+            imgui::igGetItemRectMax(&mut max);
+            let x = if self.locale.rtl() {
+                max.x - padding.x - self.text_width(&text)
+            } else {
+                min.x + padding.x
+            };
+            let position = ImVec2 {
+                x: x,
+                y: min.y + padding.y,
+            };
+            imgui::ImDrawList_AddText(imgui::igGetWindowDrawList(),
+                                      position,
+                                      imgui::igGetColorU32(ImGuiCol::TextDisabled, 1.0),
+                                      text.as_ptr(),
+                                      ptr::null());
+        }
+    }
+
+    // A bar the width of a button, filled and colored by `strength`, with its label under it.
+    fn strength_meter(&self, strength: f32) {
+        let label = self.locale.translate(password::strength_label(strength));
+        let color = match password::strength_label(strength) {
+            "Weak" => ERROR_COLOR,
+            "Fair" => FAIR_COLOR,
+            _ => GOOD_COLOR,
+        };
+        unsafe {
+            let size = ImVec2 {
+                x: button_size().x,
+                y: imgui::igGetTextLineHeight() * 0.5,
+            };
+            let mut min = ZERO_SIZE;
+            imgui::igGetCursorScreenPos(&mut min);
+            let max = ImVec2 {
+                x: min.x + size.x,
+                y: min.y + size.y,
+            };
+            // Right-to-left, the bar fills from the right.
+            let filled = size.x * strength;
+            let (fill_min, fill_max) = if self.locale.rtl() {
+                (ImVec2 { x: max.x - filled, y: min.y }, max)
+            } else {
+                (min, ImVec2 { x: min.x + filled, y: max.y })
+            };
+            let draw_list = imgui::igGetWindowDrawList();
+            imgui::ImDrawList_AddRectFilled(draw_list,
+                                            min,
+                                            max,
+                                            imgui::igGetColorU32(ImGuiCol::FrameBg, 1.0),
+                                            0.0,
+                                            0);
+            imgui::ImDrawList_AddRectFilled(draw_list,
+                                            fill_min,
+                                            fill_max,
+                                            imgui::igColorConvertFloat4ToU32(color),
+                                            0.0,
+                                            0);
+            imgui::igDummy(&size);
+        }
+        self.text(Some(color), label);
+        self.focus.borrow_mut().text(label)
+    }
+
+    // A box, filled once `rule` is kept to, and the rule beside it.
+    fn checklist_item(&self, rule: &str, kept: bool) {
+        let text = CString::new(bidi::visual(rule, self.locale.rtl())).unwrap();
+        unsafe {
+            let box_size = imgui::igGetTextLineHeight();
+            let spacing = (*imgui::igGetStyle()).item_spacing.x;
+            let width = box_size + spacing + self.text_width(&text);
+            self.align_right(width);
+            let mut position = ZERO_SIZE;
+            imgui::igGetCursorScreenPos(&mut position);
+            let (box_x, text_x) = if self.locale.rtl() {
+                (position.x + width - box_size, position.x)
+            } else {
+                (position.x, position.x + box_size + spacing)
+            };
+            let inset = box_size * 0.2;
+            let box_min = ImVec2 {
+                x: box_x + inset,
+                y: position.y + inset,
+            };
+            let box_max = ImVec2 {
+                x: box_x + box_size - inset,
+                y: position.y + box_size - inset,
+            };
+            let draw_list = imgui::igGetWindowDrawList();
+            if kept {
+                imgui::ImDrawList_AddRectFilled(draw_list,
+                                                box_min,
+                                                box_max,
+                                                imgui::igColorConvertFloat4ToU32(GOOD_COLOR),
+                                                0.0,
+                                                0)
+            } else {
+                imgui::ImDrawList_AddRect(draw_list,
+                                          box_min,
+                                          box_max,
+                                          imgui::igColorConvertFloat4ToU32(ERROR_COLOR),
+                                          0.0,
+                                          0,
+                                          1.0)
+            }
+            imgui::ImDrawList_AddText(draw_list,
+                                      ImVec2 {
+                                          x: text_x,
+                                          y: position.y,
+                                      },
+                                      imgui::igGetColorU32(ImGuiCol::Text, 1.0),
+                                      text.as_ptr(),
+                                      ptr::null());
+            imgui::igDummy(&ImVec2 {
+                x: width,
+                y: box_size,
+            });
+        }
+        let state = if kept { "checked" } else { "unchecked" };
+        self.focus.borrow_mut().item(Role::Checkbox, rule, Some(state))
+    }
+
+    // A row of `digits` boxes, the first `filled` of them with a dot in, across at most a button's
+    // width.
+    fn pin_slots(&self, label: &str, digits: usize, filled: usize) {
+        unsafe {
+            let spacing = (*imgui::igGetStyle()).item_spacing.x;
+            let most = (button_size().x - spacing * (digits - 1) as f32) / digits as f32;
+            let slot_size = (imgui::igGetTextLineHeight() * 1.5).min(most);
+            let width = slot_size * digits as f32 + spacing * (digits - 1) as f32;
+            self.align_right(width);
+            let mut position = ZERO_SIZE;
+            imgui::igGetCursorScreenPos(&mut position);
+            let draw_list = imgui::igGetWindowDrawList();
+            let color = imgui::igGetColorU32(ImGuiCol::Text, 1.0);
+            for slot in 0..digits {
+                // Right-to-left, the slots fill from the right.
+                let index = if self.locale.rtl() { digits - 1 - slot } else { slot };
+                let min = ImVec2 {
+                    x: position.x + (slot_size + spacing) * index as f32,
+                    y: position.y,
+                };
+                let max = ImVec2 {
+                    x: min.x + slot_size,
+                    y: min.y + slot_size,
+                };
+                imgui::ImDrawList_AddRect(draw_list, min, max, color, 0.0, 0, 1.0);
+                if slot < filled {
+                    let centre = ImVec2 {
+                        x: min.x + slot_size * 0.5,
+                        y: min.y + slot_size * 0.5,
+                    };
+                    imgui::ImDrawList_AddCircleFilled(draw_list, centre, slot_size * 0.2, color, 12)
+                }
+            }
+            imgui::igDummy(&ImVec2 {
+                x: width,
+                y: slot_size,
+            });
+        }
+        self.focus.borrow_mut().item(Role::PasswordEntry, label, None)
+    }
+
+    // Text that screen reader users need to hear, as opposed to labels attached to widgets.
+    fn prompt(&self, text: &str) {
+        self.text(None, text);
+        self.focus.borrow_mut().text(text)
+    }
+
+    // `description` is what a screen reader announces for the item.
+    fn selectable(&self, label: &str, description: &str, selected: bool) -> bool {
+        self.selectable_with_flags(label, description, selected, ImGuiSelectableFlags::empty())
+    }
+
+    fn selectable_with_flags(&self,
+                             label: &str,
+                             description: &str,
+                             selected: bool,
+                             flags: ImGuiSelectableFlags)
+                             -> bool {
+        let text = CString::new(bidi::visual(label, self.locale.rtl())).unwrap();
+        // imgui always draws selectable labels on the left, so in right-to-left mode draw an
+        // empty one and put the text over it.
+        let id = if self.locale.rtl() {
+            CString::new(format!("##{}", label)).unwrap()
+        } else {
+            text.clone()
+        };
+        unsafe {
+            let clicked = imgui::igSelectable(id.as_ptr(), selected, flags, ZERO_SIZE);
+            let state = if selected { Some("selected") } else { None };
+            self.focus.borrow_mut().item(Role::Item, description, state);
+            if self.locale.rtl() {
+                imgui::igSameLine(0.0, 0.0);
+                self.align_right(self.text_width(&text));
+                imgui::igText(b"%s\0" as *const c_uchar as *const c_char, text.as_ptr());
+            }
+            clicked
+        }
+    }
+
+    fn button(&self, label: &str, size: ImVec2, state: Option<&'static str>) -> bool {
+        self.align_right(size.x);
+        let c_label = CString::new(bidi::visual(label, self.locale.rtl())).unwrap();
+        let clicked = unsafe { imgui::igButton(c_label.as_ptr(), size) };
+        self.focus.borrow_mut().item(Role::Button, label, state);
+        clicked
+    }
+
+    // What the Cancel button does. With `--confirm-cancel`, `render` asks first.
+    fn cancel(&self, options: &Options, exit_code: &mut Option<c_int>) {
+        if options.confirm_cancel {
+            self.cancel_requested.set(true)
+        } else {
+            *exit_code = Some(1)
+        }
+    }
+
+    fn ok_cancel_button(&self,
+                        ok_enabled: bool,
+                        options: &Options,
+                        exit_code: &mut Option<c_int>) {
+        unsafe {
+            let button_size = button_size();
+            if !ok_enabled {
+                imgui::igPushStyleColor(ImGuiCol::Text, LABEL_COLOR);
+            }
+            let state = if ok_enabled { None } else { Some("disabled") };
+            if self.button(self.locale.translate("OK"), button_size, state) && ok_enabled {
+                *exit_code = Some(0)
+            }
+            if !ok_enabled {
+                imgui::igPopStyleColor(1);
+            }
+            if !options.no_cancel &&
+                    self.button(self.locale.translate("Cancel"), button_size, None) {
+                self.cancel(options, exit_code)
+            }
+        }
+    }
+
+    fn render_file_dialog(&self,
+                          subdialog: &mut FileDialog,
+                          options: &Options,
+                          exit_code: &mut Option<c_int>) {
+        subdialog.receive_entries(Some(Duration::from_secs(0)));
+        subdialog.refresh_if_changed();
+        subdialog.refresh_devices_if_changed();
+        unsafe {
+            let mut destination = None;
+            if !subdialog.devices.is_empty() {
+                self.text(Some(LABEL_COLOR), self.locale.translate("Devices"));
+            }
+            for device in &subdialog.devices {
+                let label = match device.free {
+                    Some(free) => {
+                        let free = filter::format_size(free, &self.locale);
+                        let free = self.locale.translate("{} free").replace("{}", &free);
+                        format!("{} ({})", device.label, free)
+                    }
+                    None => device.label.clone(),
+                };
+                let description = format!("{}, {}", label, device.path.display());
+                if self.selectable(&label, &description, false) {
+                    destination = Some(device.path.clone())
+                }
+            }
+            if !subdialog.devices.is_empty() {
+                imgui::igSeparator()
+            }
+
+            if !subdialog.recent_files.is_empty() {
+                self.text(Some(LABEL_COLOR), self.locale.translate("Recent"));
+            }
+            for recent_file in &subdialog.recent_files {
+                let label = recent_file.display().to_string();
+                if self.selectable(&label, &label, false) {
+                    subdialog.chosen_file = Some(recent_file.clone());
+                    *exit_code = Some(0)
+                }
+            }
+            if !subdialog.recent_files.is_empty() {
+                imgui::igSeparator()
+            }
+
+            if !subdialog.places.is_empty() {
+                self.text(Some(LABEL_COLOR), self.locale.translate("Places"));
+            }
+            for place in &subdialog.places {
+                let description = format!("{}, {}", place.label, place.path.display());
+                if self.selectable(&place.label, &description, false) && place.path.is_dir() {
+                    destination = Some(place.path.clone())
+                }
+            }
+            if !subdialog.places.is_empty() {
+                imgui::igSeparator()
+            }
+            for bookmark in &subdialog.bookmarks {
+                let label = bookmark.display().to_string();
+                if self.selectable(&label, &label, false) && bookmark.is_dir() {
+                    destination = Some(bookmark.clone())
+                }
+            }
+            if !subdialog.bookmarks.is_empty() {
+                imgui::igSeparator()
+            }
+            if let Some(destination) = destination {
+                subdialog.change_directory(destination)
+            }
+
+            if let Some(ancestor) = self.render_breadcrumbs(&subdialog.path, subdialog.root()) {
+                subdialog.change_directory(ancestor)
+            }
+
+            imgui::igPushItemWidth(button_size().x);
+            // A typed path only makes sense when choosing one file.
+            let mut location_entered = false;
+            if !options.fselect_multi {
+                location_entered =
+                    imgui::igInputText(b"##location\0" as *const c_uchar as *const c_char,
+                                       subdialog.location.as_mut_ptr() as *mut c_char,
+                                       subdialog.location.len(),
+                                       imgui::ImGuiInputTextFlags_EnterReturnsTrue |
+                                       imgui::ImGuiInputTextFlags_CallbackCompletion,
+                                       Some(complete_location),
+                                       &*subdialog as *const FileDialog as *mut c_void);
+                if imgui::igIsItemActive() {
+                    subdialog.list_focused = false
+                }
+                let label = self.locale.translate("Location");
+                self.focus.borrow_mut().item(Role::Entry, label, None);
+                if let Some(ref error) = subdialog.location_error {
+                    self.text(Some(ERROR_COLOR), error);
+                }
+            }
+
+            if let Some(ref loader) = subdialog.loader {
+                let frame = duration_to_seconds(loader.elapsed()) * SPINNER_FRAMES_PER_SECOND;
+                let label = self.locale.translate("Loading...");
+                let spinner = SPINNER[frame as usize % SPINNER.len()];
+                self.text(Some(LABEL_COLOR), &format!("{} {}", label, spinner));
+                self.focus.borrow_mut().text(label)
+            }
+            if !subdialog.search.is_empty() {
+                let search = format!("{}: {}", self.locale.translate("Search"), subdialog.search);
+                self.text(Some(LABEL_COLOR), &search);
+                self.focus.borrow_mut().text(&search)
+            }
+
+            // Drawn by hand rather than with igListBox() so the preselected entry can be scrolled
+            // into view.
+            let mut activated = mem::replace(&mut subdialog.activate_selection, false);
+            let mut list_size = ZERO_SIZE;
+            let rows = options.list_height.map_or(subdialog.list_rows, |rows| rows as c_int);
+            if imgui::igListBoxHeader2(b"\0" as *const c_uchar as *const c_char,
+                                       subdialog.entries.entries.len() as c_int,
+                                       rows) {
+                if options.details {
+                    let width = button_size().x;
+                    imgui::igColumns(3, b"details\0" as *const c_uchar as *const c_char, false);
+                    imgui::igSetColumnOffset(1, width * SIZE_COLUMN_START);
+                    imgui::igSetColumnOffset(2, width * MODIFIED_COLUMN_START);
+                }
+                for index in 0..subdialog.entries.entries.len() {
+                    let selected = index as c_int == subdialog.entries.index;
+                    imgui::igPushIdInt(index as c_int);
+                    let entry = subdialog.entries.entries[index].label.clone();
+                    let eligible = subdialog.reason(index).is_none();
+                    let first_entry = subdialog.entries.first_entry;
+                    if options.fselect_multi && index >= first_entry && !entry.ends_with('/') {
+                        let mut checked = subdialog.entries.entries[index].checked;
+                        if imgui::igCheckbox(b"##checked\0" as *const c_uchar as *const c_char,
+                                             &mut checked) {
+                            subdialog.toggle_checked(index)
+                        }
+                        let state = if checked { "checked" } else { "unchecked" };
+                        self.focus.borrow_mut().item(Role::Checkbox, &entry, Some(state));
+                        imgui::igSameLine(0.0, -1.0);
+                    }
+                    if !eligible {
+                        imgui::igPushStyleColor(ImGuiCol::Text, LABEL_COLOR);
+                    }
+                    let flags = if options.details {
+                        imgui::ImGuiSelectableFlags_SpanAllColumns
+                    } else {
+                        ImGuiSelectableFlags::empty()
+                    };
+                    let label = match subdialog.entries.entries[index].details.link {
+                        Some(ref target) => format!("{} -> {}", entry, target.display()),
+                        None => entry.clone(),
+                    };
+                    if self.selectable_with_flags(&label, &label, selected, flags) {
+                        subdialog.entries.index = index as c_int;
+                        activated = true
+                    }
+                    if options.details {
+                        let details = &subdialog.entries.entries[index].details;
+                        imgui::igNextColumn();
+                        if !details.is_dir {
+                            self.text(None, &filter::format_size(details.size, &self.locale));
+                        }
+                        imgui::igNextColumn();
+                        if index >= first_entry {
+                            if let Some(modified) = details.modified {
+                                self.text(None, &self.locale.format_date_time(modified));
+                            }
+                        }
+                        imgui::igNextColumn();
+                    }
+                    if !eligible {
+                        imgui::igPopStyleColor(1);
+                    }
+                    if selected && subdialog.scroll_to_selection {
+                        imgui::igSetScrollHere(0.5)
+                    }
+                    imgui::igPopId()
+                }
+                if options.details {
+                    imgui::igColumns(1, ptr::null(), false);
+                }
+                if let Some(scroll) = subdialog.restored_scroll.take() {
+                    imgui::igSetScrollY(scroll)
+                }
+                subdialog.scroll = imgui::igGetScrollY();
+                if imgui::igIsWindowHovered() && imgui::igIsMouseClicked(0, false) {
+                    subdialog.list_focused = true
+                }
+                imgui::igListBoxFooter();
+                imgui::igGetItemRectSize(&mut list_size);
+            }
+            subdialog.scroll_to_selection = false;
+            if activated {
+                if subdialog.entries.first_entry == 1 && subdialog.entries.index == 0 {
+                    let parent = subdialog.path.parent().unwrap().to_owned();
+                    subdialog.change_directory(parent)
+                } else if let Some((selected_path, file_type)) = subdialog.selected_path() {
+                    match file_type {
+                        SelectedFileType::File if options.fselect_multi => {
+                            let index = subdialog.entries.index as usize;
+                            subdialog.toggle_checked(index)
+                        }
+                        SelectedFileType::File => {
+                            if subdialog.reason(subdialog.entries.index as usize).is_none() {
+                                *exit_code = Some(0)
+                            }
+                        }
+                        SelectedFileType::Directory => subdialog.enter_directory(selected_path),
+                    }
+                }
+            }
+            igPopItemWidth();
+            let reason = subdialog.reason(subdialog.entries.index as usize).map(|reason| {
+                reason.to_string()
+            });
+            if let Some(ref reason) = reason {
+                self.text(Some(ERROR_COLOR), reason);
+                self.focus.borrow_mut().text(reason)
+            }
+            let bookmark_label = self.locale.translate("Bookmark this directory");
+            if !subdialog.bookmarks.contains(&subdialog.path) &&
+                    self.button(bookmark_label, button_size(), None) {
+                if let Err(error) = bookmarks::add(&subdialog.path) {
+                    writeln!(io::stderr(), "warning: couldn't save bookmark: {}", error).unwrap()
+                }
+                subdialog.bookmarks.push(subdialog.path.clone())
+            }
+            self.render_new_directory(subdialog);
+            self.ok_cancel_button(reason.is_none(), options, exit_code);
+            if location_entered || (*exit_code == Some(0) && subdialog.location_edited()) {
+                *exit_code = subdialog.enter_location()
+            }
+            if options.list_height.is_none() {
+                self.fit_file_list(subdialog, list_size.y)
+            }
+            if *exit_code == Some(0) && options.save && subdialog.chosen_path().exists() {
+                let path = subdialog.chosen_path();
+                let prompt = {
+                    let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+                    self.locale.translate("Overwrite {}?").replace("{}", &name)
+                };
+                subdialog.pending_overwrite = Some((path, Confirmation::new(prompt)));
+                *exit_code = None
+            }
+            if *exit_code == Some(0) {
+                subdialog.print_chosen_paths(options)
+            }
+        }
+
+        let answer = match subdialog.pending_overwrite {
+            Some((_, ref mut confirmation)) => self.confirm(confirmation),
+            None => None,
+        };
+        match answer {
+            Some(true) => {
+                subdialog.chosen_file = subdialog.pending_overwrite.take().map(|(path, _)| path);
+                subdialog.print_chosen_paths(options);
+                *exit_code = Some(0)
+            }
+            Some(false) => subdialog.pending_overwrite = None,
+            None => {}
+        }
+    }
+
+    // Picks as many rows as will make the dialog as tall as it was asked to be, or as tall as the
+    // screen for a height of 0, given how much room everything but the list took this frame. Called
+    // once the whole dialog has been laid out.
+    fn fit_file_list(&self, subdialog: &mut FileDialog, list_height: f32) {
+        unsafe {
+            let style = &*imgui::igGetStyle();
+            let row_height = imgui::igGetTextLineHeightWithSpacing();
+            let screen_height = (*imgui::igGetIO()).display_size.y - style.window_padding.y * 2.0;
+            let dialog_height = match subdialog.height {
+                0 => screen_height,
+                lines => (lines as f32 * row_height).min(screen_height),
+            };
+            let rest = imgui::igGetCursorPosY() + style.window_padding.y - list_height;
+            let rows = (dialog_height - rest - style.frame_padding.y * 2.0) / row_height - 0.4;
+            subdialog.list_rows = (rows as c_int).max(MIN_LIST_HEIGHT)
+        }
+    }
+
+    // One button for each directory from the root down to `path`, wrapping to fit. Returns the one
+    // clicked, if any.
+    fn render_breadcrumbs(&self, path: &Path, root: Option<&Path>) -> Option<PathBuf> {
+        let mut ancestors: Vec<&Path> = path.ancestors().filter(|ancestor| {
+            root.map_or(true, |root| ancestor.starts_with(root))
+        }).collect();
+        ancestors.reverse();
+        let mut clicked = None;
+        unsafe {
+            let style = &*imgui::igGetStyle();
+            let mut line_width = 0.0;
+            for (index, ancestor) in ancestors.iter().enumerate() {
+                let label = match ancestor.file_name() {
+                    Some(name) => name.to_string_lossy().into_owned(),
+                    None => ancestor.display().to_string(),
+                };
+                let c_label = CString::new(label).unwrap();
+                let width = self.text_width(&c_label) + style.frame_padding.x * 2.0;
+                if index > 0 && line_width + style.item_spacing.x + width <= button_size().x {
+                    imgui::igSameLine(0.0, -1.0);
+                    line_width += style.item_spacing.x + width
+                } else {
+                    line_width = width
+                }
+                imgui::igPushIdInt(index as c_int);
+                if imgui::igSmallButton(c_label.as_ptr()) && *ancestor != path {
+                    clicked = Some(ancestor.to_path_buf())
+                }
+                imgui::igPopId();
+                self.focus.borrow_mut().item(Role::Button, &ancestor.display().to_string(), None);
+            }
+        }
+        clicked
+    }
+
+    fn render_new_directory(&self, subdialog: &mut FileDialog) {
+        let created = match subdialog.new_directory {
+            None => {
+                if self.button(self.locale.translate("New folder..."), button_size(), None) {
+                    subdialog.new_directory = Some(vec![0; MAX_TEXT_LENGTH]);
+                    subdialog.new_directory_error = None;
+                    subdialog.focus_new_directory = true
+                }
+                return
+            }
+            Some(ref mut buffer) => unsafe {
+                // Takes the focus from the location field, which would otherwise get it.
+                if mem::replace(&mut subdialog.focus_new_directory, false) {
+                    imgui::igSetKeyboardFocusHere(0)
+                }
+                imgui::igPushItemWidth(button_size().x);
+                let entered =
+                    imgui::igInputText(b"##new-directory\0" as *const c_uchar as *const c_char,
+                                       buffer.as_mut_ptr() as *mut c_char,
+                                       buffer.len(),
+                                       imgui::ImGuiInputTextFlags_EnterReturnsTrue,
+                                       None,
+                                       ptr::null_mut());
+                if imgui::igIsItemActive() {
+                    subdialog.list_focused = false
+                }
+                igPopItemWidth();
+                entered
+            },
+        };
+        self.focus.borrow_mut().item(Role::Entry, self.locale.translate("New folder..."), None);
+        if let Some(ref error) = subdialog.new_directory_error {
+            self.text(Some(ERROR_COLOR), error);
+        }
+        if self.button(self.locale.translate("Create"), button_size(), None) || created {
+            subdialog.create_directory()
+        }
+    }
+
+    fn render_input_dialog(&self,
+                           subdialog: &mut InputDialog,
+                           options: &Options,
+                           exit_code: &mut Option<c_int>) {
         unsafe {
-            let io = imgui::igGetIO();
-            let data_file_path = get_data_file_path(FONT_FILENAME, base_directories).to_str()
-                                                                                    .unwrap()
-                                                                                    .to_string();
-            let data_file_path = CString::new(data_file_path).unwrap();
-            let standard_font = imgui::ImFontAtlas_AddFontFromFileTTF((*io).fonts,
-                                                                      data_file_path.as_ptr(),
-                                                                      STANDARD_FONT_SIZE,
-                                                                      ptr::null(),
-                                                                      ptr::null());
-            let label_font = imgui::ImFontAtlas_AddFontFromFileTTF((*io).fonts,
-                                                                   data_file_path.as_ptr(),
-                                                                   LABEL_FONT_SIZE,
-                                                                   ptr::null(),
-                                                                   ptr::null());
+            self.prompt(&subdialog.text);
+            imgui::igPushItemWidth(button_size().x);
+            let data_c_string = subdialog.data.as_mut_ptr() as *mut c_uchar as *mut c_char;
+            let mut flags = imgui::ImGuiInputTextFlags_EnterReturnsTrue;
+            if subdialog.password && !subdialog.revealed {
+                flags = flags | imgui::ImGuiInputTextFlags_Password
+            }
+            let mut callback: imgui::ImGuiTextEditCallback = None;
+            if subdialog.cursor_placement.is_some() {
+                flags = flags | imgui::ImGuiInputTextFlags_CallbackAlways;
+                callback = Some(place_cursor)
+            }
+            let user_data = subdialog as *mut InputDialog as *mut c_void;
+            imgui::igPushIdInt(subdialog.generation);
+            // Enter starts a new line in a multiline box, and Ctrl+Enter confirms it.
+            let entered = if options.multiline && !subdialog.password {
+                let size = ImVec2 {
+                    x: button_size().x,
+                    y: imgui::igGetTextLineHeight() * MULTILINE_INPUT_LINES +
+                        (*imgui::igGetStyle()).frame_padding.y * 2.0,
+                };
+                imgui::igInputTextMultiline(b"\0" as *const c_uchar as *const c_char,
+                                            data_c_string,
+                                            subdialog.data.len(),
+                                            size,
+                                            flags,
+                                            callback,
+                                            user_data)
+            } else {
+                imgui::igInputText(b"\0" as *const c_uchar as *const c_char,
+                                   data_c_string,
+                                   subdialog.data.len(),
+                                   flags,
+                                   callback,
+                                   user_data)
+            };
+            imgui::igPopId();
+            if let Some(ref hint) = subdialog.hint {
+                if subdialog.entered_text().is_empty() && subdialog.composition.is_empty() {
+                    self.hint(hint)
+                }
+            }
+            let role = if subdialog.password { Role::PasswordEntry } else { Role::Entry };
+            self.focus.borrow_mut().item(role, &subdialog.text, None);
+            let mut confirmation_text = None;
+            let mut entered_confirmation = false;
+            if let Some(ref mut confirmation) = subdialog.confirmation {
+                let label = self.locale.translate("Confirm password");
+                self.text(Some(LABEL_COLOR), label);
+                entered_confirmation =
+                    imgui::igInputText(b"##confirmation\0" as *const c_uchar as *const c_char,
+                                       confirmation.as_mut_ptr() as *mut c_uchar as *mut c_char,
+                                       confirmation.len(),
+                                       flags & !imgui::ImGuiInputTextFlags_CallbackAlways,
+                                       None,
+                                       ptr::null_mut());
+                self.focus.borrow_mut().item(Role::PasswordEntry, label, None);
+                let length = confirmation.iter().position(|&byte| byte == 0)
+                                                .unwrap_or(confirmation.len());
+                confirmation_text = Some(confirmation[..length].to_vec())
+            }
+            // A second field that's still being typed isn't a mismatch yet, though it still
+            // holds back OK.
+            let confirmed = match confirmation_text {
+                Some(ref text) if &text[..] != subdialog.entered_text() => {
+                    if !subdialog.entered_text().starts_with(text) {
+                        let mismatch = self.locale.translate("The passwords don't match");
+                        self.text(Some(ERROR_COLOR), mismatch);
+                        self.focus.borrow_mut().text(mismatch)
+                    }
+                    false
+                }
+                _ => true,
+            };
+            igPopItemWidth();
+            if subdialog.password {
+                let (label, state) = if subdialog.revealed {
+                    ("Hide password", "pressed")
+                } else {
+                    ("Show password", "not pressed")
+                };
+                if self.button(self.locale.translate(label), button_size(), Some(state)) {
+                    subdialog.revealed = !subdialog.revealed
+                }
+            }
+            if !subdialog.composition.is_empty() {
+                self.text(Some(LABEL_COLOR), &subdialog.composition);
+            }
+            if let Some(max_length) = subdialog.max_length {
+                let length = subdialog.entered_text().len();
+                let color = if length >= max_length { ERROR_COLOR } else { LABEL_COLOR };
+                self.text(Some(color), &format!("{}/{}", length, max_length));
+            }
 
-            init_keys();
-            let texture = init_texture();
+            let mut entered_on_keyboard = false;
+            let key_press = match subdialog.keyboard {
+                Some(ref mut keyboard) => keyboard.render(button_size().x),
+                None => None,
+            };
+            match key_press {
+                Some(KeyPress::Character(character)) => subdialog.insert_character(character),
+                Some(KeyPress::Backspace) => subdialog.delete_character(),
+                Some(KeyPress::Enter) if options.multiline => subdialog.insert_character('\n'),
+                Some(KeyPress::Enter) => entered_on_keyboard = true,
+                None => {}
+            }
 
-            let vertex_shader = Shader::new("imgui.vs.glsl", gl::VERTEX_SHADER, base_directories);
-            let fragment_shader = Shader::new("imgui.fs.glsl",
-                                              gl::FRAGMENT_SHADER,
-                                              base_directories);
-            let program = gl::CreateProgram();
-            gl::AttachShader(program, vertex_shader.0);
-            gl::AttachShader(program, fragment_shader.0);
-            gl::LinkProgram(program);
-            gl::UseProgram(program);
+            let mut ok_enabled = confirmed;
+            if subdialog.password && options.strength_meter {
+                let password = String::from_utf8_lossy(subdialog.entered_text()).into_owned();
+                self.strength_meter(password::strength(&password));
+                // Without a policy, the rules are only advice.
+                let advice = PasswordPolicy::advice();
+                let policy = subdialog.policy.as_ref().unwrap_or(&advice);
+                for (rule, kept) in policy.checklist(&password) {
+                    self.checklist_item(&rule, kept);
+                    if !kept && subdialog.policy.is_some() {
+                        ok_enabled = false
+                    }
+                }
+            } else if let Some(ref policy) = subdialog.policy {
+                let password = String::from_utf8_lossy(subdialog.entered_text()).into_owned();
+                let strength = password::strength(&password);
+                let label = CString::new(self.locale.translate(password::strength_label(strength)))
+                                .unwrap();
+                let bar_size = button_size();
+                imgui::igProgressBar(strength, &bar_size, label.as_ptr());
+                for rule in policy.unmet_rules(&password) {
+                    self.text(Some(ERROR_COLOR), &rule);
+                    self.focus.borrow_mut().text(&rule);
+                    ok_enabled = false
+                }
+            }
+            if !subdialog.rules.is_empty() {
+                let text = String::from_utf8_lossy(subdialog.entered_text()).into_owned();
+                for problem in subdialog.rules.iter().filter_map(|rule| rule.problem(&text)) {
+                    self.text(Some(ERROR_COLOR), &problem);
+                    self.focus.borrow_mut().text(&problem);
+                    ok_enabled = false
+                }
+            }
 
-            let u_window_size =
-                gl::GetUniformLocation(program,
-                                       b"uWindowSize\0" as *const c_uchar as *const c_char);
-            let u_texture =
-                gl::GetUniformLocation(program, b"uTexture\0" as *const c_uchar as *const c_char);
-            let a_position =
-                gl::GetAttribLocation(program, b"aPosition\0" as *const c_uchar as *const c_char);
-            let a_texture_uv =
-                gl::GetAttribLocation(program, b"aTextureUV\0" as *const c_uchar as *const c_char);
-            let a_color = gl::GetAttribLocation(program,
-                                                b"aColor\0" as *const c_uchar as *const c_char);
+            if (entered || entered_confirmation || entered_on_keyboard) && ok_enabled {
+                *exit_code = Some(0)
+            }
+            self.ok_cancel_button(ok_enabled, options, exit_code);
+            if *exit_code == Some(0) {
+                if let Some(ref history) = subdialog.history {
+                    add_input_history(&history.name,
+                                      &String::from_utf8_lossy(subdialog.entered_text()))
+                }
+                let text = if options.escape_newlines {
+                    let text = String::from_utf8_lossy(subdialog.entered_text());
+                    text.replace('\\', "\\\\").replace('\n', "\\n").into_bytes()
+                } else {
+                    subdialog.entered_text().to_vec()
+                };
+                options.results.print(&text, &Fields {
+                    value: Some(&text),
+                    ..Fields::default()
+                })
+            }
+        }
+    }
 
-            let mut vbo = 0;
-            gl::GenBuffers(1, &mut vbo);
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+    // Asks the question in a modal popup. Call it every frame until it returns the answer.
+    fn confirm(&self, confirmation: &mut Confirmation) -> Option<bool> {
+        unsafe {
+            let id = b"confirmation\0" as *const c_uchar as *const c_char;
+            if !confirmation.opened {
+                imgui::igOpenPopup(id);
+                confirmation.opened = true
+            }
+            let flags = imgui::ImGuiWindowFlags_NoTitleBar | imgui::ImGuiWindowFlags_NoResize |
+                imgui::ImGuiWindowFlags_NoMove | imgui::ImGuiWindowFlags_AlwaysAutoResize;
+            if !imgui::igBeginPopupModal(id, ptr::null_mut(), flags) {
+                return confirmation.answer
+            }
+            if confirmation.answer.is_none() {
+                confirmation.answer = self.yes_no_prompt(&confirmation.question)
+            }
+            if confirmation.answer.is_some() {
+                imgui::igCloseCurrentPopup()
+            }
+            imgui::igEndPopup();
+            confirmation.answer
+        }
+    }
 
-            let offset_of = |field_addr| {
-                (field_addr - (&ZERO_VERTEX as *const _ as usize)) as *const c_void
-            };
+    fn yes_no_prompt(&self, prompt: &str) -> Option<bool> {
+        self.prompt(prompt);
+        let button_size = button_size();
+        if self.button(self.locale.translate("Yes"), button_size, None) {
+            return Some(true)
+        }
+        if self.button(self.locale.translate("No"), button_size, None) {
+            return Some(false)
+        }
+        None
+    }
 
-            gl::VertexAttribPointer(a_position as c_uint,
-                                    2,
-                                    gl::FLOAT,
-                                    gl::FALSE,
-                                    mem::size_of::<ImDrawVert>() as c_int,
-                                    offset_of(&ZERO_VERTEX.pos as *const _ as usize));
-            gl::VertexAttribPointer(a_texture_uv as c_uint,
-                                    2,
-                                    gl::FLOAT,
-                                    gl::FALSE,
-                                    mem::size_of::<ImDrawVert>() as c_int,
-                                    offset_of(&ZERO_VERTEX.uv as *const _ as usize));
-            gl::VertexAttribPointer(a_color as c_uint,
-                                    4,
-                                    gl::UNSIGNED_BYTE,
-                                    gl::TRUE,
-                                    mem::size_of::<ImDrawVert>() as c_int,
-                                    offset_of(&ZERO_VERTEX.col as *const _ as usize));
+    fn render_menu_dialog(&self,
+                          subdialog: &mut MenuDialog,
+                          options: &Options,
+                          exit_code: &mut Option<c_int>) {
+        unsafe {
+            self.prompt(&subdialog.text);
+            if !subdialog.filter.is_empty() {
+                let search = format!("{}: {}", self.locale.translate("Search"), subdialog.filter);
+                self.text(Some(LABEL_COLOR), &search);
+                self.focus.borrow_mut().text(&search)
+            }
+            let descriptions = !options.no_tags && !options.no_items;
+            let columns = descriptions && !options.compact;
+            // Like dialog, a menu height of 0 shows every item.
+            let scrolling = subdialog.menu_height > 0;
+            if scrolling {
+                let lines_per_item = if descriptions && !columns { 2.0 } else { 1.0 };
+                let size = ImVec2 {
+                    x: button_size().x,
+                    y: imgui::igGetTextLineHeightWithSpacing() * lines_per_item *
+                        subdialog.menu_height as f32,
+                };
+                imgui::igBeginChild(b"menu\0" as *const c_uchar as *const c_char,
+                                    size,
+                                    false,
+                                    imgui::ImGuiWindowFlags::empty());
+            }
+            let labels: Vec<String> =
+                subdialog.items.iter().map(|item| item.label(options)).collect();
+            let icon_width = imgui::igGetTextLineHeight();
+            if columns {
+                // The descriptions start just past the widest tag that's showing.
+                let spacing = (*imgui::igGetStyle()).item_spacing.x;
+                let mut tag_width: f32 = 0.0;
+                for (index, item) in subdialog.items.iter().enumerate() {
+                    if item.separator || !subdialog.shows(index) {
+                        continue
+                    }
+                    let label = CString::new(bidi::visual(&labels[index], self.locale.rtl()))
+                        .unwrap();
+                    let icon = if item.icon.is_some() { icon_width + spacing } else { 0.0 };
+                    tag_width = tag_width.max(icon + self.text_width(&label))
+                }
+                imgui::igColumns(2, b"menu columns\0" as *const c_uchar as *const c_char, false);
+                imgui::igSetColumnOffset(1, tag_width + spacing * 2.0);
+            }
+            let activated = mem::replace(&mut subdialog.activate_highlighted, false);
+            let scroll = mem::replace(&mut subdialog.scroll_to_highlighted, false);
+            for (index, item) in subdialog.items.iter().enumerate() {
+                if !subdialog.shows(index) {
+                    continue
+                }
+                if item.separator {
+                    imgui::igSeparator();
+                    if columns {
+                        imgui::igNextColumn();
+                        imgui::igNextColumn();
+                    }
+                    continue
+                }
+                let label = &labels[index];
+                let mut description = if options.no_tags {
+                    item.item.clone()
+                } else if options.no_items {
+                    item.tag.clone()
+                } else {
+                    format!("{}, {}", item.tag, item.item)
+                };
+                if let Some(hotkey) = item.hotkey {
+                    description = format!("{}, {}", description, hotkey)
+                }
+                let highlighted = index == subdialog.highlighted && !item.disabled;
+                if highlighted && scroll {
+                    imgui::igSetScrollHere(0.5)
+                }
+                if let Some(icon) = item.icon {
+                    subdialog.icons.draw(icon, icon_width)
+                }
+                let flags = if columns {
+                    imgui::ImGuiSelectableFlags_SpanAllColumns
+                } else {
+                    ImGuiSelectableFlags::empty()
+                };
+                if item.disabled {
+                    self.text(Some(LABEL_COLOR), label);
+                    self.focus.borrow_mut().item(Role::Item, &description, Some("disabled"))
+                } else if self.selectable_with_flags(label, &description, highlighted, flags) ||
+                        (highlighted && activated) {
+                    subdialog.highlighted = index;
+                    if let Some(ref question) = item.confirm {
+                        subdialog.pending_confirmation =
+                            Some((index, Confirmation::new(question.clone())))
+                    } else {
+                        output::print_line(format_choice(index, item, options));
+                        *exit_code = Some(0)
+                    }
+                }
 
-            gl::EnableVertexAttribArray(a_position as c_uint);
-            gl::EnableVertexAttribArray(a_texture_uv as c_uint);
-            gl::EnableVertexAttribArray(a_color as c_uint);
+                if columns {
+                    imgui::igNextColumn();
+                    self.text(Some(LABEL_COLOR), &item.item);
+                    imgui::igNextColumn()
+                } else if descriptions {
+                    imgui::igPushFont(self.label_font);
+                    self.text(Some(LABEL_COLOR), &item.item);
+                    imgui::igPopFont();
+                }
+            }
+            if columns {
+                imgui::igColumns(1, ptr::null(), false)
+            }
+            if scrolling {
+                imgui::igEndChild();
+                let shown: Vec<usize> = (0..subdialog.items.len()).filter(|&index| {
+                    !subdialog.items[index].separator && subdialog.shows(index)
+                }).collect();
+                if shown.len() > subdialog.menu_height as usize {
+                    let position = shown.iter()
+                                        .position(|&index| index == subdialog.highlighted)
+                                        .map_or(0, |position| position + 1);
+                    let (position, count) = (self.locale.format_integer(position as u64),
+                                             self.locale.format_integer(shown.len() as u64));
+                    let footer = self.locale
+                                     .translate("Item {} of {}")
+                                     .replacen("{}", &position, 1)
+                                     .replacen("{}", &count, 1);
+                    self.text(Some(LABEL_COLOR), &footer);
+                    self.focus.borrow_mut().text(&footer)
+                }
+            }
 
-            Renderer {
-                standard_font: standard_font,
-                label_font: label_font,
-                texture: texture,
-                vertex_shader: vertex_shader,
-                fragment_shader: fragment_shader,
-                program: program,
-                u_window_size: u_window_size,
-                u_texture: u_texture,
-                a_position: a_position,
-                a_texture_uv: a_texture_uv,
-                a_color: a_color,
-                vbo: vbo,
+            if let Some(ref mut preview) = subdialog.keymap {
+                self.keymap_test(preview, &subdialog.items, subdialog.highlighted)
+            }
+
+            if !options.no_cancel &&
+                    self.button(self.locale.translate("Cancel"), button_size(), None) {
+                self.cancel(options, exit_code);
+                if options.cancel_prints_selection && *exit_code == Some(1) {
+                    subdialog.print_highlighted(options)
+                }
+            }
+        }
+
+        let answer = match subdialog.pending_confirmation {
+            Some((_, ref mut confirmation)) => self.confirm(confirmation),
+            None => None,
+        };
+        match answer {
+            Some(true) => {
+                let index = subdialog.pending_confirmation.take().unwrap().0;
+                output::print_line(format_choice(index, &subdialog.items[index], options));
+                *exit_code = Some(0)
+            }
+            Some(false) => subdialog.pending_confirmation = None,
+            None => {}
+        }
+    }
+
+    // Switches to the highlighted layout under X11, and gives a field to try it out in.
+    fn keymap_test(&self, preview: &mut KeymapPreview, items: &[MenuItem], highlighted: usize) {
+        if preview.kind == keymap::Kind::X11 && preview.previewed != Some(highlighted) {
+            preview.previewed = Some(highlighted);
+            if let Err(message) = keymap::preview(&items[highlighted].tag) {
+                writeln!(io::stderr(), "warning: couldn't try the layout: {}", message).unwrap()
             }
         }
+        let label = self.locale.translate("Type here to try the layout");
+        self.text(Some(LABEL_COLOR), label);
+        unsafe {
+            imgui::igPushItemWidth(button_size().x);
+            imgui::igInputText(b"##keymap test\0" as *const c_uchar as *const c_char,
+                               preview.test.as_mut_ptr() as *mut c_char,
+                               preview.test.len(),
+                               imgui::ImGuiInputTextFlags::empty(),
+                               None,
+                               ptr::null_mut());
+            igPopItemWidth();
+        }
+        self.focus.borrow_mut().item(Role::Entry, label, None)
     }
 
-    fn ok_cancel_button(&self, exit_code: &mut Option<c_int>) {
+    fn render_yesno_dialog(&self,
+                           subdialog: &mut YesNoDialog,
+                           remember: Option<&str>,
+                           exit_code: &mut Option<c_int>) {
         unsafe {
+            self.prompt(&subdialog.text);
+            if remember.is_some() {
+                let label = self.locale.translate("Don't ask again");
+                let c_label = CString::new(bidi::visual(label, self.locale.rtl())).unwrap();
+                imgui::igCheckbox(c_label.as_ptr(), &mut subdialog.dont_ask_again);
+                let state = if subdialog.dont_ask_again { "checked" } else { "unchecked" };
+                self.focus.borrow_mut().item(Role::Checkbox, label, Some(state));
+            }
             let button_size = button_size();
-            if imgui::igButton(b"OK\0" as *const c_uchar as *const c_char, button_size) {
+            if self.button(self.locale.translate("Yes"), button_size, None) {
                 *exit_code = Some(0)
             }
-            if imgui::igButton(b"Cancel\0" as *const c_uchar as *const c_char, button_size) {
+            if self.button(self.locale.translate("No"), button_size, None) {
                 *exit_code = Some(1)
             }
         }
+
+        if let (Some(key), Some(answer)) = (remember, *exit_code) {
+            if subdialog.dont_ask_again {
+                set_remembered_answer(key, Some(answer))
+            }
+        }
+    }
+
+    fn render_message_dialog(&self, subdialog: &MessageDialog, exit_code: &mut Option<c_int>) {
+        self.prompt(&subdialog.text);
+        if self.button(self.locale.translate("OK"), button_size(), None) {
+            *exit_code = Some(0)
+        }
     }
 
-    fn render_file_dialog(&self, subdialog: &mut FileDialog, exit_code: &mut Option<c_int>) {
+    fn render_gauge_dialog(&self, subdialog: &mut GaugeDialog) {
         unsafe {
-            imgui::igPushItemWidth(button_size().x);
-            if imgui::igListBox(b"\0" as *const c_uchar as *const c_char,
-                                &mut subdialog.entries.index,
-                                subdialog.entries.entries.as_mut_ptr(),
-                                subdialog.entries.entries.len() as c_int,
-                                LIST_HEIGHT) {
-                if subdialog.path.parent().is_some() && subdialog.entries.index == 0 {
-                    subdialog.path = subdialog.path.parent().unwrap().to_owned();
-                    subdialog.entries = FileDialogEntries::new(&subdialog.path)
-                } else {
-                    let (selected_path, file_type) = subdialog.selected_path();
-                    match file_type {
-                        SelectedFileType::File => *exit_code = Some(0),
-                        SelectedFileType::Directory => {
-                            subdialog.path = selected_path;
-                            subdialog.entries = FileDialogEntries::new(&subdialog.path)
-                        }
-                    }
-                }
+            self.prompt(&subdialog.text);
+            if let Some(ref install) = subdialog.install {
+                self.text(Some(LABEL_COLOR), &install.step);
+                self.focus.borrow_mut().text(&install.step)
             }
-            igPopItemWidth();
-            self.ok_cancel_button(exit_code);
-            if *exit_code == Some(0) {
-                println!("{}", subdialog.selected_path().0.display());
+            let label = CString::new(self.locale.format_percent(subdialog.percent)).unwrap();
+            let bar_size = button_size();
+            imgui::igProgressBar((subdialog.percent as f32) / 100.0, &bar_size, label.as_ptr());
+
+            if let Some(ref mut install) = subdialog.install {
+                let size = ImVec2 {
+                    x: bar_size.x,
+                    y: imgui::igGetTextLineHeightWithSpacing() * INSTALL_LOG_LINES,
+                };
+                imgui::igBeginChild(b"log\0" as *const c_uchar as *const c_char,
+                                    size,
+                                    true,
+                                    imgui::ImGuiWindowFlags::empty());
+                // Where the log was scrolled to as of the last frame, before the new lines.
+                let at_bottom = imgui::igGetScrollY() >= imgui::igGetScrollMaxY();
+                for line in &install.lines {
+                    self.text(None, line)
+                }
+                if install.grew && at_bottom {
+                    imgui::igSetScrollHere(1.0)
+                }
+                install.grew = false;
+                imgui::igEndChild()
             }
         }
     }
 
-    fn render_input_dialog(&self, subdialog: &mut InputDialog, exit_code: &mut Option<c_int>) {
+    fn render_number_dialog(&self,
+                            subdialog: &mut NumberDialog,
+                            options: &Options,
+                            exit_code: &mut Option<c_int>) {
         unsafe {
-            imgui::igText(CString::new(subdialog.text.clone()).unwrap().as_ptr());
+            self.prompt(&subdialog.text);
             imgui::igPushItemWidth(button_size().x);
-            let data_c_string = subdialog.data.as_mut_ptr() as *mut c_uchar as *mut c_char;
-            if imgui::igInputText(b"\0" as *const c_uchar as *const c_char,
-                                  data_c_string,
-                                  subdialog.data.len(),
-                                  imgui::ImGuiInputTextFlags_EnterReturnsTrue,
-                                  None,
-                                  ptr::null_mut()) {
+            imgui::igPushIdInt(subdialog.generation);
+            imgui::igInputInt(b"\0" as *const c_uchar as *const c_char,
+                              &mut subdialog.value,
+                              1,
+                              10,
+                              imgui::ImGuiInputTextFlags_CharsDecimal);
+            imgui::igPopId();
+            let label = format!("{}, {}", subdialog.text, subdialog.value);
+            self.focus.borrow_mut().item(Role::SpinButton, &label, None);
+            igPopItemWidth();
+            subdialog.value = subdialog.value.max(subdialog.min).min(subdialog.max);
+
+            if mem::replace(&mut subdialog.entered, false) {
                 *exit_code = Some(0)
             }
-            igPopItemWidth();
-            self.ok_cancel_button(exit_code);
+            self.ok_cancel_button(true, options, exit_code);
             if *exit_code == Some(0) {
-                let length = subdialog.data
-                                      .iter()
-                                      .position(|&x| x == 0)
-                                      .unwrap_or(subdialog.data.len());
-                io::stdout().write_all(&subdialog.data[..length]).unwrap();
-                println!("");
+                let value = subdialog.value.to_string();
+                options.results.print(value.as_bytes(), &Fields {
+                    value: Some(value.as_bytes()),
+                    ..Fields::default()
+                })
+            }
+        }
+    }
+
+    // The dialog finishes as soon as the last digit is in, so there's no OK button.
+    fn render_pin_dialog(&self,
+                         subdialog: &mut PinDialog,
+                         options: &Options,
+                         exit_code: &mut Option<c_int>) {
+        self.prompt(&subdialog.text);
+        self.pin_slots(&subdialog.text, subdialog.digits, subdialog.entered.len());
+        match subdialog.keypad.render(button_size().x) {
+            Some(KeypadPress::Digit(digit)) => subdialog.enter_digit(digit),
+            Some(KeypadPress::Delete) => {
+                subdialog.entered.pop();
             }
+            Some(KeypadPress::Clear) => subdialog.entered.clear(),
+            None => {}
+        }
+        if !options.no_cancel && self.button(self.locale.translate("Cancel"), button_size(), None) {
+            self.cancel(options, exit_code)
+        }
+        if exit_code.is_none() && subdialog.entered.len() == subdialog.digits {
+            *exit_code = Some(0);
+            options.results.print(subdialog.entered.as_bytes(), &Fields {
+                value: Some(subdialog.entered.as_bytes()),
+                ..Fields::default()
+            })
         }
     }
 
-    fn render_menu_dialog(&self, subdialog: &mut MenuDialog, exit_code: &mut Option<c_int>) {
+    fn render_form_dialog(&self,
+                          subdialog: &mut FormDialog,
+                          options: &Options,
+                          exit_code: &mut Option<c_int>) {
         unsafe {
-            for item in &subdialog.items {
-                if imgui::igSelectable(CString::new(item.tag.clone()).unwrap().as_ptr(),
-                                       false,
-                                       ImGuiSelectableFlags::empty(),
-                                       ZERO_SIZE) {
-                    println!("{}", item.tag);
-                    *exit_code = Some(0)
+            self.prompt(&subdialog.text);
+            let mut entered = false;
+            for (index, field) in subdialog.fields.iter_mut().enumerate() {
+                imgui::igPushIdInt(index as c_int);
+                if let Value::Bool(ref mut value) = field.value {
+                    let label = CString::new(bidi::visual(&field.label, self.locale.rtl()))
+                                    .unwrap();
+                    imgui::igCheckbox(label.as_ptr(), value);
+                    let state = if *value { "checked" } else { "unchecked" };
+                    self.focus.borrow_mut().item(Role::Checkbox, &field.label, Some(state));
+                    imgui::igPopId();
+                    continue
                 }
+                self.text(Some(LABEL_COLOR), &field.label);
+                let no_label = b"\0" as *const c_uchar as *const c_char;
+                imgui::igPushItemWidth(button_size().x);
+                let role = match field.value {
+                    Value::Text(ref mut data) => {
+                        let mut flags = imgui::ImGuiInputTextFlags_EnterReturnsTrue;
+                        if field.password {
+                            flags = flags | imgui::ImGuiInputTextFlags_Password
+                        }
+                        entered = imgui::igInputText(no_label,
+                                                     data.as_mut_ptr() as *mut c_char,
+                                                     data.len(),
+                                                     flags,
+                                                     None,
+                                                     ptr::null_mut()) || entered;
+                        if field.password { Role::PasswordEntry } else { Role::Entry }
+                    }
+                    Value::Integer { ref mut value, min, max } => {
+                        imgui::igInputInt(no_label,
+                                          value,
+                                          1,
+                                          10,
+                                          imgui::ImGuiInputTextFlags_CharsDecimal);
+                        *value = (*value).max(min).min(max);
+                        Role::SpinButton
+                    }
+                    Value::Choice { ref choices, ref mut index } => {
+                        let mut items: Vec<*const c_char> = choices.iter().map(|choice| {
+                            choice.as_ptr()
+                        }).collect();
+                        imgui::igCombo(no_label,
+                                       index,
+                                       items.as_mut_ptr(),
+                                       items.len() as c_int,
+                                       -1);
+                        Role::Item
+                    }
+                    Value::Bool(_) => unreachable!(),
+                };
+                igPopItemWidth();
+                let label = match field.value {
+                    Value::Text(_) => field.label.clone(),
+                    _ => format!("{}, {}", field.label, field.output()),
+                };
+                self.focus.borrow_mut().item(role, &label, None);
+                imgui::igPopId();
+            }
 
-                imgui::igPushFont(self.label_font);
-                imgui::igTextColored(LABEL_COLOR,
-                                     CString::new(item.item.clone()).unwrap().as_ptr());
-                imgui::igPopFont();
+            if entered {
+                *exit_code = Some(0)
+            }
+            self.ok_cancel_button(true, options, exit_code);
+            if *exit_code == Some(0) {
+                let mut password_file = options.password_fd.map(|fd| File::from_raw_fd(fd));
+                for (index, field) in subdialog.fields.iter().enumerate() {
+                    let value = field.output();
+                    let line = if options.key_value {
+                        format!("{}={}", field.key, value)
+                    } else {
+                        value.clone()
+                    };
+                    let line = options.results.format(line.as_bytes(), &Fields {
+                        tag: Some(&field.key),
+                        value: Some(value.as_bytes()),
+                        index: Some(index),
+                        ..Fields::default()
+                    });
+                    match password_file {
+                        Some(ref mut file) if field.password => {
+                            if let Err(error) = file.write_all(&line).and_then(|_| {
+                                file.write_all(b"\n")
+                            }) {
+                                writeln!(io::stderr(), "error: couldn't write the password: {}",
+                                         error).unwrap();
+                                *exit_code = Some(1)
+                            }
+                        }
+                        _ => output::print_line(line),
+                    }
+                }
             }
         }
     }
@@ -626,40 +4877,118 @@ impl Renderer {
     fn render(&self, window: &Window, dialog: &mut Dialog) -> Option<c_int> {
         let mut exit_code = None;
         unsafe {
-            let (width, height) = window.size();
-            gl::Viewport(0, 0, width as c_int, height as c_int);
-            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT);
+            let now = Instant::now();
+            let frame_time = duration_to_seconds(now.duration_since(self.last_frame_time.get()));
+            self.last_frame_time.set(now);
+            (*imgui::igGetIO()).delta_time = frame_time.max(MIN_FRAME_TIME).min(MAX_FRAME_TIME);
 
             imgui::igNewFrame();
+            self.focus.borrow_mut().begin_frame();
             imgui::igSetNextWindowPosCenter(ImGuiSetCond::empty());
             imgui::igBegin(b"imdialog\0" as *const c_uchar as *const c_char,
                            &mut true,
                            imgui::ImGuiWindowFlags_NoTitleBar | imgui::ImGuiWindowFlags_NoResize);
-            if !imgui::igIsAnyItemHovered() && !imgui::igIsAnyItemActive() {
+            if !imgui::igIsAnyItemHovered() && !imgui::igIsAnyItemActive() &&
+                    !dialog.list_focused() {
                 imgui::igSetKeyboardFocusHere(0)
             }
 
             match dialog.subdialog {
                 Subdialog::File(ref mut subdialog) => {
-                    self.render_file_dialog(subdialog, &mut exit_code)
+                    self.render_file_dialog(subdialog, &dialog.options, &mut exit_code)
                 }
                 Subdialog::Input(ref mut subdialog) => {
-                    self.render_input_dialog(subdialog, &mut exit_code)
+                    self.render_input_dialog(subdialog, &dialog.options, &mut exit_code)
                 }
                 Subdialog::Menu(ref mut subdialog) => {
-                    self.render_menu_dialog(subdialog, &mut exit_code)
+                    self.render_menu_dialog(subdialog, &dialog.options, &mut exit_code)
+                }
+                Subdialog::YesNo(ref mut subdialog) => {
+                    let remember = dialog.options.remember.as_ref().map(|key| &key[..]);
+                    self.render_yesno_dialog(subdialog, remember, &mut exit_code)
+                }
+                Subdialog::Message(ref subdialog) => {
+                    self.render_message_dialog(subdialog, &mut exit_code)
+                }
+                Subdialog::Gauge(ref mut subdialog) => self.render_gauge_dialog(subdialog),
+                Subdialog::Number(ref mut subdialog) => {
+                    self.render_number_dialog(subdialog, &dialog.options, &mut exit_code)
+                }
+                Subdialog::Form(ref mut subdialog) => {
+                    self.render_form_dialog(subdialog, &dialog.options, &mut exit_code)
                 }
+                Subdialog::Pin(ref mut subdialog) => {
+                    self.render_pin_dialog(subdialog, &dialog.options, &mut exit_code)
+                }
+            }
+            if dialog.options.back_button && !dialog.is_gauge() &&
+                    self.button(self.locale.translate("Back"), button_size(), None) {
+                exit_code = Some(BACK_EXIT_CODE)
+            }
+
+            if self.cancel_requested.replace(false) {
+                dialog.ask_to_cancel()
+            }
+            let answer = match dialog.pending_cancel {
+                Some(ref mut confirmation) => self.confirm(confirmation),
+                None => None,
+            };
+            match answer {
+                Some(true) => {
+                    dialog.cancel();
+                    exit_code = Some(1)
+                }
+                Some(false) => dialog.pending_cancel = None,
+                None => {}
             }
 
+            self.focus.borrow_mut().end_frame();
             imgui::igEnd();
 
+            // Everything from here to the buffer swap is GPU work, which the watchdog times.
+            self.begin_gpu_work();
+            let (width, height) = window.size();
+            gl::Viewport(0, 0, width as c_int, height as c_int);
+            let (red, green, blue) = self.background_color;
+            gl::ClearColor(red, green, blue, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            self.draw_background_image();
+
             RENDERER = self;
             imgui::igRender();
         }
+        self.draw_dimming();
         exit_code
     }
 
+    fn begin_gpu_work(&self) {
+        if let Some(ref watchdog) = self.watchdog {
+            watchdog.begin()
+        }
+    }
+
+    fn end_gpu_work(&self) {
+        if let Some(ref watchdog) = self.watchdog {
+            watchdog.end()
+        }
+    }
+
+    // Reads back what's been drawn so far this frame, top row first.
+    fn read_pixels(&self) -> Vec<u8> {
+        let row_size = FRAMEBUFFER_WIDTH as usize * 4;
+        let mut pixels = vec![0; row_size * FRAMEBUFFER_HEIGHT as usize];
+        unsafe {
+            gl::ReadPixels(0,
+                           0,
+                           FRAMEBUFFER_WIDTH as c_int,
+                           FRAMEBUFFER_HEIGHT as c_int,
+                           gl::RGBA,
+                           gl::UNSIGNED_BYTE,
+                           pixels.as_mut_ptr() as *mut c_void);
+        }
+        pixels.chunks(row_size).rev().flat_map(|row| row.iter().cloned()).collect()
+    }
+
     fn render_draw_lists(&self, draw_data: &ImDrawData) {
         unsafe {
             gl::UseProgram(self.program);
@@ -671,6 +5000,18 @@ impl Renderer {
             gl::BindTexture(gl::TEXTURE_2D, self.texture);
             gl::Uniform2f(self.u_window_size, FRAMEBUFFER_WIDTH as f32, FRAMEBUFFER_HEIGHT as f32);
             gl::Uniform1i(self.u_texture, 0);
+            // imgui lays out for the framebuffer size, which a fullscreen window is stretched
+            // from, so clip rectangles have to be stretched the same way.
+            let mut viewport = [0; 4];
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+            let scale_x = viewport[2] as f32 / FRAMEBUFFER_WIDTH as f32;
+            let scale_y = viewport[3] as f32 / FRAMEBUFFER_HEIGHT as f32;
+            // Sliding and shifting move the whole dialog, leaving the background where it is.
+            let (shift_x, shift_y) = self.shift.get();
+            let shift_x = (shift_x as f32 * scale_x) as c_int;
+            let slide = (self.slide.get() * viewport[3] as f32) as c_int +
+                (shift_y as f32 * scale_y) as c_int;
+            gl::Viewport(viewport[0] + shift_x, viewport[1] - slide, viewport[2], viewport[3]);
 
             let gl_buffer_type = if mem::size_of::<ImDrawIdx>() == 2 {
                 gl::UNSIGNED_SHORT
@@ -694,10 +5035,15 @@ impl Renderer {
                     let index_ptr = imgui::ImDrawList_GetIndexPtr(draw_list, 0);
                     let index_size = (*draw_command).elem_count;
                     let clip_rect = (*draw_command).clip_rect;
-                    gl::Scissor(clip_rect.x as c_int,
-                                ((FRAMEBUFFER_HEIGHT as f32) - clip_rect.w) as c_int,
-                                (clip_rect.z - clip_rect.x) as c_int,
-                                (clip_rect.w - clip_rect.y) as c_int);
+                    // Everything but menu icons comes from the font atlas, which has no ID.
+                    let texture = (*draw_command).texture_id as usize as c_uint;
+                    let texture = if texture != 0 { texture } else { self.texture };
+                    gl::BindTexture(gl::TEXTURE_2D, texture);
+                    gl::Scissor((clip_rect.x * scale_x) as c_int + shift_x,
+                                (((FRAMEBUFFER_HEIGHT as f32) - clip_rect.w) * scale_y) as c_int -
+                                    slide,
+                                ((clip_rect.z - clip_rect.x) * scale_x) as c_int,
+                                ((clip_rect.w - clip_rect.y) * scale_y) as c_int);
                     gl::DrawElements(gl::TRIANGLES,
                                      index_size as c_int,
                                      gl_buffer_type,
@@ -705,14 +5051,90 @@ impl Renderer {
                     index_offset += index_size as isize
                 }
             }
+            gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        }
+    }
+}
+
+extern "C" fn render_draw_lists(draw_data: *mut ImDrawData) {
+    unsafe {
+        let draw_data: &ImDrawData = mem::transmute::<*mut ImDrawData, &ImDrawData>(draw_data);
+        (mem::transmute::<*const Renderer, &Renderer>(RENDERER)).render_draw_lists(draw_data)
+    }
+}
+
+// Tab completion for the file dialog's location field. `user_data` points to the current
+// directory.
+extern "C" fn complete_location(data: *mut ImGuiTextEditCallbackData) -> c_int {
+    unsafe {
+        let data = &mut *data;
+        let dialog = &*(data.user_data as *const FileDialog);
+        let typed = CStr::from_ptr(data.buf).to_string_lossy().into_owned();
+        let completed = match complete_path(&dialog.path, &typed, dialog.root()) {
+            Some(ref completed) if completed.len() < data.buf_size as usize => completed.clone(),
+            _ => return 0,
+        };
+        ptr::copy_nonoverlapping(completed.as_ptr() as *const c_char, data.buf, completed.len());
+        *data.buf.offset(completed.len() as isize) = 0;
+        data.buf_text_len = completed.len() as c_int;
+        data.cursor_pos = data.buf_text_len;
+        data.selection_start = data.buf_text_len;
+        data.selection_end = data.buf_text_len;
+        data.buf_dirty = true;
+    }
+    0
+}
+
+// Moves the cursor or selects everything the first time an input box's field is active.
+extern "C" fn place_cursor(data: *mut ImGuiTextEditCallbackData) -> c_int {
+    unsafe {
+        let data = &mut *data;
+        let dialog = &mut *(data.user_data as *mut InputDialog);
+        let length = data.buf_text_len;
+        match dialog.cursor_placement.take() {
+            Some(CursorPlacement::SelectAll) => {
+                data.selection_start = 0;
+                data.selection_end = length;
+                data.cursor_pos = length
+            }
+            Some(CursorPlacement::Offset(offset)) => {
+                // imgui counts in bytes.
+                let text = CStr::from_ptr(data.buf).to_string_lossy();
+                let position = text.char_indices().nth(offset).map_or(length, |(index, _)| {
+                    index as c_int
+                });
+                data.cursor_pos = position;
+                data.selection_start = position;
+                data.selection_end = position
+            }
+            None => {}
         }
     }
+    0
 }
 
-extern "C" fn render_draw_lists(draw_data: *mut ImDrawData) {
+extern "C" fn get_clipboard_text() -> *const c_char {
     unsafe {
-        let draw_data: &ImDrawData = mem::transmute::<*mut ImDrawData, &ImDrawData>(draw_data);
-        (mem::transmute::<*const Renderer, &Renderer>(RENDERER)).render_draw_lists(draw_data)
+        let text = (*CLIPBOARD).clipboard_text().unwrap_or(String::new());
+        if !CLIPBOARD_TEXT.is_null() {
+            drop(CString::from_raw(CLIPBOARD_TEXT))
+        }
+        CLIPBOARD_TEXT = CString::new(text).unwrap_or(CString::new("").unwrap()).into_raw();
+        CLIPBOARD_TEXT
+    }
+}
+
+extern "C" fn set_clipboard_text(text: *const c_char) {
+    unsafe {
+        if let Ok(text) = CStr::from_ptr(text).to_str() {
+            drop((*CLIPBOARD).set_clipboard_text(text))
+        }
+    }
+}
+
+extern "C" fn set_ime_position(x: c_int, y: c_int) {
+    unsafe {
+        (*TEXT_INPUT).set_rect(Rect::new(x, y, 1, 1))
     }
 }
 
@@ -727,6 +5149,122 @@ fn set_mod_state(sdl: &Sdl) {
     }
 }
 
+fn any_key_down() -> bool {
+    unsafe {
+        (*imgui::igGetIO()).keys_down.iter().any(|&down| down)
+    }
+}
+
+// `#rrggbb`.
+fn parse_color(value: &str) -> Option<(f32, f32, f32)> {
+    if !value.starts_with('#') || value.len() != 7 {
+        return None
+    }
+    let channel = |index: usize| {
+        value.get(index..(index + 2)).and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                                     .map(|channel| channel as f32 / 255.0)
+    };
+    match (channel(1), channel(3), channel(5)) {
+        (Some(red), Some(green), Some(blue)) => Some((red, green, blue)),
+        _ => None,
+    }
+}
+
+fn is_input(event: &Event) -> bool {
+    match *event {
+        Event::KeyDown { .. } | Event::TextInput { .. } | Event::MouseMotion { .. } |
+        Event::MouseButtonDown { .. } | Event::MouseWheel { .. } | Event::FingerDown { .. } |
+        Event::FingerMotion { .. } => true,
+        _ => false,
+    }
+}
+
+fn click_sound(event: &Event) -> Option<Sound> {
+    match *event {
+        Event::KeyDown { scancode: Some(Scancode::Return), repeat: false, .. } => {
+            Some(Sound::Confirm)
+        }
+        Event::KeyDown { repeat: false, .. } | Event::MouseButtonDown { .. } |
+        Event::FingerDown { .. } => Some(Sound::Click),
+        _ => None,
+    }
+}
+
+// How many milliseconds until `--idle-dim` should dim the screen, if it's still to come.
+fn idle_timeout(idle_dim: Option<Duration>, idle_time: Duration) -> Option<u32> {
+    match idle_dim {
+        Some(idle_dim) if idle_time < idle_dim => {
+            let remaining = idle_dim - idle_time;
+            Some(remaining.as_secs() as u32 * 1000 + remaining.subsec_millis() + 1)
+        }
+        _ => None,
+    }
+}
+
+// How many milliseconds the screen can go without redrawing while there's no input, if it needs
+// redrawing at all before then.
+fn wake_timeout(options: &Options, idle_time: Duration) -> Option<u32> {
+    let idle_timeout = idle_timeout(options.idle_dim, idle_time);
+    if !options.pixel_shift && options.dim_schedule.is_none() {
+        return idle_timeout
+    }
+    Some(idle_timeout.map_or(BURN_IN_CHECK_INTERVAL, |timeout| {
+        timeout.min(BURN_IN_CHECK_INTERVAL)
+    }))
+}
+
+// `HH:MM-HH:MM`, which may run past midnight.
+fn parse_dim_schedule(schedule: &str) -> Option<(u32, u32)> {
+    let minutes = |time: &str| {
+        let mut fields = time.trim().splitn(2, ':');
+        match (fields.next()?.parse::<u32>().ok()?, fields.next()?.parse::<u32>().ok()?) {
+            (hours, minutes) if hours < 24 && minutes < 60 => Some(hours * 60 + minutes),
+            _ => None,
+        }
+    };
+    let mut times = schedule.splitn(2, '-');
+    Some((minutes(times.next()?)?, minutes(times.next()?)?))
+}
+
+fn in_dim_schedule((from, until): (u32, u32)) -> bool {
+    let now = unsafe {
+        let seconds = libc::time(ptr::null_mut());
+        let mut tm = mem::zeroed();
+        if libc::localtime_r(&seconds, &mut tm).is_null() {
+            return false
+        }
+        tm.tm_hour as u32 * 60 + tm.tm_min as u32
+    };
+    if from <= until {
+        now >= from && now < until
+    } else {
+        now >= from || now < until
+    }
+}
+
+// Where `--pixel-shift` puts the dialog now. It goes by the clock, so that the dialogs of a
+// server session carry on around the orbit rather than starting again.
+fn pixel_shift() -> (c_int, c_int) {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let index = (seconds / PIXEL_SHIFT_INTERVAL) as usize % PIXEL_SHIFT_ORBIT.len();
+    let (x, y) = PIXEL_SHIFT_ORBIT[index];
+    (x * PIXEL_SHIFT_STEP, y * PIXEL_SHIFT_STEP)
+}
+
+// Cuts the dialog off from the mouse and keyboard, for the frames of its closing animation.
+fn release_input() {
+    unsafe {
+        let io = imgui::igGetIO();
+        for key in (*io).keys_down.iter_mut() {
+            *key = false
+        }
+        for button in (*io).mouse_down.iter_mut() {
+            *button = false
+        }
+        (*io).mouse_pos = ImVec2 { x: -1.0, y: -1.0 };
+    }
+}
+
 fn init_keys() {
     unsafe {
         let io = imgui::igGetIO();
@@ -736,6 +5274,19 @@ fn init_keys() {
     }
 }
 
+fn init_touch_style() {
+    unsafe {
+        let style = imgui::igGetStyle();
+        (*style).frame_padding.x *= TOUCH_STYLE_SCALE;
+        (*style).frame_padding.y *= TOUCH_STYLE_SCALE;
+        (*style).item_spacing.x *= TOUCH_STYLE_SCALE;
+        (*style).item_spacing.y *= TOUCH_STYLE_SCALE;
+        (*style).scrollbar_size *= TOUCH_STYLE_SCALE;
+        (*style).grab_min_size *= TOUCH_STYLE_SCALE;
+        (*style).touch_extra_padding = (*style).item_spacing;
+    }
+}
+
 fn init_texture() -> c_uint {
     unsafe {
         let io = imgui::igGetIO();
@@ -768,106 +5319,816 @@ fn shutdown() {}
 
 #[cfg(target_os="linux")]
 fn shutdown() {
-    if libc::isatty(0) {
+    if unsafe { libc::isatty(0) != 0 } {
         ioctl::kdskbmute(0, 0);
         ioctl::kdskbmode(0, K_XLATE);
     }
 }
 
-pub fn main() {
-    let base_directories = BaseDirectories::with_prefix(PathBuf::from("imdialog/")).unwrap();
-    let mut dialog = Dialog::new();
-
-    let sdl = sdl2::init().unwrap();
-    let video = sdl.video().unwrap();
-    let window = video.window("imdialog", FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT)
-                      .position_centered()
-                      .opengl()
-                      .build()
-                      .unwrap();
-
-    let context = window.gl_create_context().unwrap();
-    window.gl_make_current(&context).unwrap();
-    gl::load_with(|name| video.gl_get_proc_address(name) as *const c_void);
-
-    let renderer = Renderer::new(&base_directories);
-   
+// Nobody may be able to see the console while imdialog has it, so panic messages go to a log file
+// too, and the keyboard is put back before the main thread unwinds.
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().name() == Some("main") {
+            shutdown()
+        }
+        error!("{}", info);
+        let _ = state::append_line(PANIC_LOG_FILENAME, &info.to_string());
+        default_hook(info)
+    }))
+}
+
+fn gpu_hang() {
+    writeln!(io::stderr(), "error: rendering stalled; giving up").unwrap();
+    error!("rendering stalled for more than the GPU timeout");
+    shutdown();
+    // The main thread is stuck in the driver, so skip exit handlers that might touch it.
     unsafe {
-        let io = imgui::igGetIO();
-        let (width, height) = window.size();
-        (*io).display_size.x = width as f32;
-        (*io).display_size.y = height as f32;
-        (*io).render_draw_lists_fn = Some(render_draw_lists);
+        libc::_exit(GPU_HANG_EXIT_CODE)
+    }
+}
+
+// Renders into a renderbuffer instead of the window, which then never needs to be shown.
+fn bind_offscreen_framebuffer() {
+    unsafe {
+        let (mut framebuffer, mut renderbuffer) = (0, 0);
+        gl::GenFramebuffers(1, &mut framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+        gl::GenRenderbuffers(1, &mut renderbuffer);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+        gl::RenderbufferStorage(gl::RENDERBUFFER,
+                                gl::RGBA8,
+                                FRAMEBUFFER_WIDTH as c_int,
+                                FRAMEBUFFER_HEIGHT as c_int);
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER,
+                                    gl::COLOR_ATTACHMENT0,
+                                    gl::RENDERBUFFER,
+                                    renderbuffer);
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            writeln!(io::stderr(), "error: couldn't create an offscreen framebuffer").unwrap();
+            shutdown();
+            process::exit(1)
+        }
+    }
+}
+
+fn save_screenshot(path: &Path, pixels: &[u8]) {
+    if let Err(error) = png::write(path, FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT, pixels) {
+        writeln!(io::stderr(), "warning: couldn't write `{}`: {}", path.display(), error).unwrap()
+    }
+}
+
+// Screenshots taken with the hotkey go in the current directory.
+fn hotkey_screenshot_path() -> PathBuf {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs())
+                                                                .unwrap_or(0);
+    let mut path = PathBuf::from(format!("imdialog-{}.png", timestamp));
+    let mut index = 1;
+    while path.exists() {
+        index += 1;
+        path = PathBuf::from(format!("imdialog-{}-{}.png", timestamp, index))
+    }
+    path
+}
+
+fn gpu_description() -> String {
+    let string = |name| {
+        unsafe {
+            let string = gl::GetString(name);
+            if string.is_null() {
+                "unknown".to_string()
+            } else {
+                CStr::from_ptr(string as *const c_char).to_string_lossy().into_owned()
+            }
+        }
+    };
+    format!("{} ({}, OpenGL {})", string(gl::RENDERER), string(gl::VENDOR), string(gl::VERSION))
+}
+
+// Where to put the window for `--display`, `--monitor-primary` and `--position`, or `None` to
+// leave SDL to center it.
+fn window_position(video: &VideoSubsystem, options: &Options) -> Option<(i32, i32)> {
+    if options.screen.is_none() && options.position.is_none() {
+        return None
     }
+    let count = video.num_video_displays().unwrap_or(1);
+    let index = match options.screen {
+        Some(Screen::Index(index)) if index < count => index,
+        Some(Screen::Index(index)) => {
+            writeln!(io::stderr(),
+                     "error: there's no display {}; there are only {}",
+                     index,
+                     count).unwrap();
+            shutdown();
+            process::exit(1)
+        }
+        Some(Screen::Primary) | None => {
+            (0..count).find(|&index| {
+                video.display_bounds(index).map(|bounds| (bounds.x(), bounds.y()) == (0, 0))
+                                           .unwrap_or(false)
+            }).unwrap_or(0)
+        }
+    };
+    let bounds = match video.display_bounds(index) {
+        Ok(bounds) => bounds,
+        Err(message) => {
+            warn!("couldn't get the bounds of display {}: {}", index, message);
+            return None
+        }
+    };
+    info!("putting the window on display {} ({}), at {:?}",
+          index,
+          video.display_name(index),
+          bounds);
+    Some(match options.position {
+        Some((x, y)) => (bounds.x() + x, bounds.y() + y),
+        None => {
+            (bounds.x() + (bounds.width() as i32 - FRAMEBUFFER_WIDTH as i32) / 2,
+             bounds.y() + (bounds.height() as i32 - FRAMEBUFFER_HEIGHT as i32) / 2)
+        }
+    })
+}
+
+struct Display {
+    sdl: Sdl,
+    window: Window,
+    _context: GLContext,
+    renderer: Renderer,
+    events: EventPump,
+    controllers: Controllers,
+    headless: bool,
+    script: Option<Script>,
+    dump_frames: Option<PathBuf>,
+    frames_presented: u32,
+    screenshot: Option<PathBuf>,
+    screenshot_requested: bool,
+    last_frame: Option<Vec<u8>>,
+    metrics: Rc<Metrics>,
+    // Keeps the event loop from sleeping indefinitely, so that `run_observed` callbacks get a
+    // chance to run.
+    wake_periodically: bool,
+    // Only opened when some sound was asked for.
+    speaker: Option<Speaker>,
+}
 
-    let mut events = sdl.event_pump().unwrap();
-    let mut exit_code = 0;
-    let mut event_queue = vec![];
-    loop {
-        if let Some(code) = renderer.render(&window, &mut dialog) {
-            exit_code = code;
-            break
+impl Display {
+    fn new(options: &Options) -> Display {
+        let base_directories = BaseDirectories::with_prefix(PathBuf::from("imdialog/")).unwrap();
+        if options.headless && env::var_os("SDL_VIDEODRIVER").is_none() {
+            // SDL's offscreen driver provides a GL context without any display server.
+            env::set_var("SDL_VIDEODRIVER", "offscreen")
         }
+        let sdl = sdl2::init().unwrap();
+        let video = sdl.video().unwrap();
+        info!("SDL started with the `{}` video driver", video.current_video_driver());
+        signals::install();
+        let mut window_builder = video.window("imdialog", FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT);
+        match window_position(&video, options) {
+            Some((x, y)) => window_builder.position(x, y),
+            None => window_builder.position_centered(),
+        };
+        if options.fullscreen {
+            window_builder.fullscreen_desktop();
+        }
+        if options.borderless {
+            window_builder.borderless();
+        }
+        if options.always_on_top {
+            let flags = window_builder.window_flags() | SDL_WINDOW_ALWAYS_ON_TOP;
+            window_builder.set_window_flags(flags);
+        }
+        let window = window_builder.opengl().hidden().build().unwrap();
 
-        if event_queue.is_empty() {
-            event_queue.push(events.wait_event());
+        let context = window.gl_create_context().unwrap();
+        window.gl_make_current(&context).unwrap();
+        gl::load_with(|name| video.gl_get_proc_address(name) as *const c_void);
+        let (major, minor) = video.gl_attr().context_version();
+        info!("GL context {}.{} on {}, swap interval {}",
+              major,
+              minor,
+              gpu_description(),
+              video.gl_get_swap_interval());
+        if options.headless {
+            debug!("rendering offscreen at {}x{}", FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT);
+            bind_offscreen_framebuffer()
         }
-        while let Some(event) = events.poll_event() {
-            event_queue.push(event)
+
+        let mut renderer = Renderer::new(&base_directories,
+                                         options.locale.clone(),
+                                         FocusTracker::new(options.a11y_fd, options.speak));
+        if let Some(ref background) = options.background {
+            if let Err(message) = renderer.set_background(background) {
+                writeln!(io::stderr(), "error: `--background`: {}", message).unwrap();
+                shutdown();
+                process::exit(1)
+            }
+        }
+        if options.touch {
+            init_touch_style()
         }
 
-        match event_queue.remove(0) {
-            Event::Quit { .. } => break,
-            Event::KeyDown { scancode: Some(scancode), .. } => {
-                unsafe {
-                    let io = imgui::igGetIO();
-                    if let Some(scancode) = scancode.to_u8() {
-                        (*io).keys_down[scancode as usize] = true
+        unsafe {
+            let io = imgui::igGetIO();
+            let (width, height) = window.size();
+            (*io).display_size.x = width as f32;
+            (*io).display_size.y = height as f32;
+            (*io).render_draw_lists_fn = Some(render_draw_lists);
+
+            CLIPBOARD = Box::into_raw(Box::new(video.clipboard()));
+            (*io).get_clipboard_text_fn = Some(get_clipboard_text);
+            (*io).set_clipboard_text_fn = Some(set_clipboard_text);
+
+            TEXT_INPUT = Box::into_raw(Box::new(video.text_input()));
+            (*io).ime_set_input_screen_pos_fn = Some(set_ime_position);
+        }
+
+        if options.hide_cursor {
+            sdl.mouse().show_cursor(false)
+        }
+
+        let events = sdl.event_pump().unwrap();
+        let controllers = Controllers::new(&sdl);
+        let speaker = if options.beep || options.beep_after || options.click_sounds {
+            Some(Speaker::new(&sdl))
+        } else {
+            None
+        };
+        renderer.watchdog = if options.gpu_timeout > 0 {
+            Some(Watchdog::start(Duration::from_secs(options.gpu_timeout), gpu_hang))
+        } else {
+            None
+        };
+        let script = options.script.as_ref().map(|path| {
+            match Script::load(path) {
+                Ok(script) => script,
+                Err(message) => {
+                    writeln!(io::stderr(), "error: `--script`: {}", message).unwrap();
+                    shutdown();
+                    process::exit(1)
+                }
+            }
+        });
+        if let Some(ref directory) = options.dump_frames {
+            if let Err(error) = fs::create_dir_all(directory) {
+                writeln!(io::stderr(), "error: `--dump-frames`: {}", error).unwrap();
+                shutdown();
+                process::exit(1)
+            }
+        }
+        // A frame taking longer than two refreshes means one was missed.
+        let dropped_frame_time = Duration::from_millis(ANIMATION_FRAME_INTERVAL as u64 * 2);
+
+        Display {
+            sdl: sdl,
+            window: window,
+            _context: context,
+            renderer: renderer,
+            events: events,
+            controllers: controllers,
+            headless: options.headless,
+            script: script,
+            dump_frames: options.dump_frames.clone(),
+            frames_presented: 0,
+            screenshot: options.screenshot.clone(),
+            screenshot_requested: false,
+            last_frame: None,
+            metrics: Rc::new(Metrics::new(gpu_description(), dropped_frame_time)),
+            wake_periodically: false,
+            speaker: speaker,
+        }
+    }
+
+    fn play(&mut self, sound: Sound) {
+        if let Some(ref mut speaker) = self.speaker {
+            speaker.play(sound)
+        }
+    }
+
+    fn present(&mut self) {
+        let pixels = if self.dump_frames.is_some() || self.screenshot.is_some() ||
+                self.screenshot_requested {
+            Some(self.renderer.read_pixels())
+        } else {
+            None
+        };
+        if !self.headless {
+            self.window.gl_swap_window()
+        }
+        self.renderer.end_gpu_work();
+        if let Some(pixels) = pixels {
+            if let Some(ref directory) = self.dump_frames {
+                let filename = format!("frame-{:05}.png", self.frames_presented);
+                save_screenshot(&directory.join(filename), &pixels)
+            }
+            if self.screenshot_requested {
+                save_screenshot(&hotkey_screenshot_path(), &pixels);
+                self.screenshot_requested = false
+            }
+            if self.screenshot.is_some() {
+                self.last_frame = Some(pixels)
+            }
+        }
+        self.frames_presented += 1
+    }
+
+    fn begin_frame(&self) {
+        self.metrics.begin_frame()
+    }
+
+    // A frame that's rendered but not presented leaves the GPU's work unfinished.
+    fn end_frame(&self) {
+        self.renderer.end_gpu_work();
+        self.metrics.end_frame()
+    }
+
+    fn run(&mut self, dialog: &mut Dialog) -> c_int {
+        let exit_code = self.run_observed(dialog, |_| {});
+        dialog.settle_keymap(exit_code)
+    }
+
+    // Like `run`, but hands the dialog to `observe` after every frame.
+    fn run_observed<F>(&mut self, dialog: &mut Dialog, mut observe: F) -> c_int
+                       where F: FnMut(&Dialog) {
+        self.metrics.dialog_shown();
+        let mut touch = Touch::new(dialog.options.touch,
+                                   FRAMEBUFFER_WIDTH as f32,
+                                   FRAMEBUFFER_HEIGHT as f32,
+                                   STANDARD_FONT_SIZE * SCROLL_LINES);
+        let start_time = Instant::now();
+        self.animate(&dialog.options, start_time, false);
+
+        // Lay out and present the first frame before the window appears, so it never shows up
+        // empty.
+        self.begin_frame();
+        self.renderer.render(&self.window, dialog);
+        self.renderer.render(&self.window, dialog);
+        self.present();
+        self.end_frame();
+        if !self.headless {
+            self.window.show();
+        }
+        if dialog.options.beep {
+            self.play(Sound::Beep)
+        }
+
+        let commands = if dialog.options.stdin_commands {
+            Some(CommandChannel::start())
+        } else {
+            None
+        };
+
+        let mut exit_code = 0;
+        let mut event_queue = vec![];
+        let mut last_input = Instant::now();
+        'main: loop {
+            let idle_time = last_input.elapsed();
+            self.renderer.dimmed.set(dialog.options.idle_dim.map_or(false, |idle_dim| {
+                idle_time >= idle_dim
+            }) || dialog.options.dim_schedule.map_or(false, in_dim_schedule));
+            if dialog.options.pixel_shift {
+                self.renderer.shift.set(pixel_shift())
+            }
+            let opening = self.animate(&dialog.options, start_time, false);
+            self.begin_frame();
+            if let Some(code) = self.renderer.render(&self.window, dialog) {
+                exit_code = code;
+                break
+            }
+            self.end_frame();
+            observe(dialog);
+
+            while event_queue.is_empty() {
+                if let Some(ref mut script) = self.script {
+                    match script.next_frame() {
+                        Frame::Event(event) => event_queue.push(event),
+                        Frame::Idle => {}
+                        Frame::Finished => {
+                            info!("the script finished before the dialog closed");
+                            exit_code = SCRIPT_FINISHED_EXIT_CODE;
+                            break 'main
+                        }
+                    }
+                    break
+                }
+                let animating = touch.coasting() || any_key_down() || opening || dialog.loading();
+                let event = if animating {
+                    match self.events.wait_event_timeout(ANIMATION_FRAME_INTERVAL) {
+                        Some(event) => event,
+                        None => break,
                     }
-                    set_mod_state(&sdl);
-                    if scancode == Scancode::Escape {
-                        break
+                } else if commands.is_some() || self.wake_periodically || dialog.watching() {
+                    match self.events.wait_event_timeout(COMMAND_POLL_INTERVAL) {
+                        Some(event) => event,
+                        None => break,
                     }
+                } else if let Some(timeout) = wake_timeout(&dialog.options, idle_time) {
+                    match self.events.wait_event_timeout(timeout) {
+                        Some(event) => event,
+                        None => break,
+                    }
+                } else {
+                    self.events.wait_event()
+                };
+                event_queue.extend(self.controllers.translate_event(event));
+            }
+            while let Some(event) = self.events.poll_event() {
+                event_queue.extend(self.controllers.translate_event(event))
+            }
+
+            let event = if event_queue.is_empty() {
+                None
+            } else {
+                Some(event_queue.remove(0))
+            };
+            if let Some(ref event) = event {
+                trace!("event: {:?}", event);
+                if is_input(event) {
+                    last_input = Instant::now()
                 }
+                if dialog.options.click_sounds {
+                    if let Some(sound) = click_sound(event) {
+                        self.play(sound)
+                    }
+                }
+                touch.handle_event(event)
             }
-            Event::KeyUp { scancode: Some(scancode), .. } => {
-                unsafe {
-                    let io = imgui::igGetIO();
-                    if let Some(scancode) = scancode.to_u8() {
-                        (*io).keys_down[scancode as usize] = false
+
+            match event {
+                Some(Event::Quit { .. }) => {
+                    if let Some(signal) = signals::caught() {
+                        info!("quitting on signal {}", signal);
+                        exit_code = 128 + signal
+                    }
+                    break
+                }
+                Some(Event::KeyDown { scancode: Some(scancode), .. }) if dialog.confirming() => {
+                    unsafe {
+                        let io = imgui::igGetIO();
+                        if let Some(scancode) = scancode.to_u8() {
+                            (*io).keys_down[scancode as usize] = true
+                        }
+                        set_mod_state(&self.sdl);
+                    }
+                    match scancode {
+                        Scancode::Return => dialog.answer_confirmation(true),
+                        Scancode::Escape => dialog.answer_confirmation(false),
+                        _ => {}
                     }
-                    set_mod_state(&sdl);
                 }
+                Some(Event::KeyDown { scancode: Some(scancode), .. }) => {
+                    unsafe {
+                        let io = imgui::igGetIO();
+                        if let Some(scancode) = scancode.to_u8() {
+                            (*io).keys_down[scancode as usize] = true
+                        }
+                        set_mod_state(&self.sdl);
+                        debug!("key down: {:?}", scancode);
+                        if scancode == Scancode::Escape && !dialog.clear_search() {
+                            if dialog.options.confirm_cancel {
+                                dialog.ask_to_cancel()
+                            } else {
+                                dialog.cancel();
+                                exit_code = dialog.options.esc_code;
+                                break
+                            }
+                        }
+                        if scancode == Scancode::Backspace {
+                            dialog.erase_search()
+                        }
+                        if scancode == Scancode::Return {
+                            dialog.activate_selection()
+                        }
+                        if scancode == Scancode::Up {
+                            dialog.arrow_key(1)
+                        }
+                        if scancode == Scancode::PageUp {
+                            dialog.page_key(1)
+                        }
+                        if scancode == Scancode::PageDown {
+                            dialog.page_key(-1)
+                        }
+                        if scancode == Scancode::Down {
+                            dialog.arrow_key(-1)
+                        }
+                        if scancode == Scancode::Left {
+                            dialog.sideways_key(-1)
+                        }
+                        if scancode == Scancode::Right {
+                            dialog.sideways_key(1)
+                        }
+                        if scancode == Scancode::PrintScreen {
+                            self.screenshot_requested = true
+                        }
+                        if scancode == Scancode::H && (*io).key_ctrl {
+                            dialog.toggle_hidden_files()
+                        }
+                        if scancode == Scancode::R && (*io).key_ctrl {
+                            dialog.toggle_revealed()
+                        }
+                        if scancode == Scancode::Space {
+                            dialog.toggle_checked()
+                        }
+                    }
+                }
+                Some(Event::KeyUp { scancode: Some(scancode), .. }) => {
+                    unsafe {
+                        let io = imgui::igGetIO();
+                        if let Some(scancode) = scancode.to_u8() {
+                            (*io).keys_down[scancode as usize] = false
+                        }
+                        set_mod_state(&self.sdl);
+                    }
+                }
+                Some(Event::TextEditing { text, .. }) => dialog.set_composition(text),
+                Some(Event::TextInput { .. }) if dialog.confirming() => {}
+                Some(Event::TextInput { text, .. }) => {
+                    dialog.set_composition(String::new());
+                    if !dialog.type_ahead(&text) {
+                        unsafe {
+                            if let Ok(text) = CString::new(text) {
+                                imgui::ImGuiIO_AddInputCharactersUTF8(text.as_ptr())
+                            }
+                        }
+                    }
+                }
+                Some(Event::MouseWheel { y, .. }) => {
+                    unsafe {
+                        let io = imgui::igGetIO();
+                        (*io).mouse_wheel += y as f32
+                    }
+                }
+                _ => {}
             }
-            Event::TextInput { text, .. } => {
-                unsafe {
-                    if let Ok(text) = CString::new(text) {
-                        imgui::ImGuiIO_AddInputCharactersUTF8(text.as_ptr())
+
+            unsafe {
+                let io = imgui::igGetIO();
+                if touch.is_active() {
+                    let (touch_x, touch_y) = touch.position();
+                    (*io).mouse_pos.x = touch_x;
+                    (*io).mouse_pos.y = touch_y;
+                    (*io).mouse_down[0] = touch.pressed();
+                    (*io).mouse_down[1] = false;
+                    (*io).mouse_down[2] = false;
+                } else if let Some(ref script) = self.script {
+                    let (mouse_x, mouse_y, mouse_down) = script.mouse();
+                    (*io).mouse_pos.x = mouse_x as f32;
+                    (*io).mouse_pos.y = mouse_y as f32;
+                    (*io).mouse_down[0] = mouse_down;
+                    (*io).mouse_down[1] = false;
+                    (*io).mouse_down[2] = false;
+                } else {
+                    let (mouse_state, mouse_x, mouse_y) = self.sdl.mouse().mouse_state();
+                    // A fullscreen window is bigger than what imgui lays out.
+                    let (width, height) = self.window.size();
+                    (*io).mouse_pos.x = mouse_x as f32 * FRAMEBUFFER_WIDTH as f32 / width as f32;
+                    (*io).mouse_pos.y = mouse_y as f32 * FRAMEBUFFER_HEIGHT as f32 / height as f32;
+                    (*io).mouse_down[0] = mouse_state.left();
+                    (*io).mouse_down[1] = mouse_state.right();
+                    (*io).mouse_down[2] = mouse_state.middle();
+                }
+                (*io).mouse_wheel += touch.take_wheel();
+            }
+
+            if let Some(ref commands) = commands {
+                while let Some(command) = commands.try_recv() {
+                    debug!("command: {:?}", command);
+                    match command {
+                        Command::SetText(text) => dialog.set_text(text),
+                        Command::SetPercent(percent) => dialog.set_percent(percent),
+                        Command::SetStep(step) => dialog.set_step(step),
+                        Command::Log(line) => dialog.log(line),
+                        Command::Close(code) => {
+                            exit_code = code;
+                            break 'main
+                        }
+                        Command::EndOfInput if dialog.is_gauge() => break 'main,
+                        Command::EndOfInput => {}
                     }
                 }
             }
-            _ => {}
+
+            self.begin_frame();
+            if let Some(code) = self.renderer.render(&self.window, dialog) {
+                exit_code = code;
+                break
+            }
+
+            self.present();
+            self.end_frame();
+        }
+
+        self.end_frame();
+        if let (Some(path), Some(pixels)) = (self.screenshot.take(), self.last_frame.take()) {
+            save_screenshot(&path, &pixels)
+        }
+        if signals::caught().is_none() {
+            if dialog.options.beep_after {
+                self.play(Sound::Beep)
+            }
+            self.play_closing_animation(dialog)
+        }
+        self.window.hide();
+        if let Some(ref speaker) = self.speaker {
+            speaker.wait()
+        }
+        exit_code
+    }
+
+    // Sets up this frame of the opening or closing animation, `start_time` being when it began,
+    // and says whether there are more frames to come.
+    fn animate(&self, options: &Options, start_time: Instant, closing: bool) -> bool {
+        let animation = match options.animation {
+            Some(animation) => animation,
+            None => return false,
+        };
+        let progress = if options.animation_time > 0.0 {
+            (duration_to_seconds(start_time.elapsed()) / options.animation_time).min(1.0)
+        } else {
+            1.0
+        };
+        let shown = if closing { 1.0 - progress } else { progress };
+        match animation {
+            Animation::Fade => {
+                unsafe {
+                    (*imgui::igGetStyle()).alpha = shown
+                }
+            }
+            Animation::Slide => {
+                // Eased, so that it slows down as it settles.
+                let eased = 1.0 - (1.0 - shown).powi(3);
+                self.renderer.slide.set((1.0 - eased) * SLIDE_DISTANCE)
+            }
         }
+        progress < 1.0
+    }
 
+    // Plays the opening animation backwards over what the dialog last showed.
+    fn play_closing_animation(&mut self, dialog: &mut Dialog) {
+        if dialog.options.animation.is_none() {
+            return
+        }
+        release_input();
+        let start_time = Instant::now();
+        loop {
+            let animating = self.animate(&dialog.options, start_time, true);
+            self.begin_frame();
+            // The dialog already has its answer, so whatever this frame says is ignored.
+            self.renderer.render(&self.window, dialog);
+            self.present();
+            self.end_frame();
+            if !animating {
+                break
+            }
+            thread::sleep(Duration::from_millis(ANIMATION_FRAME_INTERVAL as u64))
+        }
+        // The server shows more dialogs afterward, which mightn't be animated.
         unsafe {
-            let io = imgui::igGetIO();
-            let (mouse_state, mouse_x, mouse_y) = sdl.mouse().mouse_state();
-            (*io).mouse_pos.x = mouse_x as f32;
-            (*io).mouse_pos.y = mouse_y as f32;
-            (*io).mouse_down[0] = mouse_state.left();
-            (*io).mouse_down[1] = mouse_state.right();
-            (*io).mouse_down[2] = mouse_state.middle();
+            (*imgui::igGetStyle()).alpha = 1.0
         }
+        self.renderer.slide.set(0.0)
+    }
+}
+
+#[cfg(target_os="linux")]
+fn enter_sandbox(options: &Options) {
+    if !options.sandbox {
+        return
+    }
+    let allow_exec = options.on_ok.is_some() || options.exec.is_some() || options.speak ||
+        options.kbdlayout;
+    if let Err(message) = sandbox::restrict(allow_exec) {
+        writeln!(io::stderr(), "error: `--sandbox`: {}", message).unwrap();
+        shutdown();
+        process::exit(1)
+    }
+}
+
+#[cfg(not(target_os="linux"))]
+fn enter_sandbox(options: &Options) {
+    if options.sandbox {
+        writeln!(io::stderr(), "error: `--sandbox` is only supported on Linux").unwrap();
+        process::exit(1)
+    }
+}
+
+fn redirect_output(fd: &str) {
+    let fd: c_int = match fd.parse() {
+        Ok(fd) => fd,
+        Err(_) => argument_error("output-fd", "needs a file descriptor number"),
+    };
+    if fd != 1 && unsafe { libc::dup2(fd, 1) } < 0 {
+        writeln!(io::stderr(), "error: can't write results to fd {}", fd).unwrap();
+        process::exit(1)
+    }
+}
+
+fn init_logging(matches: &ArgMatches) {
+    let path = matches.value_of("log-file").map(Path::new);
+    if let Err(message) = logging::init(matches.occurrences_of("verbose"), path) {
+        writeln!(io::stderr(), "error: {}", message).unwrap();
+        process::exit(1)
+    }
+    info!("imdialog started: {:?}", env::args().collect::<Vec<_>>())
+}
+
+#[cfg(feature = "server")]
+fn serve(bus: &str, options: Options) -> ! {
+    server::serve(bus, options)
+}
+
+#[cfg(not(feature = "server"))]
+fn serve(_: &str, _: Options) -> ! {
+    writeln!(io::stderr(), "error: imdialog was built without D-Bus support").unwrap();
+    process::exit(1)
+}
+
+pub fn main() {
+    install_panic_hook();
+    let (matches, help_string) = parse_command_line();
+    init_logging(&matches);
+    if let Some(fd) = matches.value_of("output-fd") {
+        redirect_output(fd)
+    }
+    if let Some(bus) = matches.value_of("server") {
+        serve(bus, Options::new(&matches))
+    }
+    if let Some(corpus) = matches.value_of("compat-test") {
+        compat::run(Path::new(corpus))
+    }
+    if matches.is_present("doctor") {
+        doctor::run(matches.is_present("headless"))
+    }
 
-        if let Some(code) = renderer.render(&window, &mut dialog) {
-            exit_code = code;
-            break
+    if let Some(path) = matches.value_of("wizard") {
+        let options = Options::new(&matches);
+        if options.export_env.is_some() {
+            output::capture()
         }
+        let resume = matches.is_present("wizard-resume");
+        let answer_code = run_wizard(path, resume, &options, &help_string);
+        shutdown();
+        info!("the wizard finished with exit code {}", answer_code);
+        finish(&options, answer_code)
+    }
 
-        window.gl_swap_window();
+    let mut dialog = Dialog::new(&matches, &help_string);
+    if dialog.options.export_env.is_some() {
+        output::capture()
+    }
+    // A remembered answer still runs the commands, as if the user had given it again.
+    if let Some(answer_code) = dialog.remembered_answer() {
+        info!("using the remembered answer {}", answer_code);
+        finish(&dialog.options, answer_code)
     }
 
-    shutdown();
+    let single_choice = if dialog.options.auto_select_single {
+        dialog.single_choice()
+    } else {
+        None
+    };
+    let answer_code = match single_choice {
+        Some(choice) => {
+            output::print_line(choice);
+            0
+        }
+        None => {
+            let mut display = Display::new(&dialog.options);
+            enter_sandbox(&dialog.options);
+            let exit_code = display.run(&mut dialog);
+            shutdown();
+            exit_code
+        }
+    };
+    info!("the dialog closed with exit code {}", answer_code);
+    finish(&dialog.options, answer_code)
+}
+
+// Runs `--on-ok` and `--exec` for the answer, and exits.
+fn finish(options: &Options, answer_code: c_int) -> ! {
+    // An interrupted dialog has no answer for the commands to act on.
+    signals::exit_if_caught();
+    let mut environment = vec![];
+    if let Some(ref name) = options.export_env {
+        let mut answer = output::captured().unwrap_or(vec![]);
+        if answer.last() == Some(&b'\n') {
+            answer.pop();
+        }
+        environment.push((OsString::from(name), OsString::from_vec(answer)))
+    }
+    let mut exit_code = answer_code;
+    if answer_code == 0 {
+        if let Some(ref command_line) = options.on_ok {
+            exit_code = options.spawn.run(command_line, &environment);
+            info!("`--on-ok` exited with {}", exit_code)
+        }
+    }
+    if let Some(ref command_line) = options.exec {
+        environment.push((OsString::from("IMDIALOG_EXIT_CODE"),
+                          OsString::from(answer_code.to_string())));
+        exit_code = options.spawn.run(command_line, &environment);
+        info!("`--exec` exited with {}", exit_code)
+    }
     process::exit(exit_code)
 }
 
@@ -0,0 +1,70 @@
+// imdialog/src/metrics.rs
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+// Only the server reports these, but they're collected in every mode.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub struct Metrics {
+    started: Instant,
+    gpu: String,
+    dialogs_shown: Cell<u32>,
+    frame_start: Cell<Option<Instant>>,
+    last_frame_time: Cell<Duration>,
+    dropped_frames: Cell<u32>,
+    dropped_frame_time: Duration,
+}
+
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+impl Metrics {
+    // Frames that take longer than `dropped_frame_time` to render and present count as dropped.
+    pub fn new(gpu: String, dropped_frame_time: Duration) -> Metrics {
+        Metrics {
+            started: Instant::now(),
+            gpu: gpu,
+            dialogs_shown: Cell::new(0),
+            frame_start: Cell::new(None),
+            last_frame_time: Cell::new(Duration::from_secs(0)),
+            dropped_frames: Cell::new(0),
+            dropped_frame_time: dropped_frame_time,
+        }
+    }
+
+    pub fn dialog_shown(&self) {
+        self.dialogs_shown.set(self.dialogs_shown.get() + 1)
+    }
+
+    pub fn begin_frame(&self) {
+        self.frame_start.set(Some(Instant::now()))
+    }
+
+    pub fn end_frame(&self) {
+        if let Some(start) = self.frame_start.take() {
+            let frame_time = start.elapsed();
+            self.last_frame_time.set(frame_time);
+            if frame_time > self.dropped_frame_time {
+                self.dropped_frames.set(self.dropped_frames.get() + 1)
+            }
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn gpu(&self) -> &str {
+        &self.gpu
+    }
+
+    pub fn dialogs_shown(&self) -> u32 {
+        self.dialogs_shown.get()
+    }
+
+    pub fn last_frame_time(&self) -> Duration {
+        self.last_frame_time.get()
+    }
+
+    pub fn dropped_frames(&self) -> u32 {
+        self.dropped_frames.get()
+    }
+}
@@ -0,0 +1,191 @@
+// imdialog/src/osk.rs
+
+use imgui_sys::{self as imgui, ImGuiCol, ImVec2};
+use libc::{c_char, c_uchar};
+use std::ffi::CString;
+
+static ROWS: [&'static str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+static SHIFTED_ROWS: [&'static str; 4] = ["!@#$%^&*()", "QWERTYUIOP", "ASDFGHJKL", "ZXCVBNM"];
+const KEYS_PER_ROW: f32 = 10.0;
+static KEYPAD: [&'static str; 12] = ["1", "2", "3",
+                                      "4", "5", "6",
+                                      "7", "8", "9",
+                                      "Clear", "0", "Del"];
+const KEYPAD_COLUMNS: usize = 3;
+// How tall a keypad key is, in lines of text, so that it's easy to hit with a finger.
+const KEYPAD_KEY_LINES: f32 = 2.5;
+// The 5, in the middle, which is where the highlight starts.
+const KEYPAD_MIDDLE: usize = 4;
+
+pub enum KeyPress {
+    Character(char),
+    Backspace,
+    Enter,
+}
+
+pub struct OnScreenKeyboard {
+    shifted: bool,
+}
+
+impl OnScreenKeyboard {
+    pub fn new() -> OnScreenKeyboard {
+        OnScreenKeyboard {
+            shifted: false,
+        }
+    }
+
+    pub fn render(&mut self, width: f32) -> Option<KeyPress> {
+        let mut key_press = None;
+        unsafe {
+            let spacing = (*imgui::igGetStyle()).item_spacing.x;
+            let key_width = (width - spacing * (KEYS_PER_ROW - 1.0)) / KEYS_PER_ROW;
+            let key_size = ImVec2 {
+                x: key_width,
+                y: 0.0,
+            };
+
+            let rows = if self.shifted { &SHIFTED_ROWS } else { &ROWS };
+            for row in rows.iter() {
+                for (index, character) in row.chars().enumerate() {
+                    if index > 0 {
+                        imgui::igSameLine(0.0, -1.0)
+                    }
+                    let label = CString::new(character.to_string()).unwrap();
+                    if imgui::igButton(label.as_ptr(), key_size) {
+                        key_press = Some(KeyPress::Character(character));
+                        self.shifted = false
+                    }
+                }
+            }
+
+            let wide_key_size = ImVec2 {
+                x: key_width * 2.0 + spacing,
+                y: 0.0,
+            };
+            let space_size = ImVec2 {
+                x: key_width * 4.0 + spacing * 3.0,
+                y: 0.0,
+            };
+            if imgui::igButton(b"Shift\0" as *const c_uchar as *const c_char, wide_key_size) {
+                self.shifted = !self.shifted
+            }
+            imgui::igSameLine(0.0, -1.0);
+            if imgui::igButton(b"Space\0" as *const c_uchar as *const c_char, space_size) {
+                key_press = Some(KeyPress::Character(' '))
+            }
+            imgui::igSameLine(0.0, -1.0);
+            if imgui::igButton(b"Del\0" as *const c_uchar as *const c_char, wide_key_size) {
+                key_press = Some(KeyPress::Backspace)
+            }
+            imgui::igSameLine(0.0, -1.0);
+            if imgui::igButton(b"Enter\0" as *const c_uchar as *const c_char, wide_key_size) {
+                key_press = Some(KeyPress::Enter)
+            }
+        }
+        key_press
+    }
+}
+
+pub enum KeypadPress {
+    Digit(char),
+    Delete,
+    Clear,
+}
+
+// A big 3×4 keypad of digits, for PINs on touch screens and remote controls. The arrow keys move a
+// highlight around it, which Enter presses.
+pub struct NumericKeypad {
+    highlighted: Option<usize>,
+    press_highlighted: bool,
+}
+
+impl NumericKeypad {
+    pub fn new() -> NumericKeypad {
+        NumericKeypad {
+            highlighted: None,
+            press_highlighted: false,
+        }
+    }
+
+    // The first move shows the highlight on the 5; after that it stops at the edges.
+    pub fn move_highlight(&mut self, rows: isize, columns: isize) {
+        let index = match self.highlighted {
+            Some(index) => index as isize,
+            None => {
+                self.highlighted = Some(KEYPAD_MIDDLE);
+                return
+            }
+        };
+        let columns_per_row = KEYPAD_COLUMNS as isize;
+        let last_row = (KEYPAD.len() / KEYPAD_COLUMNS) as isize - 1;
+        let row = (index / columns_per_row + rows).max(0).min(last_row);
+        let column = (index % columns_per_row + columns).max(0).min(columns_per_row - 1);
+        self.highlighted = Some((row * columns_per_row + column) as usize)
+    }
+
+    pub fn press_highlighted(&mut self) {
+        self.press_highlighted = self.highlighted.is_some()
+    }
+
+    pub fn render(&mut self, width: f32) -> Option<KeypadPress> {
+        let mut pressed = None;
+        if self.press_highlighted {
+            self.press_highlighted = false;
+            pressed = self.highlighted
+        }
+        unsafe {
+            let style = imgui::igGetStyle();
+            let spacing = (*style).item_spacing.x;
+            let columns = KEYPAD_COLUMNS as f32;
+            let key_size = ImVec2 {
+                x: (width - spacing * (columns - 1.0)) / columns,
+                y: imgui::igGetTextLineHeight() * KEYPAD_KEY_LINES,
+            };
+            for (index, key) in KEYPAD.iter().enumerate() {
+                if index % KEYPAD_COLUMNS > 0 {
+                    imgui::igSameLine(0.0, -1.0)
+                }
+                let highlighted = self.highlighted == Some(index);
+                if highlighted {
+                    let color = (*style).colors[ImGuiCol::ButtonHovered as usize];
+                    imgui::igPushStyleColor(ImGuiCol::Button, color)
+                }
+                let label = CString::new(*key).unwrap();
+                if imgui::igButton(label.as_ptr(), key_size) {
+                    pressed = Some(index)
+                }
+                if highlighted {
+                    imgui::igPopStyleColor(1)
+                }
+            }
+        }
+        pressed.map(|index| {
+            match KEYPAD[index] {
+                "Clear" => KeypadPress::Clear,
+                "Del" => KeypadPress::Delete,
+                digit => KeypadPress::Digit(digit.chars().next().unwrap()),
+            }
+        })
+    }
+}
+
+#[cfg(target_os="linux")]
+pub fn keyboard_present() -> bool {
+    let entries = match ::std::fs::read_dir("/dev/input/by-path") {
+        Ok(entries) => entries,
+        Err(_) => return true,
+    };
+    for entry in entries {
+        if let Ok(entry) = entry {
+            if entry.file_name().to_string_lossy().ends_with("-event-kbd") {
+                return true
+            }
+        }
+    }
+    false
+}
+
+#[cfg(not(target_os="linux"))]
+pub fn keyboard_present() -> bool {
+    true
+}
@@ -0,0 +1,157 @@
+// imdialog/src/output.rs
+//
+// Everything a dialog prints as its answer goes through a `ResultWriter`, so that
+// `--output-format` can rewrite it. A template like `{index}: {tag}` is filled in once per line the
+// dialog would have printed:
+//
+//     {tag}    a menu item's tag, or a form field's key
+//     {value}  a menu item's text, what was typed, the number, a form field's value, or the path
+//     {index}  where the item, field or file comes in its list, from 0
+//     {path}   a chosen file
+//
+// Anything a dialog doesn't have comes out empty, and `{{` and `}}` are literal braces.
+//
+// With `--export-env`, the lines are kept for the command's environment instead of being printed.
+
+use std::cell::RefCell;
+use shutdown;
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::process;
+
+// Output being kept instead of printed, innermost last.
+thread_local!(static CAPTURED: RefCell<Vec<Vec<u8>>> = RefCell::new(vec![]));
+
+#[derive(Clone)]
+enum Piece {
+    Text(String),
+    Tag,
+    Value,
+    Index,
+    Path,
+}
+
+// What one line of output is made from.
+#[derive(Default)]
+pub struct Fields<'a> {
+    pub tag: Option<&'a str>,
+    pub value: Option<&'a [u8]>,
+    pub index: Option<usize>,
+    pub path: Option<&'a Path>,
+}
+
+#[derive(Clone)]
+pub struct ResultWriter {
+    template: Option<Vec<Piece>>,
+}
+
+impl ResultWriter {
+    pub fn new(template: Option<&str>) -> Result<ResultWriter, String> {
+        let template = match template {
+            Some(template) => template,
+            None => return Ok(ResultWriter { template: None }),
+        };
+        let mut pieces = vec![];
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find(|c| c == '{' || c == '}') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                text.push_str(&rest[..1]);
+                rest = &rest[2..];
+                continue
+            }
+            let end = match rest.find('}') {
+                Some(end) if rest.starts_with('{') => end,
+                _ => return Err(format!("unmatched brace in `{}`", template)),
+            };
+            let piece = match &rest[1..end] {
+                "tag" => Piece::Tag,
+                "value" => Piece::Value,
+                "index" => Piece::Index,
+                "path" => Piece::Path,
+                name => return Err(format!("unknown placeholder `{{{}}}`", name)),
+            };
+            if !text.is_empty() {
+                pieces.push(Piece::Text(text.split_off(0)))
+            }
+            pieces.push(piece);
+            rest = &rest[end + 1..]
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text))
+        }
+        Ok(ResultWriter { template: Some(pieces) })
+    }
+
+    // The line for `fields`, or `default` without a template. Bytes, since paths needn't be
+    // UTF-8.
+    pub fn format(&self, default: &[u8], fields: &Fields) -> Vec<u8> {
+        let template = match self.template {
+            Some(ref template) => template,
+            None => return default.to_vec(),
+        };
+        let mut line = vec![];
+        for piece in template {
+            match *piece {
+                Piece::Text(ref text) => line.extend_from_slice(text.as_bytes()),
+                Piece::Tag => line.extend_from_slice(fields.tag.unwrap_or("").as_bytes()),
+                Piece::Value => line.extend_from_slice(fields.value.unwrap_or(b"")),
+                Piece::Index => {
+                    if let Some(index) = fields.index {
+                        line.extend_from_slice(index.to_string().as_bytes())
+                    }
+                }
+                Piece::Path => {
+                    if let Some(path) = fields.path {
+                        line.extend_from_slice(path.as_os_str().as_bytes())
+                    }
+                }
+            }
+        }
+        line
+    }
+
+    // Prints the line for `fields` on standard output.
+    pub fn print(&self, default: &[u8], fields: &Fields) {
+        print_line(self.format(default, fields))
+    }
+}
+
+pub fn print_line(mut line: Vec<u8>) {
+    line.push(b'\n');
+    CAPTURED.with(|captured| {
+        match captured.borrow_mut().last_mut() {
+            Some(captured) => captured.extend_from_slice(&line),
+            None => {
+                if let Err(error) = io::stdout().write_all(&line) {
+                    print_failed(error)
+                }
+            }
+        }
+    })
+}
+
+// Standard output being closed by whatever reads it, as with `imdialog ... | true`, is no error.
+fn print_failed(error: io::Error) -> ! {
+    if error.kind() == ErrorKind::BrokenPipe {
+        info!("standard output was closed")
+    } else {
+        writeln!(io::stderr(), "error: couldn't print the answer: {}", error).unwrap()
+    }
+    shutdown();
+    process::exit(1)
+}
+
+// Keeps everything printed from now on for `captured`, instead of printing it. Captures nest, so
+// that a wizard's steps can be kept apart from the answers it gives at the end.
+pub fn capture() {
+    CAPTURED.with(|captured| captured.borrow_mut().push(vec![]))
+}
+
+pub fn captured() -> Option<Vec<u8>> {
+    CAPTURED.with(|captured| captured.borrow_mut().pop())
+}
@@ -0,0 +1,145 @@
+// imdialog/src/password.rs
+
+const CHARACTER_CLASS_COUNT: usize = 4;
+// How many of each class there are to guess from: lowercase, uppercase, digits and symbols.
+const CLASS_SIZES: [u32; 4] = [26, 26, 10, 33];
+const STRONG_PASSWORD_BITS: f32 = 80.0;
+// What `--strength-meter` suggests when there's no `--policy`.
+const ADVISED_LENGTH: usize = 12;
+const ADVISED_CLASSES: usize = 3;
+
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub min_classes: usize,
+}
+
+impl PasswordPolicy {
+    pub fn parse(spec: &str) -> Result<PasswordPolicy, String> {
+        let mut policy = PasswordPolicy {
+            min_length: 0,
+            min_classes: 0,
+        };
+        for rule in spec.split(',') {
+            let mut parts = rule.splitn(2, '=');
+            let (key, value) = match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => (key.trim(), value.trim()),
+                _ => return Err(format!("malformed policy rule `{}`", rule)),
+            };
+            let value: usize = match value.parse() {
+                Ok(value) => value,
+                Err(_) => return Err(format!("policy rule `{}` needs a number", key)),
+            };
+            match key {
+                "minlen" => policy.min_length = value,
+                "classes" => {
+                    if value > CHARACTER_CLASS_COUNT {
+                        return Err(format!("at most {} character classes exist",
+                                           CHARACTER_CLASS_COUNT))
+                    }
+                    policy.min_classes = value
+                }
+                _ => return Err(format!("unknown policy rule `{}`", key)),
+            }
+        }
+        Ok(policy)
+    }
+
+    pub fn advice() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: ADVISED_LENGTH,
+            min_classes: ADVISED_CLASSES,
+        }
+    }
+
+    // Every rule, and whether `password` keeps to it.
+    pub fn checklist(&self, password: &str) -> Vec<(String, bool)> {
+        let mut rules = vec![];
+        if self.min_length > 0 {
+            rules.push((format!("At least {} characters", self.min_length),
+                        password.chars().count() >= self.min_length))
+        }
+        if self.min_classes > 0 {
+            rules.push((format!("At least {} of: lowercase, uppercase, digits, symbols",
+                                self.min_classes),
+                        character_classes(password) >= self.min_classes))
+        }
+        rules
+    }
+
+    pub fn unmet_rules(&self, password: &str) -> Vec<String> {
+        let mut rules = vec![];
+        let length = password.chars().count();
+        if length < self.min_length {
+            rules.push(format!("Use at least {} characters ({} more needed)",
+                               self.min_length,
+                               self.min_length - length))
+        }
+        let classes = character_classes(password);
+        if classes < self.min_classes {
+            rules.push(format!("Mix at least {} of: lowercase, uppercase, digits, symbols \
+                                ({} used)",
+                               self.min_classes,
+                               classes))
+        }
+        rules
+    }
+}
+
+pub fn character_classes(password: &str) -> usize {
+    classes_used(password).iter().filter(|&&present| present).count()
+}
+
+fn classes_used(password: &str) -> [bool; CHARACTER_CLASS_COUNT] {
+    let (mut lower, mut upper, mut digit, mut other) = (false, false, false, false);
+    for character in password.chars() {
+        if character.is_lowercase() {
+            lower = true
+        } else if character.is_uppercase() {
+            upper = true
+        } else if character.is_numeric() {
+            digit = true
+        } else {
+            other = true
+        }
+    }
+    [lower, upper, digit, other]
+}
+
+// Roughly how many bits of guessing the password takes: each character is one of all the
+// characters in the classes used, except that one repeating or counting on from the one before
+// it, as in `aaa` or `1234`, is worth a single bit.
+pub fn entropy_bits(password: &str) -> f32 {
+    let used = classes_used(password);
+    let pool: u32 = CLASS_SIZES.iter().zip(used.iter()).filter(|&(_, &present)| present)
+                                                       .map(|(&size, _)| size)
+                                                       .sum();
+    if pool == 0 {
+        return 0.0
+    }
+    let character_bits = (pool as f32).log2();
+    let mut bits = 0.0;
+    let mut previous: Option<u32> = None;
+    for character in password.chars() {
+        let code = character as u32;
+        let predictable = previous.map_or(false, |previous| {
+            previous == code || previous + 1 == code || code + 1 == previous
+        });
+        bits += if predictable { 1.0 } else { character_bits };
+        previous = Some(code)
+    }
+    bits
+}
+
+pub fn strength(password: &str) -> f32 {
+    (entropy_bits(password) / STRONG_PASSWORD_BITS).min(1.0)
+}
+
+pub fn strength_label(strength: f32) -> &'static str {
+    if strength < 0.4 {
+        "Weak"
+    } else if strength < 0.75 {
+        "Fair"
+    } else {
+        "Strong"
+    }
+}
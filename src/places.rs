@@ -0,0 +1,230 @@
+// imdialog/src/places.rs
+//
+// Quick-access locations for the file dialog: the home directory, the XDG user directories, and
+// mounted removable media.
+
+use libc;
+use locale::Locale;
+use std::env;
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::str;
+
+static USER_DIRS: [(&'static str, &'static str); 6] = [
+    ("XDG_DESKTOP_DIR", "Desktop"),
+    ("XDG_DOCUMENTS_DIR", "Documents"),
+    ("XDG_DOWNLOAD_DIR", "Downloads"),
+    ("XDG_MUSIC_DIR", "Music"),
+    ("XDG_PICTURES_DIR", "Pictures"),
+    ("XDG_VIDEOS_DIR", "Videos"),
+];
+
+static MOUNT_POINT_PARENTS: [&'static str; 3] = ["/media", "/run/media", "/mnt"];
+
+#[derive(Clone)]
+pub struct Place {
+    pub label: String,
+    pub path: PathBuf,
+}
+
+// A mounted removable disk, like a USB stick.
+pub struct Device {
+    pub label: String,
+    pub path: PathBuf,
+    // Bytes that can still be written, if that's known.
+    pub free: Option<u64>,
+}
+
+impl Place {
+    // Parses `--shortcut LABEL=PATH`.
+    pub fn parse(shortcut: &str) -> Option<Place> {
+        let equals = shortcut.find('=')?;
+        let (label, path) = (shortcut[..equals].trim(), shortcut[equals + 1..].trim());
+        if label.is_empty() || path.is_empty() {
+            return None
+        }
+        Some(Place {
+            label: label.to_string(),
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+// The home directory and the user directories in it that exist on this system.
+pub fn standard(locale: &Locale) -> Vec<Place> {
+    let mut places = vec![];
+    let home = match env::var_os("HOME") {
+        Some(ref home) if !home.is_empty() => PathBuf::from(home),
+        _ => return places,
+    };
+    places.push(Place {
+        label: locale.translate("Home").to_string(),
+        path: home.clone(),
+    });
+    let user_dirs = read_user_dirs(&home);
+    for &(key, label) in &USER_DIRS {
+        let path = match user_dirs.iter().find(|&&(ref name, _)| name == key) {
+            Some(&(_, ref path)) => path.clone(),
+            None => continue,
+        };
+        // user-dirs.dirs sets a directory to $HOME to turn it off.
+        if path != home && path.is_dir() {
+            places.push(Place {
+                label: locale.translate(label).to_string(),
+                path: path,
+            })
+        }
+    }
+    places
+}
+
+// Reads `$XDG_CONFIG_HOME/user-dirs.dirs`, which is written as shell assignments like
+// `XDG_DOWNLOAD_DIR="$HOME/Downloads"`.
+fn read_user_dirs(home: &Path) -> Vec<(String, PathBuf)> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(ref directory) if !directory.is_empty() => PathBuf::from(directory),
+        _ => home.join(".config"),
+    };
+    let file = match File::open(config_home.join("user-dirs.dirs")) {
+        Ok(file) => file,
+        Err(_) => return vec![],
+    };
+    let mut user_dirs = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let line = line.trim();
+        let equals = match line.find('=') {
+            Some(equals) if !line.starts_with('#') => equals,
+            _ => continue,
+        };
+        let value = line[equals + 1..].trim().trim_matches('"');
+        let path = if value.starts_with("$HOME/") {
+            home.join(&value["$HOME/".len()..])
+        } else if value == "$HOME" {
+            home.to_path_buf()
+        } else if value.starts_with('/') {
+            PathBuf::from(value)
+        } else {
+            continue
+        };
+        user_dirs.push((line[..equals].to_string(), path))
+    }
+    user_dirs
+}
+
+// Filesystems mounted under the usual places for removable media, from /proc/mounts, labelled
+// with their filesystem labels where they have them.
+pub fn devices() -> Vec<Device> {
+    let file = match File::open("/proc/mounts") {
+        Ok(file) => file,
+        Err(_) => return vec![],
+    };
+    let labels = filesystem_labels();
+    let mut devices = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let mut fields = line.split_whitespace();
+        let (device, mount_point) = match (fields.next(), fields.next()) {
+            (Some(device), Some(mount_point)) => {
+                (PathBuf::from(unescape_mount_point(device)),
+                 PathBuf::from(unescape_mount_point(mount_point)))
+            }
+            _ => continue,
+        };
+        let removable = MOUNT_POINT_PARENTS.iter().any(|parent| {
+            mount_point.starts_with(parent) && mount_point != Path::new(parent)
+        });
+        if !removable || devices.iter().any(|device: &Device| device.path == mount_point) {
+            continue
+        }
+        let label = match labels.iter().find(|&&(ref labelled, _)| *labelled == device) {
+            Some(&(_, ref label)) => label.clone(),
+            None => {
+                match mount_point.file_name() {
+                    Some(name) => name.to_string_lossy().into_owned(),
+                    None => continue,
+                }
+            }
+        };
+        devices.push(Device {
+            label: label,
+            free: free_space(&mount_point),
+            path: mount_point,
+        })
+    }
+    devices
+}
+
+// Pairs of device nodes and labels, from the symlinks udev makes in /dev/disk/by-label.
+fn filesystem_labels() -> Vec<(PathBuf, String)> {
+    let entries = match fs::read_dir("/dev/disk/by-label") {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    entries.filter_map(|entry| {
+        let entry = entry.ok()?;
+        let device = fs::canonicalize(entry.path()).ok()?;
+        Some((device, unescape_udev_label(&entry.file_name().to_string_lossy())))
+    }).collect()
+}
+
+fn free_space(path: &Path) -> Option<u64> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    unsafe {
+        let mut statistics: libc::statvfs = mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut statistics) != 0 {
+            return None
+        }
+        Some(statistics.f_bavail as u64 * statistics.f_frsize as u64)
+    }
+}
+
+// udev writes spaces and slashes in labels as hex escapes like `\x20`.
+fn unescape_udev_label(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut result = vec![];
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'\\' && index + 4 <= bytes.len() && bytes[index + 1] == b'x' {
+            let digits = str::from_utf8(&bytes[index + 2..index + 4]).ok();
+            if let Some(byte) = digits.and_then(|digits| u8::from_str_radix(digits, 16).ok()) {
+                result.push(byte);
+                index += 4;
+                continue
+            }
+        }
+        result.push(bytes[index]);
+        index += 1
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+// /proc/mounts writes spaces and other awkward characters as octal escapes like `\040`.
+fn unescape_mount_point(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut result = vec![];
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'\\' && index + 4 <= bytes.len() {
+            let digits = str::from_utf8(&bytes[index + 1..index + 4]).ok();
+            if let Some(byte) = digits.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+                result.push(byte);
+                index += 4;
+                continue
+            }
+        }
+        result.push(bytes[index]);
+        index += 1
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
@@ -0,0 +1,325 @@
+// imdialog/src/png.rs
+//
+// Just enough PNG to write screenshots: 8-bit RGBA, no filtering, and uncompressed deflate
+// blocks. Reading handles the 8-bit, non-interlaced images that menu icons tend to be.
+
+use inflate;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+static SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+const MAX_STORED_BLOCK_SIZE: usize = 65535;
+
+// `pixels` holds the rows top to bottom, four bytes per pixel.
+pub fn write(path: &Path, width: u32, height: u32, pixels: &[u8]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(&SIGNATURE)?;
+
+    let mut header = vec![];
+    header.extend_from_slice(&be32(width));
+    header.extend_from_slice(&be32(height));
+    // Bit depth 8, color type 6 (RGBA), default compression, filtering, and no interlacing.
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(&mut file, b"IHDR", &header)?;
+
+    let row_size = width as usize * 4;
+    let mut scanlines = Vec::with_capacity((row_size + 1) * height as usize);
+    for row in pixels.chunks(row_size).take(height as usize) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row)
+    }
+    write_chunk(&mut file, b"IDAT", &zlib_stored(&scanlines))?;
+    write_chunk(&mut file, b"IEND", &[])?;
+    file.flush()
+}
+
+// Returns the width, the height, and the pixels as RGBA, laid out like `write` takes them.
+pub fn read(path: &Path) -> io::Result<(u32, u32, Vec<u8>)> {
+    let mut data = vec![];
+    File::open(path)?.read_to_end(&mut data)?;
+    if !data.starts_with(&SIGNATURE) {
+        return Err(invalid("not a PNG file"))
+    }
+
+    let mut header = None;
+    let (mut palette, mut transparency, mut compressed) = (vec![], vec![], vec![]);
+    let mut rest = &data[SIGNATURE.len()..];
+    while rest.len() >= 12 {
+        let length = read_be32(&rest[0..4]) as usize;
+        if rest.len() < length + 12 {
+            break
+        }
+        let (kind, chunk) = (&rest[4..8], &rest[8..8 + length]);
+        match kind {
+            b"IHDR" if length == 13 => header = Some(chunk.to_vec()),
+            b"PLTE" => palette = chunk.to_vec(),
+            b"tRNS" => transparency = chunk.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            _ => {}
+        }
+        rest = &rest[length + 12..]
+    }
+    let header = match header {
+        Some(header) => header,
+        None => return Err(invalid("the PNG has no header")),
+    };
+
+    let (width, height) = (read_be32(&header[0..4]), read_be32(&header[4..8]));
+    let (depth, color_type, interlace) = (header[8], header[9], header[12]);
+    let channels = match color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(invalid("unknown PNG color type")),
+    };
+    if depth != 8 || interlace != 0 {
+        return Err(invalid("only 8-bit, non-interlaced PNGs are supported"))
+    }
+    if width == 0 || height == 0 || width > 4096 || height > 4096 {
+        return Err(invalid("the PNG is empty or too big"))
+    }
+
+    let row_size = width as usize * channels;
+    let scanlines = inflate::zlib(&compressed, (row_size + 1) * height as usize)
+        .map_err(invalid)?;
+    if scanlines.len() != (row_size + 1) * height as usize {
+        return Err(invalid("the PNG has the wrong amount of image data"))
+    }
+    let mut samples: Vec<u8> = Vec::with_capacity(row_size * height as usize);
+    for (y, scanline) in scanlines.chunks(row_size + 1).enumerate() {
+        let filter = scanline[0];
+        for x in 0..row_size {
+            let left = if x >= channels { samples[y * row_size + x - channels] } else { 0 };
+            let up = if y > 0 { samples[(y - 1) * row_size + x] } else { 0 };
+            let up_left = if x >= channels && y > 0 {
+                samples[(y - 1) * row_size + x - channels]
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(invalid("unknown PNG filter")),
+            };
+            samples.push(scanline[x + 1].wrapping_add(predicted))
+        }
+    }
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    for sample in samples.chunks(channels) {
+        match color_type {
+            0 => pixels.extend_from_slice(&[sample[0], sample[0], sample[0], 255]),
+            2 => pixels.extend_from_slice(&[sample[0], sample[1], sample[2], 255]),
+            3 => {
+                let index = sample[0] as usize;
+                if index * 3 + 3 > palette.len() {
+                    return Err(invalid("a PNG pixel is outside the palette"))
+                }
+                pixels.extend_from_slice(&palette[index * 3..index * 3 + 3]);
+                pixels.push(*transparency.get(index).unwrap_or(&255))
+            }
+            4 => pixels.extend_from_slice(&[sample[0], sample[0], sample[0], sample[1]]),
+            _ => pixels.extend_from_slice(sample),
+        }
+    }
+    Ok((width, height, pixels))
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (to_left, to_up, to_up_left) = ((estimate - left as i16).abs(),
+                                        (estimate - up as i16).abs(),
+                                        (estimate - up_left as i16).abs());
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn write_chunk<W>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> where W: Write {
+    writer.write_all(&be32(data.len() as u32))?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+    writer.write_all(&be32(crc.finish()))
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK_SIZE).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        stream.push(if last { 1 } else { 0 });
+        stream.extend_from_slice(&[length as u8, (length >> 8) as u8]);
+        stream.extend_from_slice(&[!length as u8, (!length >> 8) as u8]);
+        stream.extend_from_slice(block)
+    }
+    stream.extend_from_slice(&be32(adler32(data)));
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+struct Crc32 {
+    table: [u32; 256],
+    crc: u32,
+}
+
+impl Crc32 {
+    fn new() -> Crc32 {
+        let mut table = [0; 256];
+        for (index, entry) in table.iter_mut().enumerate() {
+            let mut value = index as u32;
+            for _ in 0..8 {
+                value = if value & 1 != 0 { 0xedb88320 ^ (value >> 1) } else { value >> 1 }
+            }
+            *entry = value
+        }
+        Crc32 {
+            table: table,
+            crc: 0xffffffff,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.crc = self.table[((self.crc ^ byte as u32) & 0xff) as usize] ^ (self.crc >> 8)
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.crc ^ 0xffffffff
+    }
+}
+
+fn be32(value: u32) -> [u8; 4] {
+    [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+fn read_be32(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process;
+    use super::{Crc32, SIGNATURE, adler32, be32, read, write, write_chunk, zlib_stored};
+
+    fn temporary_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("imdialog-png-test-{}-{}.png", name, process::id()))
+    }
+
+    // Writes a PNG with the given header fields and scanlines.
+    fn write_raw(path: &Path,
+                 size: (u32, u32),
+                 depth_and_color_type: (u8, u8),
+                 chunks: &[(&[u8; 4], Vec<u8>)],
+                 scanlines: &[u8]) {
+        let mut data = SIGNATURE.to_vec();
+        let mut header = vec![];
+        header.extend_from_slice(&be32(size.0));
+        header.extend_from_slice(&be32(size.1));
+        header.extend_from_slice(&[depth_and_color_type.0, depth_and_color_type.1, 0, 0, 0]);
+        write_chunk(&mut data, b"IHDR", &header).unwrap();
+        for &(kind, ref chunk) in chunks {
+            write_chunk(&mut data, kind, chunk).unwrap()
+        }
+        write_chunk(&mut data, b"IDAT", &zlib_stored(scanlines)).unwrap();
+        write_chunk(&mut data, b"IEND", &[]).unwrap();
+        fs::write(path, data).unwrap()
+    }
+
+    #[test]
+    fn checksums_match_the_standard_ones() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xcbf43926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let path = temporary_path("round-trip");
+        let pixels: Vec<u8> = (0..3 * 2 * 4).map(|byte| byte as u8 * 10).collect();
+        write(&path, 3, 2, &pixels).unwrap();
+        assert_eq!(read(&path).unwrap(), (3, 2, pixels));
+
+        // Bigger than one stored deflate block.
+        let pixels = vec![7; 200 * 100 * 4];
+        write(&path, 200, 100, &pixels).unwrap();
+        assert_eq!(read(&path).unwrap(), (200, 100, pixels));
+        drop(fs::remove_file(path))
+    }
+
+    #[test]
+    fn undoes_scanline_filters() {
+        let path = temporary_path("filters");
+        // Sub, up, average and Paeth, on a two-pixel-wide grayscale image.
+        write_raw(&path, (2, 4), (8, 0), &[], &[1, 10, 5, 2, 1, 2, 3, 0, 0, 4, 0, 0]);
+        let (width, height, pixels) = read(&path).unwrap();
+        assert_eq!((width, height), (2, 4));
+        let grays: Vec<u8> = pixels.chunks(4).map(|pixel| {
+            assert_eq!((pixel[0], pixel[1], pixel[3]), (pixel[2], pixel[2], 255));
+            pixel[0]
+        }).collect();
+        assert_eq!(grays, [10, 15, 11, 17, 5, 11, 5, 11]);
+        drop(fs::remove_file(path))
+    }
+
+    #[test]
+    fn looks_colors_up_in_the_palette() {
+        let path = temporary_path("palette");
+        let chunks = [(b"PLTE", vec![0, 0, 0, 1, 2, 3]), (b"tRNS", vec![255, 128])];
+        write_raw(&path, (2, 1), (8, 3), &chunks, &[0, 1, 0]);
+        assert_eq!(read(&path).unwrap().2, [1, 2, 3, 128, 0, 0, 0, 255]);
+        write_raw(&path, (1, 1), (8, 3), &chunks, &[0, 2]);
+        assert!(read(&path).is_err());
+        drop(fs::remove_file(path))
+    }
+
+    #[test]
+    fn rejects_what_it_cant_read() {
+        let path = temporary_path("unsupported");
+        write_raw(&path, (1, 1), (16, 0), &[], &[0, 0, 0]);
+        assert!(read(&path).is_err());
+        write_raw(&path, (2, 1), (8, 0), &[], &[0, 0]);
+        assert!(read(&path).is_err());
+        write_raw(&path, (1, 1), (8, 0), &[], &[5, 0]);
+        assert!(read(&path).is_err());
+        fs::write(&path, b"GIF89a").unwrap();
+        assert!(read(&path).is_err());
+        drop(fs::remove_file(path))
+    }
+}
@@ -0,0 +1,89 @@
+// imdialog/src/recovery.rs
+
+use state::{self, escape, unescape};
+use std::io::{self, Write};
+
+static SNAPSHOT_FILENAME: &'static str = "server-snapshot";
+
+// The state of the dialog the server is showing. If the server dies, the client's retry of the
+// same call gets the dialog back the way the user left it.
+pub struct Snapshot {
+    pub method: String,
+    pub argument: String,
+    pub state: Vec<(String, String)>,
+}
+
+impl Snapshot {
+    pub fn load() -> Option<Snapshot> {
+        let mut lines = state::read_lines(SNAPSHOT_FILENAME).into_iter().map(|line| {
+            match line.find('\t') {
+                Some(tab) => (unescape(&line[..tab]), unescape(&line[tab + 1..])),
+                None => (unescape(&line), String::new()),
+            }
+        });
+        let method = match lines.next() {
+            Some((key, method)) if key == "method" => method,
+            _ => return None,
+        };
+        let argument = match lines.next() {
+            Some((key, argument)) if key == "argument" => argument,
+            _ => return None,
+        };
+        Some(Snapshot {
+            method: method,
+            argument: argument,
+            state: lines.collect(),
+        })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut lines = vec![format!("method\t{}", escape(&self.method)),
+                             format!("argument\t{}", escape(&self.argument))];
+        for &(ref key, ref value) in &self.state {
+            lines.push(format!("{}\t{}", escape(key), escape(value)))
+        }
+        state::write_lines(SNAPSHOT_FILENAME, &lines)
+    }
+}
+
+// Saves a snapshot whenever the dialog's state changes, which can only happen on a frame.
+pub struct Recorder {
+    snapshot: Snapshot,
+    saved: bool,
+    failed: bool,
+}
+
+impl Recorder {
+    pub fn new(method: &str, argument: &str) -> Recorder {
+        Recorder {
+            snapshot: Snapshot {
+                method: method.to_string(),
+                argument: argument.to_string(),
+                state: vec![],
+            },
+            saved: false,
+            failed: false,
+        }
+    }
+
+    pub fn record(&mut self, state: Vec<(String, String)>) {
+        if self.failed || (self.saved && state == self.snapshot.state) {
+            return
+        }
+        self.snapshot.state = state;
+        match self.snapshot.save() {
+            Ok(()) => self.saved = true,
+            Err(error) => {
+                writeln!(io::stderr(), "warning: couldn't save the dialog state: {}", error)
+                    .unwrap();
+                self.failed = true
+            }
+        }
+    }
+
+    // The dialog was answered, so there's nothing left to recover.
+    pub fn finish(self) {
+        drop(state::write_lines(SNAPSHOT_FILENAME, &[]))
+    }
+}
+
@@ -0,0 +1,231 @@
+// imdialog/src/sandbox.rs
+
+use libc::{self, c_uchar, c_uint, c_ulong, c_ushort};
+use std::io;
+
+// Classic BPF opcodes and seccomp return values, from <linux/filter.h> and <linux/seccomp.h>.
+const BPF_LD_W_ABS: c_ushort = 0x20;
+const BPF_JEQ_K: c_ushort = 0x15;
+const BPF_JGE_K: c_ushort = 0x35;
+const BPF_RET_K: c_ushort = 0x06;
+const SECCOMP_RET_KILL: u32 = 0x00000000;
+const SECCOMP_RET_ERRNO: u32 = 0x00050000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff0000;
+const SECCOMP_SET_MODE_FILTER: c_uint = 1;
+// Applies the filter to every thread of the process rather than just the calling one.
+const SECCOMP_FILTER_FLAG_TSYNC: c_uint = 1;
+
+// Offsets into `struct seccomp_data`.
+const SYSCALL_NUMBER_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+const FIRST_ARGUMENT_OFFSET: u32 = 16;
+
+#[cfg(target_arch="x86_64")]
+const AUDIT_ARCH: u32 = 0xc000003e;
+#[cfg(target_arch="aarch64")]
+const AUDIT_ARCH: u32 = 0xc00000b7;
+// Set in the numbers of x32 system calls, which come in with the x86_64 architecture and would
+// otherwise get past the checks below.
+#[cfg(target_arch="x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x40000000;
+
+#[repr(C)]
+struct SockFilter {
+    code: c_ushort,
+    jt: c_uchar,
+    jf: c_uchar,
+    k: u32,
+}
+
+#[repr(C)]
+struct SockFprog {
+    len: c_ushort,
+    filter: *const SockFilter,
+}
+
+fn statement(code: c_ushort, k: u32) -> SockFilter {
+    SockFilter {
+        code: code,
+        jt: 0,
+        jf: 0,
+        k: k,
+    }
+}
+
+fn jump(k: u32, jt: c_uchar, jf: c_uchar) -> SockFilter {
+    SockFilter {
+        code: BPF_JEQ_K,
+        jt: jt,
+        jf: jf,
+        k: k,
+    }
+}
+
+#[cfg(target_arch="x86_64")]
+fn jump_at_least(k: u32, jt: c_uchar, jf: c_uchar) -> SockFilter {
+    SockFilter {
+        code: BPF_JGE_K,
+        jt: jt,
+        jf: jf,
+        k: k,
+    }
+}
+
+// Makes `execve` and the creation of network sockets fail with EPERM from now on, in every
+// thread. Unix domain sockets stay available, since the display server and D-Bus connections may
+// need them.
+#[cfg(any(target_arch="x86_64", target_arch="aarch64"))]
+pub fn restrict(allow_exec: bool) -> Result<(), String> {
+    let program = filter(allow_exec);
+    let fprog = SockFprog {
+        len: program.len() as c_ushort,
+        filter: program.as_ptr(),
+    };
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1 as c_ulong, 0 as c_ulong, 0 as c_ulong,
+                       0 as c_ulong) != 0 {
+            return Err(format!("couldn't set no_new_privs: {}", io::Error::last_os_error()))
+        }
+        // By now the display connection has started threads of its own, which a plain
+        // `PR_SET_SECCOMP` would leave unfiltered.
+        match libc::syscall(libc::SYS_seccomp,
+                            SECCOMP_SET_MODE_FILTER,
+                            SECCOMP_FILTER_FLAG_TSYNC,
+                            &fprog as *const SockFprog) {
+            0 => {}
+            -1 => {
+                return Err(format!("couldn't install the seccomp filter: {}",
+                                   io::Error::last_os_error()))
+            }
+            thread => {
+                return Err(format!("couldn't install the seccomp filter in thread {}", thread))
+            }
+        }
+    }
+    Ok(())
+}
+
+// The program `restrict` installs.
+#[cfg(any(target_arch="x86_64", target_arch="aarch64"))]
+fn filter(allow_exec: bool) -> Vec<SockFilter> {
+    let deny = SECCOMP_RET_ERRNO | (libc::EPERM as u32);
+    let mut program = vec![
+        statement(BPF_LD_W_ABS, ARCH_OFFSET),
+        jump(AUDIT_ARCH, 1, 0),
+        statement(BPF_RET_K, SECCOMP_RET_KILL),
+        statement(BPF_LD_W_ABS, SYSCALL_NUMBER_OFFSET),
+    ];
+    #[cfg(target_arch="x86_64")]
+    program.extend(vec![
+        jump_at_least(X32_SYSCALL_BIT, 0, 1),
+        statement(BPF_RET_K, SECCOMP_RET_KILL),
+    ]);
+    if !allow_exec {
+        for &syscall in &[libc::SYS_execve, libc::SYS_execveat] {
+            program.push(jump(syscall as u32, 0, 1));
+            program.push(statement(BPF_RET_K, deny));
+        }
+    }
+    program.extend(vec![
+        jump(libc::SYS_socket as u32, 0, 5),
+        statement(BPF_LD_W_ABS, FIRST_ARGUMENT_OFFSET),
+        jump(libc::AF_INET as u32, 2, 0),
+        jump(libc::AF_INET6 as u32, 1, 0),
+        jump(libc::AF_PACKET as u32, 0, 1),
+        statement(BPF_RET_K, deny),
+        statement(BPF_RET_K, SECCOMP_RET_ALLOW),
+    ]);
+    program
+}
+
+#[cfg(not(any(target_arch="x86_64", target_arch="aarch64")))]
+pub fn restrict(_: bool) -> Result<(), String> {
+    Err("sandboxing is not supported on this architecture".to_string())
+}
+
+#[cfg(all(test, any(target_arch="x86_64", target_arch="aarch64")))]
+mod tests {
+    use libc::{self, c_long};
+    use std::mem;
+    use super::{AUDIT_ARCH, BPF_JEQ_K, BPF_JGE_K, BPF_LD_W_ABS, BPF_RET_K, SECCOMP_RET_ALLOW};
+    use super::{SECCOMP_RET_ERRNO, SECCOMP_RET_KILL, SockFilter, filter};
+
+    const DENY: u32 = SECCOMP_RET_ERRNO | libc::EPERM as u32;
+
+    // Runs `program` as the kernel would for a call with this architecture, number and first
+    // argument, checking that it never jumps off the end.
+    fn run(program: &[SockFilter], arch: u32, syscall: c_long, argument: c_long) -> u32 {
+        // The words of `struct seccomp_data` up to the first argument.
+        let mut data = [0u32; 5];
+        data[0] = syscall as u32;
+        data[1] = arch;
+        data[4] = argument as u32;
+        let (mut accumulator, mut pc) = (0, 0);
+        loop {
+            let instruction = &program[pc];
+            pc += 1;
+            match instruction.code {
+                BPF_LD_W_ABS => accumulator = data[instruction.k as usize / 4],
+                BPF_JEQ_K | BPF_JGE_K => {
+                    let taken = if instruction.code == BPF_JEQ_K {
+                        accumulator == instruction.k
+                    } else {
+                        accumulator >= instruction.k
+                    };
+                    let offset = if taken { instruction.jt } else { instruction.jf };
+                    pc += offset as usize
+                }
+                BPF_RET_K => return instruction.k,
+                code => panic!("unknown BPF instruction {:#x}", code),
+            }
+            assert!(pc < program.len(), "the program runs off its end");
+        }
+    }
+
+    #[test]
+    fn instructions_are_laid_out_like_sock_filter() {
+        assert_eq!(mem::size_of::<SockFilter>(), 8);
+        assert_eq!(mem::align_of::<SockFilter>(), 4);
+        let instruction = SockFilter {
+            code: 0x0102,
+            jt: 3,
+            jf: 4,
+            k: 0x05060708,
+        };
+        let bytes: [u8; 8] = unsafe { mem::transmute(instruction) };
+        assert_eq!(bytes, [0x02, 0x01, 3, 4, 0x08, 0x07, 0x06, 0x05]);
+    }
+
+    #[test]
+    fn denies_exec_unless_allowed() {
+        let program = filter(false);
+        assert_eq!(run(&program, AUDIT_ARCH, libc::SYS_execve, 0), DENY);
+        assert_eq!(run(&program, AUDIT_ARCH, libc::SYS_execveat, 0), DENY);
+        assert_eq!(run(&program, AUDIT_ARCH, libc::SYS_write, 0), SECCOMP_RET_ALLOW);
+        let program = filter(true);
+        assert_eq!(run(&program, AUDIT_ARCH, libc::SYS_execve, 0), SECCOMP_RET_ALLOW);
+    }
+
+    #[test]
+    fn denies_network_sockets_only() {
+        for &allow_exec in &[false, true] {
+            let program = filter(allow_exec);
+            for &family in &[libc::AF_INET, libc::AF_INET6, libc::AF_PACKET] {
+                assert_eq!(run(&program, AUDIT_ARCH, libc::SYS_socket, family as c_long), DENY)
+            }
+            let result = run(&program, AUDIT_ARCH, libc::SYS_socket, libc::AF_UNIX as c_long);
+            assert_eq!(result, SECCOMP_RET_ALLOW);
+            // Only `socket` looks at its first argument.
+            let result = run(&program, AUDIT_ARCH, libc::SYS_read, libc::AF_INET as c_long);
+            assert_eq!(result, SECCOMP_RET_ALLOW)
+        }
+    }
+
+    #[test]
+    fn kills_other_architectures() {
+        let program = filter(true);
+        assert_eq!(run(&program, 0x40000003, libc::SYS_write, 0), SECCOMP_RET_KILL);
+        #[cfg(target_arch="x86_64")]
+        assert_eq!(run(&program, AUDIT_ARCH, 0x40000000 | libc::SYS_write, 0), SECCOMP_RET_KILL);
+    }
+}
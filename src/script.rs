@@ -0,0 +1,151 @@
+// imdialog/src/script.rs
+
+use sdl2::event::Event;
+use sdl2::keyboard::{self, Keycode, Scancode};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+enum Step {
+    Event(Event),
+    MoveMouse(i32, i32),
+    MouseButton(bool),
+    Wait,
+}
+
+pub enum Frame {
+    Event(Event),
+    Idle,
+    Finished,
+}
+
+// A list of synthetic input, one step per frame. Each line is one of:
+//
+//     key NAME / keydown NAME / keyup NAME    SDL scancode names, like `Return` or `Down`
+//     type TEXT
+//     move X Y / press / release / click X Y
+//     wheel LINES
+//     wait FRAMES
+//
+// Blank lines and lines starting with `#` are ignored.
+pub struct Script {
+    steps: VecDeque<Step>,
+    mouse_position: (i32, i32),
+    mouse_down: bool,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Script, String> {
+        let file = File::open(path).map_err(|error| error.to_string())?;
+        let mut steps = VecDeque::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|error| error.to_string())?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+            let (name, argument) = match line.find(' ') {
+                Some(space) => (&line[..space], line[space + 1..].trim()),
+                None => (line, ""),
+            };
+            let parsed = parse_step(name, argument).ok_or_else(|| {
+                format!("line {}: can't understand `{}`", index + 1, line)
+            })?;
+            steps.extend(parsed)
+        }
+        Ok(Script {
+            steps: steps,
+            mouse_position: (0, 0),
+            mouse_down: false,
+        })
+    }
+
+    pub fn next_frame(&mut self) -> Frame {
+        match self.steps.pop_front() {
+            Some(Step::Event(event)) => Frame::Event(event),
+            Some(Step::MoveMouse(x, y)) => {
+                self.mouse_position = (x, y);
+                Frame::Idle
+            }
+            Some(Step::MouseButton(down)) => {
+                self.mouse_down = down;
+                Frame::Idle
+            }
+            Some(Step::Wait) => Frame::Idle,
+            None => Frame::Finished,
+        }
+    }
+
+    pub fn mouse(&self) -> (i32, i32, bool) {
+        (self.mouse_position.0, self.mouse_position.1, self.mouse_down)
+    }
+}
+
+fn parse_step(name: &str, argument: &str) -> Option<Vec<Step>> {
+    let numbers: Vec<i32> = argument.split_whitespace().filter_map(|word| word.parse().ok())
+                                                       .collect();
+    match name {
+        "key" => {
+            let scancode = Scancode::from_name(argument)?;
+            Some(vec![Step::Event(key_event(scancode, true)),
+                      Step::Event(key_event(scancode, false))])
+        }
+        "keydown" => Scancode::from_name(argument).map(|scancode| {
+            vec![Step::Event(key_event(scancode, true))]
+        }),
+        "keyup" => Scancode::from_name(argument).map(|scancode| {
+            vec![Step::Event(key_event(scancode, false))]
+        }),
+        "type" => {
+            Some(vec![Step::Event(Event::TextInput {
+                timestamp: 0,
+                window_id: 0,
+                text: argument.to_string(),
+            })])
+        }
+        "move" if numbers.len() == 2 => Some(vec![Step::MoveMouse(numbers[0], numbers[1])]),
+        "press" => Some(vec![Step::MouseButton(true)]),
+        "release" => Some(vec![Step::MouseButton(false)]),
+        "click" if numbers.len() == 2 => {
+            Some(vec![Step::MoveMouse(numbers[0], numbers[1]),
+                      Step::MouseButton(true),
+                      Step::MouseButton(false)])
+        }
+        "wheel" if numbers.len() == 1 => {
+            Some(vec![Step::Event(Event::MouseWheel {
+                timestamp: 0,
+                window_id: 0,
+                which: 0,
+                x: 0,
+                y: numbers[0],
+            })])
+        }
+        "wait" => {
+            argument.parse().ok().map(|frames: usize| (0..frames).map(|_| Step::Wait).collect())
+        }
+        _ => None,
+    }
+}
+
+fn key_event(scancode: Scancode, down: bool) -> Event {
+    if down {
+        Event::KeyDown {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Keycode::from_scancode(scancode),
+            scancode: Some(scancode),
+            keymod: keyboard::NOMOD,
+            repeat: false,
+        }
+    } else {
+        Event::KeyUp {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Keycode::from_scancode(scancode),
+            scancode: Some(scancode),
+            keymod: keyboard::NOMOD,
+            repeat: false,
+        }
+    }
+}
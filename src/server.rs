@@ -0,0 +1,234 @@
+// imdialog/src/server.rs
+
+use dbus::{BusType, Connection, ConnectionItem, Message, NameFlag, RequestNameReply};
+use libc::c_int;
+use metrics::Metrics;
+use output;
+use recovery::{Recorder, Snapshot};
+use signals;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use {Dialog, Display, Options, enter_sandbox};
+
+static BUS_NAME: &'static str = "org.imdialog.Dialog";
+static OBJECT_PATH: &'static str = "/org/imdialog/Dialog";
+static INTERFACE: &'static str = "org.imdialog.Dialog";
+static INTROSPECTABLE_INTERFACE: &'static str = "org.freedesktop.DBus.Introspectable";
+
+static INTROSPECTION: &'static str = r#"<!DOCTYPE node PUBLIC
+    "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+    "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="org.imdialog.Dialog">
+    <method name="Message">
+      <arg type="s" name="text" direction="in"/>
+      <arg type="u" name="response" direction="out"/>
+    </method>
+    <method name="Question">
+      <arg type="s" name="text" direction="in"/>
+      <arg type="u" name="response" direction="out"/>
+      <arg type="b" name="yes" direction="out"/>
+    </method>
+    <method name="FileSelection">
+      <arg type="s" name="directory" direction="in"/>
+      <arg type="u" name="response" direction="out"/>
+      <arg type="s" name="path" direction="out"/>
+    </method>
+    <method name="Metrics">
+      <arg type="t" name="uptime_seconds" direction="out"/>
+      <arg type="u" name="dialogs_shown" direction="out"/>
+      <arg type="d" name="last_frame_milliseconds" direction="out"/>
+      <arg type="s" name="gpu" direction="out"/>
+      <arg type="u" name="dropped_frames" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg type="s" name="xml_data" direction="out"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+// Response codes follow the desktop portal convention.
+const RESPONSE_SUCCESS: u32 = 0;
+const RESPONSE_CANCELLED: u32 = 1;
+const RESPONSE_OTHER: u32 = 2;
+// How long the server waits for a call before checking for SIGINT and SIGTERM, in milliseconds.
+const SIGNAL_POLL_INTERVAL: i32 = 100;
+
+pub(crate) fn serve(bus: &str, options: Options) -> ! {
+    let bus_type = if bus == "system" { BusType::System } else { BusType::Session };
+    let connection = match Connection::get_private(bus_type) {
+        Ok(connection) => connection,
+        Err(error) => fail(&format!("couldn't connect to the {} bus: {}", bus, error)),
+    };
+    match connection.register_name(BUS_NAME, NameFlag::DoNotQueue.value()) {
+        Ok(RequestNameReply::PrimaryOwner) | Ok(RequestNameReply::AlreadyOwner) => {}
+        Ok(_) => fail(&format!("`{}` is already owned by another process", BUS_NAME)),
+        Err(error) => fail(&format!("couldn't own `{}`: {}", BUS_NAME, error)),
+    }
+    if let Err(error) = connection.register_object_path(OBJECT_PATH) {
+        fail(&format!("couldn't register `{}`: {}", OBJECT_PATH, error))
+    }
+
+    // Dialogs are shown one at a time; calls for dialogs that arrive while one is up wait in
+    // `pending` and get their reply once the user has answered. Queries are answered between
+    // frames.
+    let mut display = Display::new(&options);
+    display.wake_periodically = true;
+    enter_sandbox(&options);
+    let mut pending = VecDeque::new();
+    loop {
+        // SDL only queues a quit event for a signal, which nothing reads between dialogs.
+        signals::exit_if_caught();
+        let call = match pending.pop_front() {
+            Some(call) => call,
+            None => {
+                match connection.iter(SIGNAL_POLL_INTERVAL).next() {
+                    Some(ConnectionItem::MethodCall(call)) => call,
+                    Some(_) => continue,
+                    None => break,
+                }
+            }
+        };
+        let reply = handle_call(&connection, &mut pending, &call, &mut display, &options);
+        drop(connection.send(reply))
+    }
+    process::exit(0)
+}
+
+fn handle_call(connection: &Connection,
+               pending: &mut VecDeque<Message>,
+               call: &Message,
+               display: &mut Display,
+               options: &Options)
+               -> Message {
+    if let Some(reply) = query_reply(call, &display.metrics) {
+        return reply
+    }
+    let interface = call.interface().map(|interface| interface.to_string());
+    let member = call.member().map(|member| member.to_string()).unwrap_or(String::new());
+    let argument: Option<&str> = call.get1();
+    let mut show = |display: &mut Display, dialog: &mut Dialog, argument: &str| {
+        run(connection, pending, display, dialog, &member, argument)
+    };
+    match (interface.as_ref().map(|interface| &interface[..]), &member[..], argument) {
+        (Some(interface), "Message", Some(text)) if interface == INTERFACE => {
+            let mut dialog = Dialog::message(text, 0, 0, options.clone());
+            let code = show(display, &mut dialog, text);
+            call.method_return().append1(response(code, options))
+        }
+        (Some(interface), "Question", Some(text)) if interface == INTERFACE => {
+            let mut dialog = Dialog::question(text, 0, 0, options.clone());
+            let code = show(display, &mut dialog, text);
+            let response = if code == 1 { RESPONSE_SUCCESS } else { response(code, options) };
+            call.method_return().append2(response, code == 0)
+        }
+        (Some(interface), "FileSelection", Some(directory)) if interface == INTERFACE => {
+            let path = match fs::canonicalize(directory) {
+                Ok(path) => path,
+                Err(error) => {
+                    return error_reply(call,
+                                       "org.freedesktop.DBus.Error.InvalidArgs",
+                                       &format!("`{}`: {}", directory, error))
+                }
+            };
+            let mut dialog = Dialog::file(path, 0, 0, options.clone());
+            let code = show(display, &mut dialog, directory);
+            let path = match dialog.selected_file() {
+                Some(ref path) if code == 0 => path.display().to_string(),
+                _ => String::new(),
+            };
+            call.method_return().append2(response(code, options), path)
+        }
+        _ => {
+            error_reply(call,
+                        "org.freedesktop.DBus.Error.UnknownMethod",
+                        &format!("no method `{}` with that signature", member))
+        }
+    }
+}
+
+// Shows the dialog, picking up where a previous server left off if it died showing the same one.
+fn run(connection: &Connection,
+       pending: &mut VecDeque<Message>,
+       display: &mut Display,
+       dialog: &mut Dialog,
+       method: &str,
+       argument: &str)
+       -> c_int {
+    if let Some(snapshot) = Snapshot::load() {
+        if snapshot.method == method && snapshot.argument == argument {
+            dialog.restore(&snapshot.state)
+        }
+    }
+    let mut recorder = Recorder::new(method, argument);
+    let metrics = display.metrics.clone();
+    // The answer goes back in the reply, so nothing the dialog prints goes to standard output.
+    output::capture();
+    let code = display.run_observed(dialog, |dialog| {
+        recorder.record(dialog.snapshot());
+        answer_queries(connection, pending, &metrics)
+    });
+    output::captured();
+    recorder.finish();
+    code
+}
+
+// Replies to calls that don't need the display right away, and leaves the rest for later.
+fn answer_queries(connection: &Connection, pending: &mut VecDeque<Message>, metrics: &Metrics) {
+    for item in connection.iter(0) {
+        match item {
+            ConnectionItem::MethodCall(call) => {
+                match query_reply(&call, metrics) {
+                    Some(reply) => drop(connection.send(reply)),
+                    None => pending.push_back(call),
+                }
+            }
+            ConnectionItem::Nothing => break,
+            _ => {}
+        }
+    }
+}
+
+fn query_reply(call: &Message, metrics: &Metrics) -> Option<Message> {
+    let interface = call.interface().map(|interface| interface.to_string());
+    let member = call.member().map(|member| member.to_string()).unwrap_or(String::new());
+    match (interface.as_ref().map(|interface| &interface[..]), &member[..]) {
+        (Some(interface), "Introspect") if interface == INTROSPECTABLE_INTERFACE => {
+            Some(call.method_return().append1(INTROSPECTION))
+        }
+        (Some(interface), "Metrics") if interface == INTERFACE => {
+            let last_frame_time = metrics.last_frame_time();
+            let last_frame_milliseconds = last_frame_time.as_secs() as f64 * 1000.0 +
+                last_frame_time.subsec_nanos() as f64 / 1000000.0;
+            Some(call.method_return().append3(metrics.uptime().as_secs(),
+                                              metrics.dialogs_shown(),
+                                              last_frame_milliseconds)
+                                     .append2(metrics.gpu(), metrics.dropped_frames()))
+        }
+        _ => None,
+    }
+}
+
+// Escape is a cancellation too, whatever `--esc-code` makes it exit with.
+fn response(exit_code: c_int, options: &Options) -> u32 {
+    match exit_code {
+        0 => RESPONSE_SUCCESS,
+        1 => RESPONSE_CANCELLED,
+        _ if exit_code == options.esc_code => RESPONSE_CANCELLED,
+        _ => RESPONSE_OTHER,
+    }
+}
+
+fn error_reply(call: &Message, name: &str, message: &str) -> Message {
+    Message::new_error(call, name, message).unwrap()
+}
+
+fn fail(message: &str) -> ! {
+    writeln!(io::stderr(), "error: {}", message).unwrap();
+    process::exit(1)
+}
@@ -0,0 +1,71 @@
+// imdialog/src/signals.rs
+//
+// Lets SIGINT and SIGTERM end the event loop like closing the window does, so that `shutdown`
+// gets to put the console keyboard back, and remembers which one it was for the exit code.
+//
+// SDL already turns these signals into quit events, from handlers that it installs while starting
+// up. Ours are installed over those, note the signal, and then hand it on.
+
+use libc::{self, c_int, sighandler_t};
+use shutdown;
+use std::mem;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static SIGNALS: [c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+static CAUGHT: AtomicUsize = AtomicUsize::new(0);
+// The handlers that were there before ours, in the same order as `SIGNALS`.
+static PREVIOUS: [AtomicUsize; 2] = [AtomicUsize::new(0), AtomicUsize::new(0)];
+
+// Call this after SDL's video subsystem is up, or SDL will replace these handlers.
+pub fn install() {
+    for (index, &signal) in SIGNALS.iter().enumerate() {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(c_int) as sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut previous: libc::sigaction = mem::zeroed();
+            if libc::sigaction(signal, &action, &mut previous) == 0 {
+                PREVIOUS[index].store(previous.sa_sigaction as usize, Ordering::SeqCst)
+            }
+        }
+    }
+}
+
+// The signal that asked us to quit, if one did.
+pub fn caught() -> Option<c_int> {
+    match CAUGHT.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal as c_int),
+    }
+}
+
+// Exits the way the caught signal asked, if there was one, once the console is put back.
+pub fn exit_if_caught() {
+    if let Some(signal) = caught() {
+        info!("quitting on signal {}", signal);
+        shutdown();
+        process::exit(128 + signal)
+    }
+}
+
+extern "C" fn handle(signal: c_int) {
+    CAUGHT.store(signal as usize, Ordering::SeqCst);
+    let previous = SIGNALS.iter()
+                          .position(|&handled| handled == signal)
+                          .map_or(libc::SIG_DFL, |index| PREVIOUS[index].load(Ordering::SeqCst));
+    if previous != libc::SIG_DFL && previous != libc::SIG_IGN {
+        unsafe {
+            let previous: extern "C" fn(c_int) = mem::transmute(previous);
+            previous(signal)
+        }
+        return
+    }
+    // SDL was told not to catch signals, so nothing is going to end the event loop for us. The
+    // ioctls in `shutdown` are safe to make from here, and `_exit` skips everything that isn't.
+    shutdown();
+    unsafe {
+        libc::_exit(128 + signal)
+    }
+}
@@ -0,0 +1,128 @@
+// imdialog/src/sound.rs
+//
+// Beeps for `--beep`, `--beep-after` and `--click-sounds`, as square wave tones through SDL audio.
+// Without an audio device, every sound becomes the terminal bell, which the console plays on the
+// PC speaker.
+
+use sdl2::Sdl;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SAMPLE_RATE: i32 = 44100;
+// Small, so that clicks come right away.
+const BUFFER_SAMPLES: u16 = 512;
+const VOLUME: f32 = 0.2;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Sound {
+    Beep,
+    // A key or button being pressed.
+    Click,
+    // An answer being given.
+    Confirm,
+}
+
+impl Sound {
+    // The pitch in hertz and the length in milliseconds.
+    fn tone(self) -> (f32, u32) {
+        match self {
+            Sound::Beep => (880.0, 150),
+            Sound::Click => (1760.0, 15),
+            Sound::Confirm => (1320.0, 60),
+        }
+    }
+}
+
+struct Tone {
+    sample_rate: f32,
+    frequency: f32,
+    phase: f32,
+    samples_left: usize,
+}
+
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, output: &mut [f32]) {
+        for sample in output.iter_mut() {
+            if self.samples_left == 0 {
+                *sample = 0.0;
+                continue
+            }
+            *sample = if self.phase < 0.5 { VOLUME } else { -VOLUME };
+            self.phase = (self.phase + self.frequency / self.sample_rate) % 1.0;
+            self.samples_left -= 1
+        }
+    }
+}
+
+pub struct Speaker {
+    device: Option<AudioDevice<Tone>>,
+    // When the last sound finishes.
+    finished: Instant,
+}
+
+impl Speaker {
+    pub fn new(sdl: &Sdl) -> Speaker {
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: Some(BUFFER_SAMPLES),
+        };
+        let device = sdl.audio().and_then(|audio| {
+            audio.open_playback(None, &desired, |spec| {
+                Tone {
+                    sample_rate: spec.freq as f32,
+                    frequency: 0.0,
+                    phase: 0.0,
+                    samples_left: 0,
+                }
+            })
+        });
+        let device = match device {
+            Ok(device) => {
+                device.resume();
+                Some(device)
+            }
+            Err(message) => {
+                info!("no audio device, so sounds will ring the bell: {}", message);
+                None
+            }
+        };
+        Speaker {
+            device: device,
+            finished: Instant::now(),
+        }
+    }
+
+    pub fn play(&mut self, sound: Sound) {
+        debug!("playing {:?}", sound);
+        let (frequency, length) = sound.tone();
+        let buffer_time = BUFFER_SAMPLES as u64 * 1000 / SAMPLE_RATE as u64;
+        self.finished = Instant::now() + Duration::from_millis(length as u64 + buffer_time);
+        let device = match self.device {
+            Some(ref mut device) => device,
+            None => {
+                if let Ok(mut terminal) = OpenOptions::new().write(true).open("/dev/tty") {
+                    drop(terminal.write_all(b"\x07"))
+                }
+                return
+            }
+        };
+        let mut tone = device.lock();
+        tone.frequency = frequency;
+        tone.phase = 0.0;
+        tone.samples_left = (tone.sample_rate * length as f32 / 1000.0) as usize
+    }
+
+    // Waits for the sound that's playing to end, so that exiting doesn't cut it off.
+    pub fn wait(&self) {
+        let now = Instant::now();
+        if self.device.is_some() && self.finished > now {
+            thread::sleep(self.finished - now)
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// imdialog/src/spawn.rs
+
+use libc::{self, c_int};
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus};
+
+const SPAWN_FAILED_EXIT_CODE: c_int = 127;
+
+#[derive(Clone)]
+pub struct SpawnOptions {
+    pub directory: Option<PathBuf>,
+    pub allowed_env: Option<Vec<String>>,
+    pub umask: Option<u32>,
+}
+
+impl SpawnOptions {
+    pub fn shell_command(&self, command_line: &str) -> Command {
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg(command_line);
+        if let Some(ref directory) = self.directory {
+            command.current_dir(directory);
+        }
+        if let Some(ref allowed_env) = self.allowed_env {
+            command.env_clear();
+            for name in allowed_env {
+                if let Some(value) = env::var_os(name) {
+                    command.env(name, value);
+                }
+            }
+        }
+        if let Some(mask) = self.umask {
+            set_umask(&mut command, mask)
+        }
+        command
+    }
+
+    // `environment` is set on top of whatever `allowed_env` lets through.
+    pub fn run(&self, command_line: &str, environment: &[(OsString, OsString)]) -> c_int {
+        let mut command = self.shell_command(command_line);
+        for &(ref name, ref value) in environment {
+            command.env(name, value);
+        }
+        match command.status() {
+            Ok(status) => {
+                match (status.code(), signal(&status)) {
+                    (Some(code), _) => code,
+                    (None, Some(signal)) => 128 + signal,
+                    (None, None) => SPAWN_FAILED_EXIT_CODE,
+                }
+            }
+            Err(error) => {
+                writeln!(io::stderr(), "error: couldn't run `{}`: {}", command_line, error)
+                    .unwrap();
+                SPAWN_FAILED_EXIT_CODE
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn set_umask(command: &mut Command, mask: u32) {
+    unsafe {
+        command.pre_exec(move || {
+            libc::umask(mask as libc::mode_t);
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn set_umask(_: &mut Command, _: u32) {}
+
+#[cfg(unix)]
+fn signal(status: &ExitStatus) -> Option<c_int> {
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn signal(_: &ExitStatus) -> Option<c_int> {
+    None
+}
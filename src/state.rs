@@ -0,0 +1,128 @@
+// imdialog/src/state.rs
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process;
+
+pub fn state_file_path(name: &str) -> Option<PathBuf> {
+    let mut path = match env::var_os("XDG_STATE_HOME") {
+        Some(ref directory) if !directory.is_empty() => PathBuf::from(directory),
+        _ => {
+            match env::var_os("HOME") {
+                Some(home) => PathBuf::from(home).join(".local/state"),
+                None => return None,
+            }
+        }
+    };
+    path.push("imdialog");
+    path.push(name);
+    Some(path)
+}
+
+pub fn read_lines(name: &str) -> Vec<String> {
+    let path = match state_file_path(name) {
+        Some(path) => path,
+        None => return vec![],
+    };
+    let mut contents = String::new();
+    match File::open(path) {
+        Ok(mut file) => {
+            if file.read_to_string(&mut contents).is_err() {
+                return vec![]
+            }
+        }
+        Err(_) => return vec![],
+    }
+    contents.lines().map(|line| line.to_string()).collect()
+}
+
+pub fn write_lines(name: &str, lines: &[String]) -> io::Result<()> {
+    let path = match state_file_path(name) {
+        Some(path) => path,
+        None => return Err(io::Error::new(io::ErrorKind::NotFound, "no state directory")),
+    };
+    let parent = match path.parent() {
+        Some(parent) => parent.to_path_buf(),
+        None => return Err(io::Error::new(io::ErrorKind::NotFound, "no state directory")),
+    };
+    fs::create_dir_all(&parent)?;
+    // Written beside the file and renamed over it, so that a power cut leaves either the old
+    // contents or the new ones, never an empty file.
+    let temporary_path = parent.join(format!(".{}.{}.tmp", name, process::id()));
+    let result = File::create(&temporary_path).and_then(|mut file| {
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+        file.sync_all()
+    }).and_then(|_| fs::rename(&temporary_path, &path));
+    if result.is_err() {
+        drop(fs::remove_file(&temporary_path));
+        return result
+    }
+    File::open(&parent)?.sync_all()
+}
+
+pub fn append_line(name: &str, line: &str) -> io::Result<()> {
+    let path = match state_file_path(name) {
+        Some(path) => path,
+        None => return Err(io::Error::new(io::ErrorKind::NotFound, "no state directory")),
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+// Keeps tabs and newlines out of a field of a tab-separated line.
+pub fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+pub fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut characters = text.chars();
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            result.push(character);
+            continue
+        }
+        match characters.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some(character) => result.push(character),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+    use super::{escape, read_lines, state_file_path, unescape, write_lines};
+
+    #[test]
+    fn escapes_fields() {
+        let text = "a\tb\nc\\d";
+        assert_eq!(escape(text), "a\\tb\\nc\\\\d");
+        assert_eq!(unescape(&escape(text)), text);
+    }
+
+    #[test]
+    fn writes_lines_in_place_of_the_old_ones() {
+        let directory = env::temp_dir().join(format!("imdialog-state-test-{}", process::id()));
+        env::set_var("XDG_STATE_HOME", &directory);
+        let lines = vec!["one".to_string(), "two".to_string()];
+        write_lines("test", &lines).unwrap();
+        write_lines("test", &lines[1..]).unwrap();
+        assert_eq!(read_lines("test"), ["two"]);
+        let entries = fs::read_dir(state_file_path("test").unwrap().parent().unwrap()).unwrap();
+        assert_eq!(entries.count(), 1);
+        drop(fs::remove_dir_all(directory))
+    }
+}
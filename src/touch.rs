@@ -0,0 +1,120 @@
+// imdialog/src/touch.rs
+
+use sdl2::event::Event;
+
+const DRAG_THRESHOLD: f32 = 12.0;
+const FRICTION: f32 = 0.92;
+const MIN_VELOCITY: f32 = 0.01;
+const TOUCH_MOUSE_ID: u32 = 0xffffffff;
+
+pub struct Touch {
+    momentum: bool,
+    screen_width: f32,
+    screen_height: f32,
+    scroll_step: f32,
+    finger: Option<i64>,
+    position: (f32, f32),
+    pressed: bool,
+    scrolling: bool,
+    start_y: f32,
+    last_y: f32,
+    velocity: f32,
+    wheel: f32,
+    active: bool,
+}
+
+impl Touch {
+    pub fn new(momentum: bool, screen_width: f32, screen_height: f32, scroll_step: f32)
+               -> Touch {
+        Touch {
+            momentum: momentum,
+            screen_width: screen_width,
+            screen_height: screen_height,
+            scroll_step: scroll_step,
+            finger: None,
+            position: (0.0, 0.0),
+            pressed: false,
+            scrolling: false,
+            start_y: 0.0,
+            last_y: 0.0,
+            velocity: 0.0,
+            wheel: 0.0,
+            active: false,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::FingerDown { finger_id, x, y, .. } => {
+                if self.finger.is_some() {
+                    return
+                }
+                self.finger = Some(finger_id);
+                self.position = (x * self.screen_width, y * self.screen_height);
+                self.pressed = true;
+                self.scrolling = false;
+                self.start_y = self.position.1;
+                self.last_y = self.position.1;
+                self.velocity = 0.0;
+                self.active = true;
+            }
+            Event::FingerMotion { finger_id, x, y, .. } => {
+                if self.finger != Some(finger_id) {
+                    return
+                }
+                self.position = (x * self.screen_width, y * self.screen_height);
+                if self.momentum && !self.scrolling &&
+                        (self.position.1 - self.start_y).abs() > DRAG_THRESHOLD {
+                    self.scrolling = true;
+                    self.pressed = false;
+                }
+                if self.scrolling {
+                    let steps = (self.position.1 - self.last_y) / self.scroll_step;
+                    self.wheel += steps;
+                    self.velocity = steps;
+                }
+                self.last_y = self.position.1;
+            }
+            Event::FingerUp { finger_id, x, y, .. } => {
+                if self.finger != Some(finger_id) {
+                    return
+                }
+                self.finger = None;
+                self.pressed = false;
+                if !self.scrolling {
+                    self.position = (x * self.screen_width, y * self.screen_height);
+                    self.velocity = 0.0;
+                }
+                self.scrolling = false;
+            }
+            Event::MouseMotion { which, .. } if which != TOUCH_MOUSE_ID => self.active = false,
+            _ => {}
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn position(&self) -> (f32, f32) {
+        self.position
+    }
+
+    pub fn pressed(&self) -> bool {
+        self.pressed
+    }
+
+    pub fn coasting(&self) -> bool {
+        self.finger.is_none() && self.velocity.abs() > MIN_VELOCITY
+    }
+
+    pub fn take_wheel(&mut self) -> f32 {
+        if self.coasting() {
+            self.wheel += self.velocity;
+            self.velocity *= FRICTION;
+        }
+        let wheel = self.wheel;
+        self.wheel = 0.0;
+        wheel
+    }
+}
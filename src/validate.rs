@@ -0,0 +1,168 @@
+// imdialog/src/validate.rs
+//
+// `--validate` rules for input boxes. OK stays disabled while any rule says the text is wrong.
+
+use libc::{self, c_char};
+use std::ffi::CString;
+use std::mem;
+use std::ptr;
+
+pub enum Rule {
+    // A POSIX extended regular expression that the whole text has to match.
+    Pattern(Pattern),
+    Integer {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    NonEmpty,
+}
+
+impl Rule {
+    // Parses `regex:PATTERN`, `int`, `int:MIN:MAX` (either bound may be left empty), or
+    // `nonempty`.
+    pub fn parse(spec: &str) -> Result<Rule, String> {
+        if spec.starts_with("regex:") {
+            return Pattern::new(&spec["regex:".len()..]).map(Rule::Pattern)
+        }
+        if spec == "nonempty" {
+            return Ok(Rule::NonEmpty)
+        }
+        if spec != "int" && !spec.starts_with("int:") {
+            return Err(format!("unknown rule `{}`", spec))
+        }
+        let bounds: Vec<&str> = spec.split(':').skip(1).collect();
+        let bound = |bound: &str| -> Result<Option<i64>, String> {
+            if bound.is_empty() {
+                return Ok(None)
+            }
+            bound.parse().map(Some).map_err(|_| format!("`{}` isn't a whole number", bound))
+        };
+        match bounds.len() {
+            0 => Ok(Rule::Integer { min: None, max: None }),
+            2 => {
+                Ok(Rule::Integer {
+                    min: bound(bounds[0])?,
+                    max: bound(bounds[1])?,
+                })
+            }
+            _ => Err("`int` takes a minimum and a maximum, like `int:1:65535`".to_string()),
+        }
+    }
+
+    // Why `text` breaks this rule, if it does.
+    pub fn problem(&self, text: &str) -> Option<String> {
+        match *self {
+            Rule::Pattern(ref pattern) if !pattern.matches(text) => {
+                Some("Not in the expected format".to_string())
+            }
+            Rule::Pattern(_) => None,
+            Rule::Integer { min, max } => {
+                let number: i64 = match text.trim().parse() {
+                    Ok(number) => number,
+                    Err(_) => return Some("Enter a whole number".to_string()),
+                };
+                match (min, max) {
+                    (Some(min), Some(max)) if number < min || number > max => {
+                        Some(format!("Enter a number from {} to {}", min, max))
+                    }
+                    (Some(min), _) if number < min => Some(format!("Enter at least {}", min)),
+                    (_, Some(max)) if number > max => Some(format!("Enter at most {}", max)),
+                    _ => None,
+                }
+            }
+            Rule::NonEmpty if text.trim().is_empty() => Some("Enter something".to_string()),
+            Rule::NonEmpty => None,
+        }
+    }
+}
+
+pub struct Pattern {
+    regex: Box<libc::regex_t>,
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Result<Pattern, String> {
+        // Anchored, so that `[0-9]+` doesn't accept `abc1`.
+        let pattern = CString::new(format!("^({})$", pattern))
+            .map_err(|_| "the pattern can't contain a NUL".to_string())?;
+        unsafe {
+            let mut regex: Box<libc::regex_t> = Box::new(mem::zeroed());
+            let error = libc::regcomp(&mut *regex,
+                                      pattern.as_ptr(),
+                                      libc::REG_EXTENDED | libc::REG_NOSUB);
+            if error == 0 {
+                return Ok(Pattern {
+                    regex: regex,
+                })
+            }
+            let mut message = [0u8; 256];
+            libc::regerror(error,
+                           &*regex,
+                           message.as_mut_ptr() as *mut c_char,
+                           message.len());
+            let length = message.iter().position(|&byte| byte == 0).unwrap_or(message.len());
+            Err(String::from_utf8_lossy(&message[..length]).into_owned())
+        }
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        let text = match CString::new(text) {
+            Ok(text) => text,
+            Err(_) => return false,
+        };
+        unsafe {
+            libc::regexec(&*self.regex, text.as_ptr(), 0, ptr::null_mut(), 0) == 0
+        }
+    }
+}
+
+impl Drop for Pattern {
+    fn drop(&mut self) {
+        unsafe {
+            libc::regfree(&mut *self.regex)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rule;
+
+    fn problem(spec: &str, text: &str) -> Option<String> {
+        Rule::parse(spec).unwrap().problem(text)
+    }
+
+    #[test]
+    fn patterns_match_the_whole_text() {
+        assert_eq!(problem("regex:[0-9]+", "123"), None);
+        assert!(problem("regex:[0-9]+", "abc1").is_some());
+        assert!(problem("regex:[0-9]+", "1abc").is_some());
+        assert_eq!(problem("regex:a|b", "b"), None);
+        assert!(Rule::parse("regex:(").is_err());
+    }
+
+    #[test]
+    fn integers_stay_in_bounds() {
+        assert_eq!(problem("int", " -7 "), None);
+        assert_eq!(problem("int", "7.5"), Some("Enter a whole number".to_string()));
+        assert_eq!(problem("int:1:10", "10"), None);
+        assert_eq!(problem("int:1:10", "0"), Some("Enter a number from 1 to 10".to_string()));
+        assert_eq!(problem("int:1:", "0"), Some("Enter at least 1".to_string()));
+        assert_eq!(problem("int::10", "11"), Some("Enter at most 10".to_string()));
+        assert_eq!(problem("int::", "123456"), None);
+    }
+
+    #[test]
+    fn rejects_bad_rules() {
+        assert!(Rule::parse("int:1").is_err());
+        assert!(Rule::parse("int:a:b").is_err());
+        assert!(Rule::parse("integer").is_err());
+        assert!(Rule::parse("email").is_err());
+    }
+
+    #[test]
+    fn nonempty_ignores_spaces() {
+        assert!(problem("nonempty", " \t").is_some());
+        assert_eq!(problem("nonempty", " x "), None);
+    }
+}
@@ -0,0 +1,111 @@
+// imdialog/src/watch.rs
+//
+// Notices files being added to or removed from the directory the file dialog is showing, and disks
+// being mounted or unmounted. Only Linux can do this; elsewhere nothing refreshes by itself.
+
+use libc::{self, c_int};
+use std::path::Path;
+
+pub struct DirectoryWatch {
+    fd: c_int,
+}
+
+impl DirectoryWatch {
+    #[cfg(target_os="linux")]
+    pub fn new(path: &Path) -> Option<DirectoryWatch> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let events = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO;
+        unsafe {
+            let fd = libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC);
+            if fd < 0 {
+                return None
+            }
+            let watch = DirectoryWatch {
+                fd: fd,
+            };
+            if libc::inotify_add_watch(fd, path.as_ptr(), events) < 0 {
+                return None
+            }
+            Some(watch)
+        }
+    }
+
+    #[cfg(not(target_os="linux"))]
+    pub fn new(_: &Path) -> Option<DirectoryWatch> {
+        None
+    }
+
+    // Whether anything has happened since the last call. Never blocks.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        let mut buffer = [0u8; 4096];
+        loop {
+            let length = unsafe {
+                libc::read(self.fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len())
+            };
+            if length <= 0 {
+                return changed
+            }
+            changed = true
+        }
+    }
+}
+
+impl Drop for DirectoryWatch {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+// Linux flags /proc/self/mounts for `poll` whenever the mount table changes.
+pub struct MountWatch {
+    fd: c_int,
+}
+
+impl MountWatch {
+    #[cfg(target_os="linux")]
+    pub fn new() -> Option<MountWatch> {
+        let path = b"/proc/self/mounts\0";
+        unsafe {
+            let fd = libc::open(path.as_ptr() as *const libc::c_char,
+                                libc::O_RDONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                None
+            } else {
+                Some(MountWatch {
+                    fd: fd,
+                })
+            }
+        }
+    }
+
+    #[cfg(not(target_os="linux"))]
+    pub fn new() -> Option<MountWatch> {
+        None
+    }
+
+    // Whether anything has been mounted or unmounted since the last call. Never blocks.
+    pub fn changed(&self) -> bool {
+        let mut poll_fd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLPRI,
+            revents: 0,
+        };
+        unsafe {
+            libc::poll(&mut poll_fd, 1, 0) > 0 && (poll_fd.revents & libc::POLLPRI) != 0
+        }
+    }
+}
+
+impl Drop for MountWatch {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
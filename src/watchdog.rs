@@ -0,0 +1,39 @@
+// imdialog/src/watchdog.rs
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct Watchdog {
+    busy_since: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Watchdog {
+    pub fn start(timeout: Duration, on_hang: fn()) -> Watchdog {
+        let busy_since: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+        let thread_busy_since = busy_since.clone();
+        thread::spawn(move || {
+            loop {
+                thread::sleep(timeout / 4);
+                let stalled = match *thread_busy_since.lock().unwrap() {
+                    Some(start) => start.elapsed() > timeout,
+                    None => false,
+                };
+                if stalled {
+                    on_hang()
+                }
+            }
+        });
+        Watchdog {
+            busy_since: busy_since,
+        }
+    }
+
+    pub fn begin(&self) {
+        *self.busy_since.lock().unwrap() = Some(Instant::now())
+    }
+
+    pub fn end(&self) {
+        *self.busy_since.lock().unwrap() = None
+    }
+}
@@ -0,0 +1,86 @@
+// imdialog/src/whiptail.rs
+
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+static BOXES: [&'static str; 6] = ["msgbox", "yesno", "inputbox", "passwordbox", "menu", "gauge"];
+static IGNORED_FLAGS: [&'static str; 8] = [
+    "whiptail-compat", "fb", "fullbuttons", "clear", "defaultno", "scrolltext", "topleft",
+    "separate-output",
+];
+static IGNORED_VALUE_OPTIONS: [&'static str; 7] = [
+    "title", "backtitle", "default-item", "yes-button", "no-button", "ok-button", "cancel-button",
+];
+
+pub fn invoked_as_whiptail() -> bool {
+    let arguments: Vec<String> = env::args().collect();
+    let program_is_whiptail = arguments.get(0).and_then(|program| {
+        Path::new(program).file_name().map(|name| name == "whiptail")
+    }).unwrap_or(false);
+    program_is_whiptail ||
+        arguments.iter().skip(1).any(|argument| argument == "--whiptail-compat")
+}
+
+// Rewrites a whiptail command line into the equivalent imdialog one. Like whiptail, results go to
+// stderr unless `--output-fd` says otherwise, and options may appear after the box arguments.
+pub fn translate_arguments(arguments: Vec<String>) -> Vec<String> {
+    let mut arguments = arguments.into_iter();
+    let program = arguments.next().unwrap_or("imdialog".to_string());
+
+    let mut translated = vec![program];
+    let mut output_fd = "2".to_string();
+    let mut box_name = None;
+    let mut box_arguments = vec![];
+    while let Some(argument) = arguments.next() {
+        if !argument.starts_with("--") {
+            box_arguments.push(argument);
+            continue
+        }
+        let (name, inline_value) = match argument.find('=') {
+            Some(equals) => {
+                (argument[2..equals].to_string(), Some(argument[equals + 1..].to_string()))
+            }
+            None => (argument[2..].to_string(), None),
+        };
+        let mut value = || {
+            inline_value.clone().or_else(|| arguments.next()).unwrap_or(String::new())
+        };
+        match &name[..] {
+            "nocancel" => translated.push("--no-cancel".to_string()),
+            "notags" => translated.push("--no-tags".to_string()),
+            "noitem" => translated.push("--no-items".to_string()),
+            "output-fd" => output_fd = value(),
+            name if BOXES.contains(&name) => box_name = Some(name.to_string()),
+            name if IGNORED_FLAGS.contains(&name) => {}
+            name if IGNORED_VALUE_OPTIONS.contains(&name) => drop(value()),
+            name => {
+                writeln!(io::stderr(), "warning: ignoring unsupported whiptail option `--{}`", name)
+                    .unwrap()
+            }
+        }
+    }
+
+    let box_name = match box_name {
+        Some(box_name) => box_name,
+        None => {
+            writeln!(io::stderr(),
+                     "error: whiptail mode needs one of --msgbox, --yesno, --inputbox, \
+                      --passwordbox, --menu or --gauge").unwrap();
+            process::exit(255)
+        }
+    };
+    translated.push("--output-fd".to_string());
+    translated.push(output_fd);
+
+    // whiptail takes the height before the width; imdialog takes the width first.
+    let mut box_arguments = box_arguments.into_iter();
+    let text = box_arguments.next().unwrap_or(String::new());
+    let height = box_arguments.next().unwrap_or("0".to_string());
+    let width = box_arguments.next().unwrap_or("0".to_string());
+    translated.push(format!("--{}", box_name));
+    translated.extend(vec![text, width, height]);
+    translated.extend(box_arguments);
+    translated
+}
@@ -0,0 +1,568 @@
+// imdialog/src/wizard.rs
+//
+// `--wizard FILE`: a series of dialogs in one window, for installers and the like. The file is
+// JSON:
+//
+//     {
+//         "steps": [
+//             {"id": "name", "dialog": ["--inputbox", "Your name?", "0", "0"]},
+//             {"id": "kind",
+//              "dialog": ["--menu", "Hello, ${name}. Install what?", "0", "0", "0",
+//                         "full", "Everything", "minimal", "Just the basics"],
+//              "branch": {"minimal": "confirm"}},
+//             {"id": "where", "dialog": ["--inputbox", "Install where?", "0", "0", "/opt"]},
+//             {"id": "confirm",
+//              "dialog": ["--yesno", "Install it for ${name}?", "0", "0"],
+//              "if": [{"when": "${kind} != full", "goto": "end"}],
+//              "on-exit": {"1": "kind"}},
+//             {"id": "docs", "dialog": ["--yesno", "Documentation too?", "0", "0"],
+//              "on-exit": {"1": "end"}}
+//         ]
+//     }
+//
+// A step's `dialog` is its command line, after any other arguments imdialog was given. `${id}` in
+// it becomes what that step answered, or nothing if it hasn't, and `$$` is a `$`. An answer is
+// always a value, even if it starts with a hyphen. Steps follow each other in order, except that a
+// step can have:
+//
+//     branch    answers, and the step to go to when one is given
+//     if        conditions, each `A == B` or `A != B` with a step to go to; after the step is
+//               answered, the first one that holds is followed
+//     on-exit   exit codes, and the step to go to when the dialog exits with one; any other
+//               code but 0 ends the wizard with that code
+//     next      the step to go to otherwise, where `end` finishes the wizard
+//
+// Every step after the first has a Back button, which goes back to the step before it, showing it
+// the way it was left.
+//
+// Progress is saved after each step, so that `--wizard-resume` can pick up a run that was
+// interrupted, by a power cut say, from the step it was on. Finishing or cancelling forgets it, and
+// `--history-id` keeps the progress of different wizards apart.
+//
+// When it's finished, each step on the way there that was answered prints `id<TAB>answer`, in the
+// file's order; `--output-format` has `{tag}`, `{value}` and `{index}` for these lines.
+
+use clap::ArgMatches;
+use json::{self, Value};
+use libc::c_int;
+use output::{self, Fields};
+use signals;
+use state;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use {BACK_EXIT_CODE, Dialog, Display, Options, enter_sandbox, history_filename, shutdown};
+
+static END: &'static str = "end";
+static STATE_FILENAME: &'static str = "wizard";
+
+struct Step {
+    id: Option<String>,
+    arguments: Vec<String>,
+    // Where `None` is the end.
+    branches: Vec<(String, Option<usize>)>,
+    conditions: Vec<(Condition, Option<usize>)>,
+    exits: Vec<(c_int, Option<usize>)>,
+    next: Option<usize>,
+}
+
+// Two texts with `${id}`s in them, and whether they should be equal.
+struct Condition {
+    left: String,
+    right: String,
+    equal: bool,
+}
+
+impl Condition {
+    fn parse(text: &str) -> Result<Condition, String> {
+        for &(operator, equal) in &[("==", true), ("!=", false)] {
+            if let Some(position) = text.find(operator) {
+                return Ok(Condition {
+                    left: text[..position].trim().to_string(),
+                    right: text[position + operator.len()..].trim().to_string(),
+                    equal: equal,
+                })
+            }
+        }
+        Err(format!("`{}` needs to be `A == B` or `A != B`", text))
+    }
+}
+
+pub struct Wizard {
+    // Where it was loaded from, to tell whether saved progress is for it.
+    source: PathBuf,
+    steps: Vec<Step>,
+}
+
+// How far a run has got.
+struct Progress {
+    // The step being shown.
+    current: Option<usize>,
+    // The steps that led to it, for the Back button.
+    path: Vec<usize>,
+    answers: Vec<Option<Vec<u8>>>,
+    // How each step was left, to show it the same way when it comes up again.
+    snapshots: Vec<Option<Vec<(String, String)>>>,
+    // The steps whose answers have passwords in them, which aren't saved.
+    secret: Vec<bool>,
+}
+
+impl Progress {
+    fn new(steps: usize) -> Progress {
+        Progress {
+            current: Some(0),
+            path: vec![],
+            answers: vec![None; steps],
+            snapshots: vec![None; steps],
+            secret: vec![false; steps],
+        }
+    }
+}
+
+impl Wizard {
+    // `parse` turns a step's arguments into matches, as it does when the step is shown. Each step
+    // is parsed here too, so that mistakes show up before the first dialog does.
+    pub fn load<F>(path: &Path, parse: &F) -> Result<Wizard, String>
+                   where F: Fn(Vec<String>) -> Result<ArgMatches<'static>, String> {
+        let mut text = String::new();
+        File::open(path).and_then(|mut file| file.read_to_string(&mut text)).map_err(|error| {
+            format!("couldn't read `{}`: {}", path.display(), error)
+        })?;
+        let steps = match json::parse(&text)?.get("steps") {
+            Some(&Value::Array(ref steps)) if !steps.is_empty() => steps.clone(),
+            _ => return Err("needs a `steps` list".to_string()),
+        };
+
+        let mut ids = vec![];
+        for (index, step) in steps.iter().enumerate() {
+            match step.get("id") {
+                Some(&Value::String(ref id)) if id == END || ids.contains(id) => {
+                    return Err(format!("step {}: can't be called `{}`", index + 1, id))
+                }
+                Some(&Value::String(ref id)) => ids.push(id.clone()),
+                Some(_) => return Err(format!("step {}: `id` needs to be a string", index + 1)),
+                None => {}
+            }
+        }
+        let find = |index: usize, value: &Value| -> Result<Option<usize>, String> {
+            match value.as_str() {
+                Some(id) if id == END => Ok(None),
+                Some(id) => {
+                    match steps.iter().position(|step| step.get("id").and_then(Value::as_str) ==
+                                                Some(id)) {
+                        Some(position) => Ok(Some(position)),
+                        None => Err(format!("step {}: there's no step `{}`", index + 1, id)),
+                    }
+                }
+                None => Err(format!("step {}: needs a step's `id` to go to", index + 1)),
+            }
+        };
+
+        let mut wizard = Wizard {
+            source: fs::canonicalize(path).unwrap_or(path.to_path_buf()),
+            steps: vec![],
+        };
+        for (index, step) in steps.iter().enumerate() {
+            let arguments = match step.get("dialog") {
+                Some(&Value::Array(ref arguments)) => {
+                    arguments.iter().map(|argument| argument.as_str().map(|argument| {
+                        argument.to_string()
+                    })).collect()
+                }
+                _ => None,
+            };
+            let arguments: Vec<String> = match arguments {
+                Some(arguments) => arguments,
+                None => {
+                    return Err(format!("step {}: `dialog` needs to be a list of strings",
+                                       index + 1))
+                }
+            };
+            let check = |text: &str| {
+                interpolate(text, |id| {
+                    if ids.iter().any(|known| known == id) { Some(String::new()) } else { None }
+                }).map_err(|message| format!("step {}: {}", index + 1, message))
+            };
+            let mut empty = vec![];
+            for argument in &arguments {
+                empty.push(check(argument)?)
+            }
+            parse(empty).map_err(|message| format!("step {}: {}", index + 1, message))?;
+
+            let mut branches = vec![];
+            match step.get("branch") {
+                Some(&Value::Object(ref members)) => {
+                    for &(ref answer, ref target) in members {
+                        branches.push((answer.clone(), find(index, target)?))
+                    }
+                }
+                Some(_) => {
+                    return Err(format!("step {}: `branch` needs to be an object", index + 1))
+                }
+                None => {}
+            }
+            let mut conditions = vec![];
+            match step.get("if") {
+                Some(&Value::Array(ref elements)) => {
+                    for element in elements {
+                        let (when, target) = match (element.get("when"), element.get("goto")) {
+                            (Some(&Value::String(ref when)), Some(target)) => (when, target),
+                            _ => {
+                                return Err(format!("step {}: each of `if` needs a `when` and a \
+                                                    `goto`",
+                                                   index + 1))
+                            }
+                        };
+                        let condition = Condition::parse(when).map_err(|message| {
+                            format!("step {}: {}", index + 1, message)
+                        })?;
+                        check(&condition.left)?;
+                        check(&condition.right)?;
+                        conditions.push((condition, find(index, target)?))
+                    }
+                }
+                Some(_) => return Err(format!("step {}: `if` needs to be a list", index + 1)),
+                None => {}
+            }
+            let mut exits = vec![];
+            match step.get("on-exit") {
+                Some(&Value::Object(ref members)) => {
+                    for &(ref code, ref target) in members {
+                        let code = code.parse().map_err(|_| {
+                            format!("step {}: `{}` isn't an exit code", index + 1, code)
+                        })?;
+                        exits.push((code, find(index, target)?))
+                    }
+                }
+                Some(_) => {
+                    return Err(format!("step {}: `on-exit` needs to be an object", index + 1))
+                }
+                None => {}
+            }
+            let next = match step.get("next") {
+                Some(target) => find(index, target)?,
+                None if index + 1 < steps.len() => Some(index + 1),
+                None => None,
+            };
+            wizard.steps.push(Step {
+                id: step.get("id").and_then(Value::as_str).map(|id| id.to_string()),
+                arguments: arguments,
+                branches: branches,
+                conditions: conditions,
+                exits: exits,
+                next: next,
+            })
+        }
+        Ok(wizard)
+    }
+
+    // Shows the steps in `options`' window, prints the answers, and gives the exit code. With
+    // `resume`, it starts from where the last run of the same wizard was interrupted.
+    pub(crate) fn run<F>(&self, options: &Options, help_string: &[u8], resume: bool, parse: F)
+                         -> c_int
+                         where F: Fn(Vec<String>) -> Result<ArgMatches<'static>, String> {
+        let state_name = history_filename(STATE_FILENAME, options.history_id.as_ref().map(|id| {
+            &id[..]
+        }));
+        let saved = if resume { self.load_progress(&state_name) } else { None };
+        let mut progress = saved.unwrap_or_else(|| Progress::new(self.steps.len()));
+        let mut display = Display::new(options);
+        enter_sandbox(options);
+        while let Some(index) = progress.current {
+            let step = &self.steps[index];
+            let arguments = step.arguments.iter().map(|argument| {
+                self.fill(argument, &progress.answers)
+            }).collect();
+            let matches = match parse_filled(&step.arguments, arguments, &parse) {
+                Ok(matches) => matches,
+                Err(message) => {
+                    shutdown();
+                    writeln!(io::stderr(), "error: `--wizard` step {}: {}", index + 1, message)
+                        .unwrap();
+                    process::exit(1)
+                }
+            };
+            let mut dialog = Dialog::new(&matches, help_string);
+            if let Some(ref snapshot) = progress.snapshots[index] {
+                dialog.restore(snapshot)
+            }
+            dialog.options.back_button = !progress.path.is_empty();
+            output::capture();
+            let code = display.run(&mut dialog);
+            let mut answer = output::captured().unwrap_or(vec![]);
+            if answer.last() == Some(&b'\n') {
+                answer.pop();
+            }
+            debug!("wizard step {} exited with {}", index + 1, code);
+            if signals::caught().is_some() {
+                // Interrupted, so this step comes up again on `--wizard-resume`.
+                return code
+            }
+            progress.snapshots[index] = Some(dialog.snapshot());
+            progress.secret[index] = dialog.has_secrets();
+
+            progress.answers[index] = if code == 0 { Some(answer) } else { None };
+            let answers = &progress.answers;
+            let next = if let Some(&(_, target)) = step.exits.iter().find(|&&(exit, _)| {
+                exit == code
+            }) {
+                target
+            } else if code == BACK_EXIT_CODE && !progress.path.is_empty() {
+                progress.current = progress.path.pop();
+                self.save_progress(&state_name, &progress);
+                continue
+            } else if code != 0 {
+                drop(state::write_lines(&state_name, &[]));
+                return code
+            } else if let Some(&(_, target)) = step.branches.iter().find(|&&(ref branch, _)| {
+                answers[index].as_ref().map(|answer| &answer[..]) == Some(branch.as_bytes())
+            }) {
+                target
+            } else if let Some(&(_, target)) = step.conditions.iter().find(|&&(ref condition, _)| {
+                let equal = self.fill(&condition.left, answers) ==
+                    self.fill(&condition.right, answers);
+                equal == condition.equal
+            }) {
+                target
+            } else {
+                step.next
+            };
+            progress.path.push(index);
+            progress.current = next;
+            self.save_progress(&state_name, &progress)
+        }
+        drop(state::write_lines(&state_name, &[]));
+
+        for (index, (step, answer)) in self.steps.iter().zip(progress.answers.iter()).enumerate() {
+            if !progress.path.contains(&index) {
+                continue
+            }
+            if let (&Some(ref id), &Some(ref answer)) = (&step.id, answer) {
+                let mut default = id.as_bytes().to_vec();
+                default.push(b'\t');
+                default.extend_from_slice(answer);
+                options.results.print(&default, &Fields {
+                    tag: Some(id),
+                    value: Some(answer),
+                    index: Some(index),
+                    ..Fields::default()
+                })
+            }
+        }
+        0
+    }
+
+    // The state file has a line naming the wizard, then one for the step to show next and the
+    // steps before it, and then the answers and snapshots of the steps, a line for each:
+    //
+    //     wizard	/etc/installer/wizard.json
+    //     current	3	0 1 2
+    //     answer	0	Ada
+    //     snapshot	0	text	Ada
+    //     secret	1
+    //
+    // Answers that aren't UTF-8 are saved as well as they can be, and ones with passwords in them
+    // aren't, so that resuming asks for those again.
+    fn save_progress(&self, state_name: &str, progress: &Progress) {
+        let path: Vec<String> = progress.path.iter().map(|index| index.to_string()).collect();
+        let current = progress.current.map_or(String::new(), |index| index.to_string());
+        let mut lines = vec![format!("wizard\t{}", state::escape(&self.source.to_string_lossy())),
+                             format!("current\t{}\t{}", current, path.join(" "))];
+        for (index, answer) in progress.answers.iter().enumerate() {
+            if progress.secret[index] {
+                lines.push(format!("secret\t{}", index))
+            } else if let Some(ref answer) = *answer {
+                lines.push(format!("answer\t{}\t{}",
+                                   index,
+                                   state::escape(&String::from_utf8_lossy(answer))))
+            }
+        }
+        for (index, snapshot) in progress.snapshots.iter().enumerate() {
+            for &(ref key, ref value) in snapshot.iter().flat_map(|snapshot| snapshot.iter()) {
+                lines.push(format!("snapshot\t{}\t{}\t{}",
+                                   index,
+                                   state::escape(key),
+                                   state::escape(value)))
+            }
+        }
+        if let Err(error) = state::write_lines(state_name, &lines) {
+            writeln!(io::stderr(), "warning: couldn't save the wizard's progress: {}", error)
+                .unwrap()
+        }
+    }
+
+    fn load_progress(&self, state_name: &str) -> Option<Progress> {
+        let lines = state::read_lines(state_name);
+        let mut fields = lines.iter().map(|line| {
+            line.split('\t').map(state::unescape).collect::<Vec<String>>()
+        });
+        match fields.next() {
+            Some(ref fields) if fields.len() == 2 && fields[0] == "wizard" &&
+                                Path::new(&fields[1]) == self.source => {}
+            _ => {
+                info!("no progress saved for `{}`, so the wizard starts over",
+                      self.source.display());
+                return None
+            }
+        }
+        let (current, path) = match fields.next() {
+            Some(ref fields) if fields.len() == 3 && fields[0] == "current" => {
+                (fields[1].parse().ok(),
+                 fields[2].split_whitespace().filter_map(|index| index.parse().ok()).collect())
+            }
+            _ => return None,
+        };
+        let mut progress = Progress {
+            current: current,
+            path: path,
+            ..Progress::new(self.steps.len())
+        };
+        for fields in fields {
+            let index = match fields.get(1).and_then(|index| index.parse::<usize>().ok()) {
+                Some(index) if index < self.steps.len() => index,
+                _ => continue,
+            };
+            match (&fields[0][..], fields.len()) {
+                ("secret", 2) => progress.secret[index] = true,
+                ("answer", 3) => progress.answers[index] = Some(fields[2].as_bytes().to_vec()),
+                ("snapshot", 4) => {
+                    progress.snapshots[index].get_or_insert(vec![])
+                                             .push((fields[2].clone(), fields[3].clone()))
+                }
+                _ => {}
+            }
+        }
+        let in_range = |index: &usize| *index < self.steps.len();
+        if !progress.current.iter().all(in_range) || !progress.path.iter().all(in_range) {
+            return None
+        }
+        if let Some(position) = progress.path.iter().position(|&index| progress.secret[index]) {
+            progress.current = Some(progress.path[position]);
+            progress.path.truncate(position)
+        }
+        info!("resuming the wizard at step {:?}", progress.current.map(|index| index + 1));
+        Some(progress)
+    }
+
+    // `text` with the answers so far put in for its `${id}`s.
+    fn fill(&self, text: &str, answers: &[Option<Vec<u8>>]) -> String {
+        interpolate(text, |id| {
+            let position = self.steps.iter().position(|step| {
+                step.id.as_ref().map(|step_id| &step_id[..]) == Some(id)
+            });
+            let answer = position.and_then(|position| answers[position].as_ref());
+            Some(answer.map_or(String::new(), |answer| {
+                String::from_utf8_lossy(answer).into_owned()
+            }))
+        }).unwrap()
+    }
+}
+
+// Parses a step's `arguments`, filled in from its `templates`. clap takes anything starting with a
+// hyphen for an option, so answers that do are stood in for while the arguments are parsed, and
+// then put back into the values they turned out to be.
+fn parse_filled<F>(templates: &[String], arguments: Vec<String>, parse: &F)
+                   -> Result<ArgMatches<'static>, String>
+                   where F: Fn(Vec<String>) -> Result<ArgMatches<'static>, String> {
+    let mut answers = vec![];
+    let arguments = templates.iter().zip(arguments).map(|(template, argument)| {
+        if template.starts_with('-') || !argument.starts_with('-') {
+            return argument
+        }
+        let placeholder = format!("\u{1}{}", answers.len());
+        answers.push((placeholder.clone(), argument));
+        placeholder
+    }).collect();
+    let mut matches = parse(arguments).map_err(|message| {
+        answers.iter().fold(message, |message, &(ref placeholder, ref answer)| {
+            message.replace(&placeholder[..], answer)
+        })
+    })?;
+    for (placeholder, answer) in answers {
+        // `args` is left out of clap's documentation, but nothing else can change a value.
+        let value = matches.args.values_mut().flat_map(|arg| {
+            arg.vals.iter_mut().map(|(_, value)| value)
+        }).find(|value| **value == placeholder[..]);
+        match value {
+            Some(value) => *value = OsString::from(answer),
+            None => return Err(format!("`{}` would be taken as an option", answer)),
+        }
+    }
+    Ok(matches)
+}
+
+// Replaces each `${id}` in `text` with `value(id)`, which is `None` for an unknown step.
+fn interpolate<F>(text: &str, mut value: F) -> Result<String, String>
+                  where F: FnMut(&str) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        if rest.starts_with('$') {
+            result.push('$');
+            rest = &rest[1..];
+            continue
+        }
+        let end = match rest.find('}') {
+            Some(end) if rest.starts_with('{') => end,
+            _ => return Err(format!("a `$` in `{}` needs to be `$$` or `${{id}}`", text)),
+        };
+        match value(&rest[1..end]) {
+            Some(value) => result.push_str(&value),
+            None => return Err(format!("there's no step `{}`", &rest[1..end])),
+        }
+        rest = &rest[end + 1..]
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ArgMatches;
+    use parse_wizard_step;
+    use super::{interpolate, parse_filled};
+
+    fn strings(arguments: &[&str]) -> Vec<String> {
+        arguments.iter().map(|argument| argument.to_string()).collect()
+    }
+
+    fn parse(arguments: Vec<String>) -> Result<ArgMatches<'static>, String> {
+        parse_wizard_step(&strings(&["imdialog"]), arguments)
+    }
+
+    #[test]
+    fn interpolates_answers() {
+        let text = interpolate("${name} paid $$5", |id| {
+            if id == "name" { Some("Ann".to_string()) } else { None }
+        });
+        assert_eq!(text, Ok("Ann paid $5".to_string()));
+        assert!(interpolate("${nobody}", |_| None).is_err());
+        assert!(interpolate("$5", |_| None).is_err());
+    }
+
+    #[test]
+    fn answers_starting_with_a_hyphen_are_values() {
+        let templates = strings(&["--inputbox", "${text}", "0", "0", "${init}"]);
+        let arguments = strings(&["--inputbox", "-x", "0", "0", "-5"]);
+        let matches = parse_filled(&templates, arguments, &parse).unwrap();
+        let values: Vec<_> = matches.values_of("inputbox").unwrap().collect();
+        assert_eq!(values, ["-x", "0", "0", "-5"]);
+    }
+
+    #[test]
+    fn answers_are_not_options() {
+        let templates = strings(&["--inputbox", "Text", "0", "0", "${init}"]);
+        let arguments = strings(&["--inputbox", "Text", "0", "0", "--output-fd=3"]);
+        let matches = parse_filled(&templates, arguments, &parse).unwrap();
+        assert_eq!(matches.values_of("inputbox").unwrap().last(), Some("--output-fd=3"));
+        assert!(!matches.is_present("output-fd"));
+
+        let templates = strings(&["${option}", "--yesno", "Text", "0", "0"]);
+        let arguments = strings(&["--output-fd=3", "--yesno", "Text", "0", "0"]);
+        assert!(parse_filled(&templates, arguments, &parse).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+// imdialog/src/zenity.rs
+
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+static VALUE_OPTIONS: [&'static str; 8] = [
+    "text", "entry-text", "column", "percentage", "filename", "title", "width", "height",
+];
+
+enum Mode {
+    Entry,
+    Password,
+    Question,
+    Message,
+    List,
+    Progress,
+    FileSelection,
+}
+
+pub fn invoked_as_zenity() -> bool {
+    let arguments: Vec<String> = env::args().collect();
+    let program_is_zenity = arguments.get(0).and_then(|program| {
+        Path::new(program).file_name().map(|name| name == "zenity")
+    }).unwrap_or(false);
+    program_is_zenity || arguments.iter().skip(1).any(|argument| argument == "--zenity")
+}
+
+// Rewrites a zenity command line into the equivalent imdialog one.
+pub fn translate_arguments(arguments: Vec<String>) -> Vec<String> {
+    let mut arguments = arguments.into_iter();
+    let program = arguments.next().unwrap_or("imdialog".to_string());
+
+    let mut mode = None;
+    let mut values: Vec<(String, String)> = vec![];
+    let mut hide_text = false;
+    let mut rows = vec![];
+    while let Some(argument) = arguments.next() {
+        if !argument.starts_with("--") {
+            rows.push(argument);
+            continue
+        }
+        let (name, inline_value) = match argument.find('=') {
+            Some(equals) => {
+                (argument[2..equals].to_string(), Some(argument[equals + 1..].to_string()))
+            }
+            None => (argument[2..].to_string(), None),
+        };
+        match &name[..] {
+            "zenity" => {}
+            "entry" => mode = Some(Mode::Entry),
+            "password" => mode = Some(Mode::Password),
+            "question" => mode = Some(Mode::Question),
+            "info" | "warning" | "error" => mode = Some(Mode::Message),
+            "list" => mode = Some(Mode::List),
+            "progress" => mode = Some(Mode::Progress),
+            "file-selection" => mode = Some(Mode::FileSelection),
+            "hide-text" => hide_text = true,
+            name if VALUE_OPTIONS.contains(&name) => {
+                let value = match inline_value {
+                    Some(value) => value,
+                    None => arguments.next().unwrap_or(String::new()),
+                };
+                values.push((name.to_string(), value))
+            }
+            name => {
+                writeln!(io::stderr(), "warning: ignoring unsupported zenity option `--{}`", name)
+                    .unwrap()
+            }
+        }
+    }
+
+    let value = |name: &str| {
+        values.iter().rev().find(|&&(ref key, _)| key == name).map(|&(_, ref value)| value.clone())
+    };
+    let text = value("text").unwrap_or(String::new());
+    let width = value("width").unwrap_or("0".to_string());
+    let height = value("height").unwrap_or("0".to_string());
+
+    // zenity treats Escape like Cancel.
+    let mut translated = vec![program, "--esc-code".to_string(), "1".to_string()];
+    match mode {
+        Some(Mode::Entry) | Some(Mode::Password) => {
+            let password = hide_text || match mode { Some(Mode::Password) => true, _ => false };
+            translated.push(if password { "--passwordbox" } else { "--inputbox" }.to_string());
+            translated.extend(vec![text, width, height]);
+            translated.extend(value("entry-text"));
+        }
+        Some(Mode::Question) => translated.extend(vec!["--yesno".to_string(), text, width, height]),
+        Some(Mode::Message) => translated.extend(vec!["--msgbox".to_string(), text, width, height]),
+        Some(Mode::List) => {
+            let column_count = values.iter().filter(|&&(ref key, _)| key == "column").count();
+            translated.extend(vec!["--menu".to_string(), text, width, height, "0".to_string()]);
+            for row in rows.chunks(column_count.max(1)) {
+                translated.push(row[0].clone());
+                translated.push(row[1..].join("  "));
+            }
+        }
+        Some(Mode::Progress) => {
+            translated.extend(vec!["--gauge".to_string(), text, width, height]);
+            translated.extend(value("percentage"));
+        }
+        Some(Mode::FileSelection) => {
+            let directory = match value("filename") {
+                Some(ref filename) if !filename.ends_with('/') && !Path::new(filename).is_dir() => {
+                    Path::new(filename).parent().map(|parent| parent.display().to_string())
+                }
+                filename => filename,
+            };
+            let directory = match directory {
+                Some(ref directory) if !directory.is_empty() => directory.clone(),
+                _ => ".".to_string(),
+            };
+            translated.extend(vec!["--fselect".to_string(), directory, width, height]);
+        }
+        None => {
+            writeln!(io::stderr(),
+                     "error: zenity mode needs one of --entry, --password, --question, --info, \
+                      --warning, --error, --list, --progress or --file-selection").unwrap();
+            process::exit(255)
+        }
+    }
+    translated
+}